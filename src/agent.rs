@@ -0,0 +1,330 @@
+//! A short-lived key agent, like ssh-agent.
+//!
+//! `secret-agent unlock` prompts for the passphrase once and hands it to a
+//! background process listening on `~/.secret-agent/agent.sock`. Later
+//! commands ask the agent before prompting again. The agent forgets the key
+//! and exits when its TTL runs out or on `secret-agent lock`.
+//!
+//! The agent holds the passphrase rather than a derived vault key, so each
+//! command still runs the vault's scrypt derivation once. A derived key is
+//! bound to one vault's salt, while the agent serves whichever vault a
+//! command opens (`--vault-path`, SECRET_AGENT_VAULT_PATH), and vaults in the
+//! older per-value format need the passphrase itself to read every value.
+//!
+//! The protocol is one JSON object per line:
+//! `{"op":"set","key":"...","ttl":SECS}`, `{"op":"get"}` and `{"op":"clear"}`.
+
+use std::path::PathBuf;
+
+pub fn socket_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".secret-agent").join("agent.sock"))
+}
+
+/// The master key held by a running agent, if any
+#[cfg(unix)]
+pub fn cached_key() -> Option<String> {
+    let path = socket_path()?;
+    let response = imp::request(&path, &serde_json::json!({ "op": "get" })).ok()?;
+    response["key"].as_str().map(str::to_string)
+}
+
+#[cfg(not(unix))]
+pub fn cached_key() -> Option<String> {
+    None
+}
+
+#[cfg(unix)]
+pub use imp::{request, serve};
+
+#[cfg(unix)]
+mod imp {
+    use crate::peer;
+    use anyhow::{Context, Result};
+    use secrecy::{ExposeSecret, SecretString};
+    use serde_json::{json, Value};
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// How long a connection may sit without sending a request before it's
+    /// dropped, so a client that never closes can't pin a thread forever
+    const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// The cached key and when it expires
+    pub(super) struct Cache {
+        key: Option<SecretString>,
+        expires_at: Instant,
+    }
+
+    impl Cache {
+        pub(super) fn new(key: SecretString, ttl: Duration, now: Instant) -> Self {
+            Self {
+                key: Some(key),
+                expires_at: now + ttl,
+            }
+        }
+
+        /// Drop the key (zeroizing it) once its TTL has passed
+        fn expire(&mut self, now: Instant) {
+            if now >= self.expires_at {
+                self.key = None;
+            }
+        }
+
+        fn is_empty(&self) -> bool {
+            self.key.is_none()
+        }
+    }
+
+    /// Answer one protocol line
+    pub(super) fn handle_request(cache: &mut Cache, line: &str, now: Instant) -> Value {
+        cache.expire(now);
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => return json!({ "ok": false, "error": format!("invalid request: {}", e) }),
+        };
+        match request["op"].as_str() {
+            Some("set") => {
+                let (Some(key), Some(ttl)) = (request["key"].as_str(), request["ttl"].as_u64())
+                else {
+                    return json!({ "ok": false, "error": "set requires key and ttl" });
+                };
+                *cache = Cache::new(SecretString::from(key), Duration::from_secs(ttl), now);
+                json!({ "ok": true })
+            }
+            Some("get") => match &cache.key {
+                Some(key) => json!({
+                    "ok": true,
+                    "key": key.expose_secret(),
+                    "expires_in": cache.expires_at.saturating_duration_since(now).as_secs(),
+                }),
+                None => json!({ "ok": false, "error": "locked" }),
+            },
+            Some("clear") => {
+                cache.key = None;
+                json!({ "ok": true })
+            }
+            _ => json!({ "ok": false, "error": "unknown op" }),
+        }
+    }
+
+    /// Send one request to the agent at `path` and read its response
+    pub fn request(path: &Path, request: &Value) -> Result<Value> {
+        let mut stream = UnixStream::connect(path)
+            .with_context(|| format!("no agent listening on {}", path.display()))?;
+        stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+        writeln!(stream, "{}", request)?;
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+        serde_json::from_str(&line).context("invalid response from agent")
+    }
+
+    /// Hold `key` and answer requests on `path` until it expires or is cleared
+    pub fn serve(path: &Path, key: SecretString, ttl: Duration) -> Result<()> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                anyhow::bail!("an agent is already listening on {}", path.display());
+            }
+            std::fs::remove_file(path)
+                .with_context(|| format!("failed to remove stale socket {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("failed to bind {}", path.display()))?;
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+                .with_context(|| format!("failed to set permissions on {}", path.display()))?;
+        }
+
+        let cache = Arc::new(Mutex::new(Cache::new(key, ttl, Instant::now())));
+        spawn_expiry_timer(Arc::clone(&cache), path.to_path_buf());
+
+        // Each connection gets its own thread, so a client that stays
+        // connected can't hold up other lookups or keep the loop alive
+        for stream in listener.incoming() {
+            if cache.lock().unwrap_or_else(|e| e.into_inner()).is_empty() {
+                break;
+            }
+            let Ok(stream) = stream else { continue };
+            let cache = Arc::clone(&cache);
+            let path = path.to_path_buf();
+            std::thread::spawn(move || {
+                let _ = serve_connection(&cache, stream);
+                // After a `clear`, wake the accept loop so it exits
+                if cache.lock().unwrap_or_else(|e| e.into_inner()).is_empty() {
+                    let _ = UnixStream::connect(&path);
+                }
+            });
+        }
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    fn serve_connection(cache: &Mutex<Cache>, stream: UnixStream) -> Result<()> {
+        stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+        let mut writer = stream.try_clone()?;
+        if let Err(e) = peer::check(&stream) {
+            writeln!(writer, "{}", json!({ "ok": false, "error": e.to_string() }))?;
+            return Ok(());
+        }
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+            let response = handle_request(&mut cache, &line, Instant::now());
+            drop(cache);
+            writeln!(writer, "{}", response)?;
+        }
+        Ok(())
+    }
+
+    /// Forget the key once it expires, then wake the accept loop so it exits
+    fn spawn_expiry_timer(cache: Arc<Mutex<Cache>>, path: std::path::PathBuf) {
+        std::thread::spawn(move || loop {
+            std::thread::sleep(Duration::from_secs(1));
+            let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+            if cache.is_empty() {
+                return;
+            }
+            cache.expire(Instant::now());
+            if cache.is_empty() {
+                drop(cache);
+                let _ = UnixStream::connect(&path);
+                return;
+            }
+        });
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::imp::*;
+    use secrecy::SecretString;
+    use serde_json::{json, Value};
+    use std::time::{Duration, Instant};
+
+    fn send(cache: &mut Cache, request: Value, now: Instant) -> Value {
+        handle_request(cache, &request.to_string(), now)
+    }
+
+    #[test]
+    fn test_set_then_get() {
+        let now = Instant::now();
+        let mut cache = Cache::new(SecretString::from("old"), Duration::from_secs(60), now);
+
+        let set = send(
+            &mut cache,
+            json!({"op": "set", "key": "hunter2", "ttl": 300}),
+            now,
+        );
+        assert_eq!(set["ok"], true);
+
+        let get = send(&mut cache, json!({"op": "get"}), now);
+        assert_eq!(get["key"], "hunter2");
+        assert_eq!(get["expires_in"], 300);
+    }
+
+    #[test]
+    fn test_clear_forgets_key() {
+        let now = Instant::now();
+        let mut cache = Cache::new(SecretString::from("hunter2"), Duration::from_secs(60), now);
+
+        assert_eq!(send(&mut cache, json!({"op": "clear"}), now)["ok"], true);
+
+        let get = send(&mut cache, json!({"op": "get"}), now);
+        assert_eq!(get["ok"], false);
+        assert_eq!(get["error"], "locked");
+        assert!(get.get("key").is_none());
+    }
+
+    #[test]
+    fn test_key_expires_after_ttl() {
+        let now = Instant::now();
+        let mut cache = Cache::new(SecretString::from("hunter2"), Duration::from_secs(60), now);
+
+        let later = now + Duration::from_secs(61);
+        assert_eq!(
+            send(&mut cache, json!({"op": "get"}), later)["error"],
+            "locked"
+        );
+    }
+
+    #[test]
+    fn test_rejects_bad_requests() {
+        let now = Instant::now();
+        let mut cache = Cache::new(SecretString::from("k"), Duration::from_secs(60), now);
+
+        assert_eq!(handle_request(&mut cache, "not json", now)["ok"], false);
+        assert_eq!(
+            send(&mut cache, json!({"op": "set", "key": "x"}), now)["ok"],
+            false
+        );
+        assert_eq!(send(&mut cache, json!({"op": "dump"}), now)["ok"], false);
+    }
+
+    #[test]
+    fn test_serve_over_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("agent.sock");
+        let server_path = path.clone();
+        std::thread::spawn(move || {
+            serve(
+                &server_path,
+                SecretString::from("hunter2"),
+                Duration::from_secs(60),
+            )
+        });
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !path.exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let get = request(&path, &json!({"op": "get"})).unwrap();
+        assert_eq!(get["key"], "hunter2");
+
+        assert_eq!(request(&path, &json!({"op": "clear"})).unwrap()["ok"], true);
+        while path.exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(
+            !path.exists(),
+            "agent should exit and remove its socket once cleared"
+        );
+    }
+
+    #[test]
+    fn test_idle_client_does_not_block_others() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("agent.sock");
+        let server_path = path.clone();
+        std::thread::spawn(move || {
+            serve(
+                &server_path,
+                SecretString::from("hunter2"),
+                Duration::from_secs(60),
+            )
+        });
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !path.exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // Connected but silent; the next lookup must not wait on it
+        let _idle = std::os::unix::net::UnixStream::connect(&path).unwrap();
+        let started = Instant::now();
+        let get = request(&path, &json!({"op": "get"})).unwrap();
+        assert_eq!(get["key"], "hunter2");
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        assert_eq!(request(&path, &json!({"op": "clear"})).unwrap()["ok"], true);
+        while path.exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(
+            !path.exists(),
+            "an idle client must not keep the agent alive"
+        );
+    }
+}
@@ -0,0 +1,370 @@
+//! The `secret-agent` command line: parse the arguments and run a command
+
+use crate::cli::{Cli, Commands, EnvAction};
+use crate::{commands, config, keychain, log, sanitize, vault};
+use clap::Parser;
+
+/// Run the CLI with the process's arguments, exiting on failure
+pub fn run() {
+    let cli = Cli::parse();
+    let porcelain = cli.porcelain;
+    let quiet = cli.quiet || porcelain;
+    log::init(quiet, cli.verbose);
+    if let Some(path) = cli.vault_path {
+        vault::set_vault_path(path);
+    }
+    if let Some(path) = cli.key_file {
+        keychain::set_key_file(path);
+    }
+
+    let tips = !quiet && !cli.no_tips && !tips_disabled_by_env();
+    if tips
+        && !matches!(
+            cli.command,
+            Commands::Setup { .. } | Commands::Uninstall { .. }
+        )
+        && !commands::setup::is_configured()
+    {
+        eprintln!("Tip: run `secret-agent setup` to configure Claude Code integration");
+        eprintln!();
+    }
+
+    let result = match cli.command {
+        Commands::Create {
+            name,
+            length,
+            charset,
+            exclude,
+            bytes,
+            encoding,
+            force,
+        } => in_default_bucket(name).and_then(|name| {
+            let opts = commands::create::ValueOptions {
+                length,
+                charset,
+                exclude,
+                bytes,
+                encoding,
+            };
+            commands::create::run(&name, &opts, force, quiet)
+                .map(|()| porcelain_ok(porcelain, &name))
+        }),
+
+        Commands::Generate {
+            count,
+            length,
+            charset,
+            separator,
+        } => commands::generate::run(count, length, &charset, &separator),
+
+        Commands::Charsets => commands::charsets::run(),
+
+        Commands::Import {
+            name,
+            clipboard,
+            primary,
+            clipboard_paste_cmd,
+            clipboard_cmd,
+            strip_trailing_url,
+            raw,
+            binary,
+            replace,
+            verify,
+            no_rollback,
+        } => {
+            let opts = commands::import::ImportOptions {
+                clipboard,
+                primary,
+                clipboard_paste_cmd,
+                clipboard_cmd,
+                strip_trailing_url,
+                raw,
+                binary,
+                replace,
+                verify,
+                no_rollback,
+            };
+            in_default_bucket(name).and_then(|name| {
+                commands::import::run(&name, &opts, quiet).map(|()| porcelain_ok(porcelain, &name))
+            })
+        }
+
+        Commands::List {
+            bucket,
+            format,
+            long,
+            json,
+            json_envelope,
+        } => commands::list::run(bucket.as_deref(), &format, long, json, json_envelope),
+
+        Commands::Buckets { json } => commands::buckets::run(json),
+
+        Commands::Info { name, field } => commands::info::run(&name, field.as_deref()),
+
+        Commands::Delete {
+            names,
+            if_exists,
+            bucket,
+            yes,
+        } => match bucket {
+            Some(bucket) => commands::delete::run_bucket(&bucket, yes, quiet),
+            None => commands::delete::run(&names, if_exists, quiet),
+        }
+        .map(|deleted| {
+            for name in &deleted {
+                porcelain_ok(porcelain, name);
+            }
+        }),
+
+        Commands::Rename {
+            old,
+            new,
+            regex,
+            apply,
+            dry_run: _,
+        } => {
+            let spec = match (&regex, &old, &new) {
+                (Some(expr), _, _) => commands::rename::RenameSpec::Regex { expr, apply },
+                (None, Some(old), Some(new)) => commands::rename::RenameSpec::Single { old, new },
+                _ => unreachable!("clap requires OLD and NEW without --regex"),
+            };
+            commands::rename::run(&spec, quiet).map(|renamed| {
+                for name in &renamed {
+                    porcelain_ok(porcelain, name);
+                }
+            })
+        }
+
+        Commands::Get {
+            names,
+            to,
+            raw: true,
+            force,
+            ..
+        } => match names.as_slice() {
+            [name] => commands::get::run_raw(name, &to, force),
+            _ => Err(anyhow::anyhow!("--raw reads one secret at a time")),
+        },
+
+        Commands::Get {
+            names,
+            clipboard,
+            unsafe_display,
+            to,
+            join,
+            allow_unsafe,
+            clear_after,
+            clipboard_cmd,
+            clipboard_paste_cmd,
+            ..
+        } => commands::get::run(
+            &names,
+            clipboard
+                .then(|| {
+                    commands::get::ClipboardBackend::resolve(clipboard_cmd, clipboard_paste_cmd)
+                })
+                .as_ref(),
+            unsafe_display.then_some(to.as_str()),
+            join.as_deref(),
+            allow_unsafe,
+            clear_after,
+            quiet,
+        ),
+
+        Commands::ClearClipboard { after } => commands::get::clear_later(after),
+
+        Commands::Unlock { ttl } => commands::session::unlock(ttl, quiet),
+
+        Commands::Lock => commands::session::lock(quiet),
+
+        Commands::Agent { ttl } => commands::session::serve(ttl),
+
+        Commands::Exec {
+            env_secrets,
+            bucket,
+            env_buckets,
+            secret_args,
+            args_file,
+            no_shell,
+            shell,
+            dotenv,
+            dotenv_expand,
+            tee,
+            tee_append,
+            events,
+            strip_ansi,
+            redact_env,
+            render_to,
+            template,
+            write_env_file,
+            env_fd,
+            on_exit,
+            dry_run,
+            command,
+        } => {
+            let opts = commands::exec::ExecOptions {
+                env_secrets,
+                bucket,
+                env_buckets,
+                secret_args,
+                args_file,
+                no_shell,
+                shell,
+                dotenv,
+                dotenv_expand,
+                tee,
+                tee_append,
+                events,
+                strip_ansi,
+                redact_env,
+                render_to,
+                template,
+                write_env_file,
+                env_fd,
+                on_exit,
+                dry_run,
+                quiet,
+            };
+            match commands::exec::run(&opts, &command) {
+                Ok(exit_code) => std::process::exit(exit_code),
+                Err(e) => {
+                    report_error(&e);
+                    std::process::exit(if e.is::<commands::exec::SpawnError>() {
+                        commands::exec::SpawnError::EXIT_CODE
+                    } else {
+                        exit_code(&e)
+                    });
+                }
+            }
+        }
+
+        Commands::Inject {
+            name,
+            file,
+            placeholder,
+            env_format,
+            export,
+            always_write,
+            after_marker,
+            update_only,
+            create_only,
+        } => {
+            let mode = if update_only {
+                commands::inject::EnvMode::UpdateOnly
+            } else if create_only {
+                commands::inject::EnvMode::CreateOnly
+            } else {
+                commands::inject::EnvMode::Upsert
+            };
+            let opts = commands::inject::InjectOptions {
+                placeholder,
+                env_format,
+                export,
+                always_write,
+                after_marker,
+                mode,
+            };
+            commands::inject::run(&name, &file, &opts, quiet)
+        }
+
+        Commands::Env { action } => match action {
+            EnvAction::Export {
+                file,
+                names,
+                all,
+                export,
+                always_write,
+            } => commands::env::export(&file, &names, all, export, always_write, quiet),
+            EnvAction::Import {
+                file,
+                map,
+                only_mapped,
+                report_dups,
+            } => commands::env::import(&file, map.as_deref(), only_mapped, report_dups, quiet),
+        },
+
+        Commands::Backup { file, force } => commands::backup::backup(&file, force, quiet),
+
+        Commands::Restore { file, yes } => commands::backup::restore(&file, yes, quiet),
+
+        Commands::Compact => commands::compact::run(quiet),
+
+        Commands::Batch { unsafe_display } => commands::batch::run(unsafe_display),
+
+        Commands::Daemon {
+            socket,
+            idle_timeout,
+            unsafe_display,
+        } => commands::daemon::run(
+            &socket,
+            idle_timeout.map(std::time::Duration::from_secs),
+            unsafe_display,
+            quiet,
+        ),
+
+        Commands::Reencrypt { name, yes, dry_run } => {
+            commands::reencrypt::run(&name, yes, dry_run, quiet)
+                .map(|()| porcelain_ok(porcelain, &name))
+        }
+
+        Commands::ExportBundle { file, force } => commands::bundle::export(&file, force, quiet),
+
+        Commands::ImportBundle { file, replace } => commands::bundle::import(&file, replace, quiet),
+
+        Commands::Setup {
+            print,
+            targets,
+            local,
+        } => commands::setup::run(print, &targets, local, quiet),
+
+        Commands::Uninstall { keep_vault, yes } => commands::uninstall::run(yes, keep_vault, quiet),
+    };
+
+    if let Err(e) = result {
+        report_error(&e);
+        std::process::exit(if e.is::<commands::import::VerifyError>() {
+            commands::import::VerifyError::EXIT_CODE
+        } else {
+            exit_code(&e)
+        });
+    }
+}
+
+/// The exit code for an error any command can hit: a declined keychain
+/// prompt gets its own, everything else exits 1
+fn exit_code(e: &anyhow::Error) -> i32 {
+    let denied = e.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<crate::Error>(),
+            Some(crate::Error::KeychainDenied)
+        )
+    });
+    if denied {
+        crate::Error::KEYCHAIN_DENIED_EXIT_CODE
+    } else {
+        1
+    }
+}
+
+/// SECRET_AGENT_NO_TIPS set to anything but empty or "0"
+fn tips_disabled_by_env() -> bool {
+    std::env::var("SECRET_AGENT_NO_TIPS").is_ok_and(|v| !v.is_empty() && v != "0")
+}
+
+/// Print an error, redacting any secret values this process loaded
+fn report_error(e: &anyhow::Error) {
+    eprintln!("Error: {}", sanitize::redact_loaded(&format!("{:#}", e)));
+}
+
+/// Put a bucket-less `create`/`import` name in the configured default bucket
+fn in_default_bucket(name: String) -> anyhow::Result<String> {
+    let bucket = config::Config::default_bucket()?;
+    Ok(vault::with_default_bucket(name, bucket.as_deref()))
+}
+
+/// Machine-readable success line for `--porcelain`
+fn porcelain_ok(porcelain: bool, name: &str) {
+    if porcelain {
+        println!("ok {}", name);
+    }
+}
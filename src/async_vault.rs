@@ -0,0 +1,76 @@
+//! An async adapter over `Vault` for tokio services (the `async` feature).
+//!
+//! Vault operations are synchronous and decryption can be slow, so each call
+//! runs on tokio's blocking thread pool instead of stalling the runtime. The
+//! underlying SQLite connection can't be used from two threads at once, so
+//! calls on one `AsyncVault` (and its clones) run one at a time. For parallel
+//! access, open a separate `AsyncVault` per task.
+
+use crate::error::Result;
+use crate::keychain::KeySource;
+use crate::vault::{Secret, Vault};
+use secrecy::SecretString;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// A `Vault` usable from async code. Cloning shares the same connection.
+#[derive(Clone)]
+pub struct AsyncVault {
+    inner: Arc<Mutex<Vault>>,
+}
+
+impl AsyncVault {
+    /// `Vault::open`, off the async runtime
+    pub async fn open() -> Result<Self> {
+        Self::from_blocking(Vault::open).await
+    }
+
+    /// `Vault::open_at`, off the async runtime
+    pub async fn open_at(path: PathBuf, key_source: KeySource) -> Result<Self> {
+        Self::from_blocking(move || Vault::open_at(&path, key_source)).await
+    }
+
+    pub async fn get(&self, name: &str) -> Result<SecretString> {
+        let name = name.to_string();
+        self.run(move |vault| vault.get(&name)).await
+    }
+
+    pub async fn create(&self, name: &str, value: &str) -> Result<()> {
+        let name = name.to_string();
+        // Zeroized when the task drops it
+        let value = SecretString::from(value);
+        self.run(move |vault| {
+            use secrecy::ExposeSecret;
+            vault.create(&name, value.expose_secret())
+        })
+        .await
+    }
+
+    pub async fn list(&self) -> Result<Vec<Secret>> {
+        self.run(|vault| vault.list()).await
+    }
+
+    async fn from_blocking(open: impl FnOnce() -> Result<Vault> + Send + 'static) -> Result<Self> {
+        let vault = blocking(open).await?;
+        Ok(Self {
+            inner: Arc::new(Mutex::new(vault)),
+        })
+    }
+
+    /// Run `op` on the blocking pool with exclusive use of the vault
+    async fn run<T: Send + 'static>(
+        &self,
+        op: impl FnOnce(&Vault) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let inner = Arc::clone(&self.inner);
+        blocking(move || op(&inner.lock().unwrap_or_else(|e| e.into_inner()))).await
+    }
+}
+
+/// `spawn_blocking`, re-raising a panic from the closure in the caller
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
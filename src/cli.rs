@@ -1,4 +1,5 @@
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "secret-agent")]
@@ -20,35 +21,110 @@ pub struct Cli {
     #[arg(short, long, global = true)]
     pub quiet: bool,
 
+    /// Print diagnostics to stderr: vault path, key source, secrets loaded
+    /// (never values; silenced by --quiet)
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// Print a stable `ok NAME` line on success instead of prose (implies --quiet)
+    #[arg(long, global = true)]
+    pub porcelain: bool,
+
+    /// Don't print the setup tip, without silencing other output
+    /// (or set SECRET_AGENT_NO_TIPS=1)
+    #[arg(long, global = true)]
+    pub no_tips: bool,
+
+    /// Vault database to use (overrides SECRET_AGENT_VAULT_PATH)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub vault_path: Option<PathBuf>,
+
+    /// Read the master key from this file, creating it with a random key if
+    /// missing (overrides SECRET_AGENT_PASSPHRASE and the keychain)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub key_file: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
 #[derive(Subcommand)]
+// Parsed once per run, so the size of the larger variants doesn't matter
+#[allow(clippy::large_enum_variant)]
 pub enum Commands {
     /// Generate and store a new random secret
     #[command(after_help = "Examples:
   secret-agent create DB_PASSWORD                  32-char alphanumeric (default)
   secret-agent create DB_PASSWORD -l 64            64-char alphanumeric
   secret-agent create DB_PASSWORD -c hex           Hex characters only
-  secret-agent create DB_PASSWORD --force          Overwrite existing secret")]
+  secret-agent create DB_PASSWORD --force          Overwrite existing secret
+
+Defaults for --charset and --length come from the nearest .secret-agent-policy.toml
+in the current directory or its parents, which may also set min_entropy_bits.")]
     Create {
         /// Name of the secret (e.g., API_KEY, DB_PASSWORD)
         name: String,
 
-        /// Length of the generated secret (default: 32)
+        /// Length of the generated secret (default: 32, or the policy file's)
+        #[arg(short, long)]
+        length: Option<usize>,
+
+        /// Character set to use: alphanumeric, ascii, hex, base64, printable, yaml-safe,
+        /// numeric, base58, or uuid (a v4 UUID; ignores --length);
+        /// `secret-agent charsets` shows each one
+        /// (default: alphanumeric, or the policy file's)
+        #[arg(short, long)]
+        charset: Option<String>,
+
+        /// Characters never to use, removed from the charset before sampling
+        /// (e.g. 'aeiouAEIOU' to avoid accidental words)
+        #[arg(long, value_name = "CHARS")]
+        exclude: Option<String>,
+
+        /// Generate this many random bytes and encode them (see --encoding)
+        /// instead of sampling characters from a charset
+        #[arg(long, conflicts_with_all = ["length", "charset", "exclude"])]
+        bytes: Option<usize>,
+
+        /// Encoding for --bytes: hex, base64 or base64url (RFC 4648, no padding)
+        #[arg(long, default_value = "base64", requires = "bytes")]
+        encoding: String,
+
+        /// Overwrite if the secret already exists
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Print random values without storing them (for seeding other tools)
+    #[command(after_help = "Examples:
+  secret-agent generate                             One 32-char alphanumeric value
+  secret-agent generate --count 3 --length 8        Three values, one per line
+  secret-agent generate -n 4 -c hex --separator ,   Comma-separated
+
+Output is not stored in the vault and not sanitized.")]
+    Generate {
+        /// Number of values to print
+        #[arg(short = 'n', long, default_value = "1")]
+        count: usize,
+
+        /// Length of each value
         #[arg(short, long, default_value = "32")]
         length: usize,
 
-        /// Character set to use: alphanumeric, ascii, hex, or base64
+        /// Character set to use: alphanumeric, ascii, hex, base64, printable, yaml-safe,
+        /// numeric, base58, or uuid (a v4 UUID; ignores --length);
+        /// `secret-agent charsets` shows each one
         #[arg(short, long, default_value = "alphanumeric")]
         charset: String,
 
-        /// Overwrite if the secret already exists
-        #[arg(short, long)]
-        force: bool,
+        /// Separator between values (`\n` and `\t` allowed)
+        #[arg(short, long, default_value = "\\n")]
+        separator: String,
     },
 
+    /// List the charsets --charset accepts, with an example of each
+    Charsets,
+
     /// Import a secret value from clipboard or stdin
     #[command(after_help = "Examples:
   secret-agent import API_KEY --clipboard    Read from clipboard (clears after)
@@ -63,36 +139,165 @@ pub enum Commands {
         #[arg(long)]
         clipboard: bool,
 
+        /// With --clipboard, read the primary selection instead (Linux only)
+        #[arg(long, requires = "clipboard")]
+        primary: bool,
+
+        /// With --clipboard, read by running CMD with `sh -c` and capturing its
+        /// output (e.g. `wl-paste`) instead of the system clipboard
+        /// [env: SECRET_AGENT_CLIPBOARD_PASTE_CMD]
+        #[arg(long, value_name = "CMD", requires = "clipboard")]
+        clipboard_paste_cmd: Option<String>,
+
+        /// With --clipboard, clear it afterwards by running CMD with empty input
+        /// [env: SECRET_AGENT_CLIPBOARD_CMD]
+        #[arg(long, value_name = "CMD", requires = "clipboard")]
+        clipboard_cmd: Option<String>,
+
+        /// With --clipboard, drop a trailing line that is only a URL (as browsers append)
+        #[arg(long, requires = "clipboard")]
+        strip_trailing_url: bool,
+
+        /// Store the input exactly as read. By default piped stdin loses its
+        /// trailing whitespace and newlines, and clipboard text is trimmed on both ends
+        #[arg(long, visible_alias = "no-trim", conflicts_with = "strip_trailing_url")]
+        raw: bool,
+
+        /// Store piped stdin as raw bytes (keys, keystores, any non-UTF-8 data);
+        /// read it back with `get --unsafe-display --raw`
+        #[arg(long, conflicts_with_all = ["clipboard", "raw", "verify"])]
+        binary: bool,
+
         /// Replace if the secret already exists
         #[arg(short, long)]
         replace: bool,
+
+        /// Read the value back after storing it; on a mismatch undo the import
+        /// and exit 3
+        #[arg(long)]
+        verify: bool,
+
+        /// With --verify, keep a mismatched row for inspection instead of undoing it
+        #[arg(long, requires = "verify")]
+        no_rollback: bool,
     },
 
     /// List all stored secret names (values are never shown)
     #[command(after_help = "Examples:
   secret-agent list                  List all secrets
-  secret-agent list --bucket prod    List only secrets in 'prod' bucket")]
+  secret-agent list --bucket prod    List only secrets in 'prod' bucket
+  secret-agent list --format csv     CSV for spreadsheets and audits
+  secret-agent list --json-envelope  JSON with a schema version for tooling")]
     List {
         /// Filter by bucket name (e.g., 'prod', 'dev')
         #[arg(short, long)]
         bucket: Option<String>,
+
+        /// Output format: table or csv
+        #[arg(long, default_value = "table")]
+        format: String,
+
+        /// Add a column with who created each secret (user@host)
+        #[arg(short, long)]
+        long: bool,
+
+        /// Print a JSON array of secret metadata
+        #[arg(long, conflicts_with = "format")]
+        json: bool,
+
+        /// Print JSON wrapped as {"schema":1,"secrets":[...]} so tools can
+        /// detect the output shape (implies --json)
+        #[arg(long, conflicts_with = "format")]
+        json_envelope: bool,
     },
 
-    /// Permanently delete a secret from the vault
-    Delete {
-        /// Name of the secret to delete
+    /// List buckets with how many secrets each holds
+    #[command(after_help = "Examples:
+  secret-agent buckets           Table of buckets and counts
+  secret-agent buckets --json    [{\"bucket\":\"prod\",\"count\":5},...] for bucket pickers
+
+Secrets without a bucket are counted under (none).")]
+    Buckets {
+        /// Print a JSON array of {bucket, count}
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show a secret's metadata (values are never shown)
+    #[command(after_help = "Examples:
+  secret-agent info API_KEY                       All metadata fields
+  secret-agent info API_KEY --field created_at    Just one field (for scripts)")]
+    Info {
+        /// Name of the secret
         name: String,
+
+        /// Print only this field: name, bucket, created_at, or updated_at
+        #[arg(long)]
+        field: Option<String>,
+    },
+
+    /// Permanently delete one or more secrets from the vault
+    Delete {
+        /// Names of the secrets to delete
+        #[arg(required_unless_present = "bucket", conflicts_with = "bucket")]
+        names: Vec<String>,
+
+        /// Skip names that don't exist instead of failing
+        #[arg(long)]
+        if_exists: bool,
+
+        /// Delete every secret in this bucket (nested buckets included),
+        /// after confirming the list
+        #[arg(long)]
+        bucket: Option<String>,
+
+        /// With --bucket, skip the confirmation
+        #[arg(short, long, requires = "bucket")]
+        yes: bool,
+    },
+
+    /// Rename a secret, or many at once with --regex
+    #[command(after_help = "Rename one secret:
+  secret-agent rename OLD_KEY NEW_KEY
+
+Preview, then apply, a bulk rename (all or nothing, collisions abort):
+  secret-agent rename --regex 's|^STAGING_|staging/|'
+  secret-agent rename --regex 's|^STAGING_|staging/|' --apply")]
+    Rename {
+        /// Current name of the secret
+        #[arg(requires = "new", required_unless_present = "regex")]
+        old: Option<String>,
+
+        /// New name for the secret
+        new: Option<String>,
+
+        /// Rename every secret whose name matches, as `s/PATTERN/REPLACEMENT/`
+        /// (add `g` to replace every match; `$1` refers to a capture group)
+        #[arg(long, value_name = "EXPR", conflicts_with = "old")]
+        regex: Option<String>,
+
+        /// Perform the --regex renames instead of only listing them
+        #[arg(long, requires = "regex", conflicts_with = "dry_run")]
+        apply: bool,
+
+        /// Only list the --regex renames (the default)
+        #[arg(long, requires = "regex")]
+        dry_run: bool,
     },
 
     /// Retrieve a secret value (to clipboard or display)
     #[command(after_help = "Copy to clipboard (safe for agent use):
   secret-agent get API_KEY --clipboard
 
+Copy several values as one entry (e.g. username and password):
+  secret-agent get DB_USER DB_PASS --clipboard --join '\\n' --unsafe
+
 Display in plaintext (NOT for agent use):
   secret-agent get API_KEY --unsafe-display")]
     Get {
-        /// Name of the secret to retrieve
-        name: String,
+        /// Names of the secrets to retrieve (several are copied one after another)
+        #[arg(required = true)]
+        names: Vec<String>,
 
         /// Copy secret to clipboard (never displayed, safe for agents)
         #[arg(long)]
@@ -101,6 +306,81 @@ Display in plaintext (NOT for agent use):
         /// Display the secret in plaintext (NOT for agent use)
         #[arg(long)]
         unsafe_display: bool,
+
+        /// Where --unsafe-display writes the value: stdout, stderr, or fd:N
+        #[arg(
+            long,
+            value_name = "STREAM",
+            default_value = "stdout",
+            requires = "unsafe_display"
+        )]
+        to: String,
+
+        /// With --unsafe-display, write the exact stored bytes of a binary
+        /// secret, without a trailing newline
+        #[arg(long, requires = "unsafe_display", conflicts_with = "clipboard")]
+        raw: bool,
+
+        /// With --raw, also print a secret that was stored as text
+        #[arg(long, requires = "raw")]
+        force: bool,
+
+        /// Copy all values as one clipboard entry, separated by SEP (`\n` and `\t` allowed)
+        #[arg(long, value_name = "SEP", requires = "clipboard")]
+        join: Option<String>,
+
+        /// Confirm composing plaintext values with --join
+        #[arg(long = "unsafe")]
+        allow_unsafe: bool,
+
+        /// Clear the clipboard after this many seconds, unless something else
+        /// was copied in the meantime
+        #[arg(long, value_name = "SECS", requires = "clipboard")]
+        clear_after: Option<u64>,
+
+        /// Copy by running CMD with `sh -c` and the value on its stdin (e.g.
+        /// `wl-copy` or `clip.exe`) instead of using the system clipboard
+        /// [env: SECRET_AGENT_CLIPBOARD_CMD]
+        #[arg(long, value_name = "CMD", requires = "clipboard")]
+        clipboard_cmd: Option<String>,
+
+        /// Command printing the clipboard, which --clear-after needs to check
+        /// it still holds our value [env: SECRET_AGENT_CLIPBOARD_PASTE_CMD]
+        #[arg(long, value_name = "CMD", requires = "clipboard")]
+        clipboard_paste_cmd: Option<String>,
+    },
+
+    /// Clear the clipboard later if it still holds a given value (used by get
+    /// --clear-after); the value's SHA-256 is read from stdin
+    #[command(hide = true)]
+    ClearClipboard {
+        #[arg(long)]
+        after: u64,
+    },
+
+    /// Enter the passphrase once and cache it in a background agent
+    #[command(after_help = "Examples:
+  secret-agent unlock              # cache for 15 minutes
+  secret-agent unlock --ttl 3600
+  secret-agent lock                # forget it now
+
+Only used when no keychain, key file or SECRET_AGENT_PASSPHRASE is available, i.e.
+when commands would otherwise prompt. The agent listens on ~/.secret-agent/agent.sock
+(mode 0600, same user only) and exits when the TTL runs out.")]
+    Unlock {
+        /// Seconds to keep the passphrase cached
+        #[arg(long, value_name = "SECS", default_value_t = 900)]
+        ttl: u64,
+    },
+
+    /// Forget the passphrase cached by `unlock`
+    Lock,
+
+    /// Serve a cached passphrase (started by unlock)
+    #[command(hide = true)]
+    Agent {
+        #[arg(long)]
+        ttl: u64,
     },
 
     /// Run a command with secrets injected as environment variables
@@ -114,6 +394,12 @@ Display in plaintext (NOT for agent use):
 2. As placeholders in the command string:
    secret-agent exec curl -H 'Auth: {{API_KEY}}' https://...
 
+3. As placeholders in a JSON args file (no shell involved):
+   secret-agent exec --args-file args.json
+
+Use --no-shell to run the command without `sh -c` so shell
+metacharacters in arguments are passed through literally.
+
 Output is automatically sanitized - any secret values in stdout/stderr
 are replaced with [REDACTED:NAME] so they never leak to logs or agents.")]
     Exec {
@@ -124,9 +410,101 @@ are replaced with [REDACTED:NAME] so they never leak to logs or agents.")]
         #[arg(short, long = "env", value_name = "SECRET[:VAR]")]
         env_secrets: Vec<String>,
 
+        /// Default bucket for --env specs without one: `--bucket prod -e API_KEY`
+        /// reads prod/API_KEY (still injected as API_KEY)
+        #[arg(long, value_name = "BUCKET")]
+        bucket: Option<String>,
+
+        /// Inject every secret in a bucket as an environment variable
+        /// (named without the bucket prefix). Explicit --env wins on collision.
+        #[arg(long = "env-bucket", value_name = "BUCKET")]
+        env_buckets: Vec<String>,
+
+        /// Read the command and arguments from a JSON array of strings.
+        /// Each argument may contain {{SECRET_NAME}} and is run without a shell.
+        #[arg(long, value_name = "FILE", conflicts_with = "command")]
+        args_file: Option<String>,
+
+        /// Put a secret into argv position N (0 is the program) without a shell:
+        /// replaces a __SECRET__ token in that argument, or the whole argument.
+        /// Implies --no-shell. Can be repeated.
+        #[arg(long = "secret-arg", value_name = "N=NAME")]
+        secret_args: Vec<String>,
+
+        /// Run the command directly instead of via `sh -c`.
+        /// Arguments are passed literally; {{SECRET_NAME}} still works per argument.
+        #[arg(long)]
+        no_shell: bool,
+
+        /// Shell used to run the command (default: sh). {{NAME}} placeholders
+        /// need a POSIX shell such as bash or zsh
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["no_shell", "args_file"])]
+        shell: Option<String>,
+
+        /// Load non-secret variables from a .env file into the command's environment
+        #[arg(long, value_name = "FILE")]
+        dotenv: Option<String>,
+
+        /// Resolve ${VAR} references in --dotenv values against injected
+        /// secrets, other dotenv entries, and the host environment
+        #[arg(long, requires = "dotenv")]
+        dotenv_expand: bool,
+
+        /// Also write the sanitized stdout and stderr to this file
+        #[arg(long, value_name = "PATH")]
+        tee: Option<String>,
+
+        /// Append to the --tee file instead of truncating it
+        #[arg(long, requires = "tee")]
+        tee_append: bool,
+
+        /// Emit NDJSON events (start, redaction, exit) on stderr for monitoring
+        #[arg(long)]
+        events: bool,
+
+        /// Remove ANSI escape sequences (colors, cursor movement) from the output
+        #[arg(long)]
+        strip_ansi: bool,
+
+        /// Also redact the value of this inherited host environment variable
+        /// from output (nothing is injected). Can be repeated.
+        #[arg(long = "redact-env", value_name = "VAR")]
+        redact_env: Vec<String>,
+
+        /// Render --template into this file (mode 0600) for tools that only read
+        /// config from a path; it is overwritten and deleted when the command exits
+        #[arg(long, value_name = "PATH", requires = "template")]
+        render_to: Option<String>,
+
+        /// Template with {{SECRET_NAME}} placeholders to render into --render-to
+        #[arg(long, value_name = "FILE", requires = "render_to")]
+        template: Option<String>,
+
+        /// Also write the --env secrets as a dotenv file (mode 0600) for process
+        /// managers that read env from a path. SECRET_AGENT_ENV_FILE points to
+        /// it; it is overwritten and deleted when the command exits
+        #[arg(long, value_name = "PATH")]
+        write_env_file: Option<String>,
+
+        /// Unix only: pass --env and --env-bucket secrets through an inherited
+        /// pipe instead of the environment. SECRET_AGENT_ENV_FD holds the
+        /// descriptor, which carries NUL-terminated NAME=value entries.
+        #[arg(long)]
+        env_fd: bool,
+
+        /// Shell command to run after the command exits, with its exit code in
+        /// SECRET_AGENT_EXIT_CODE. Gets no secrets; its output is sanitized too.
+        #[arg(long, value_name = "CMD")]
+        on_exit: Option<String>,
+
+        /// Print the resolved command and the variables it would get, with
+        /// secrets shown as [REDACTED:NAME], without running anything
+        #[arg(long)]
+        dry_run: bool,
+
         /// The command and arguments to execute.
         /// Use {{SECRET_NAME}} to inject secrets directly into the command string.
-        #[arg(trailing_var_arg = true, required = true)]
+        #[arg(trailing_var_arg = true, required_unless_present = "args_file")]
         command: Vec<String>,
     },
 
@@ -134,7 +512,9 @@ are replaced with [REDACTED:NAME] so they never leak to logs or agents.")]
     #[command(after_help = "Examples:
   secret-agent inject API_KEY -f .env --env-format            Append API_KEY=value
   secret-agent inject API_KEY -f .env --env-format --export   Append export API_KEY=\"value\"
-  secret-agent inject KEY -f config.json -p __KEY__           Replace __KEY__ placeholder")]
+  secret-agent inject KEY -f config.json -p __KEY__           Replace __KEY__ placeholder
+  secret-agent inject KEY -f app.ini -p __KEY__ --after-marker '[production]'
+                                                              Only within the [production] section")]
     Inject {
         /// Name of the secret to inject
         name: String,
@@ -154,6 +534,23 @@ are replaced with [REDACTED:NAME] so they never leak to logs or agents.")]
         /// Prefix with 'export ' (use with --env-format for shell scripts)
         #[arg(long)]
         export: bool,
+
+        /// Write the file even if its content would not change
+        #[arg(long)]
+        always_write: bool,
+
+        /// Only edit the section after the line equal to MARKER, up to the next
+        /// `[section]` header (for INI-like files with repeated keys)
+        #[arg(long, value_name = "MARKER")]
+        after_marker: Option<String>,
+
+        /// With --env-format, fail unless NAME is already set in the file
+        #[arg(long, requires = "env_format", conflicts_with = "create_only")]
+        update_only: bool,
+
+        /// With --env-format, fail if NAME is already set in the file
+        #[arg(long, requires = "env_format")]
+        create_only: bool,
     },
 
     /// Bulk import/export secrets to .env files
@@ -166,14 +563,157 @@ are replaced with [REDACTED:NAME] so they never leak to logs or agents.")]
         action: EnvAction,
     },
 
+    /// Copy the whole vault to a file (values stay encrypted)
+    #[command(after_help = "Examples:
+  secret-agent backup --file vault-backup.db
+  secret-agent restore --file vault-backup.db")]
+    Backup {
+        /// Destination file for the backup
+        #[arg(short, long)]
+        file: String,
+
+        /// Overwrite the destination if it exists
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Re-encrypt one secret under a new master key (read from
+    /// SECRET_AGENT_NEW_PASSPHRASE or prompted), leaving every other secret
+    /// on the current key. The current key can no longer read it afterwards.
+    Reencrypt {
+        /// Name of the secret to re-encrypt
+        name: String,
+
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Check that every secret decrypts with the current key, or the new
+        /// key if already re-encrypted, and report what would be re-encrypted,
+        /// without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Replace the current vault with a backup (requires the same master key)
+    Restore {
+        /// Backup file created by `secret-agent backup`
+        #[arg(short, long)]
+        file: String,
+
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Rebuild the vault file to reclaim space left by deleted secrets
+    Compact,
+
+    /// Run many operations over one open vault, reading NDJSON requests from stdin
+    #[command(
+        after_help = "Each stdin line is a JSON request; each stdout line is its response:
+  {\"op\":\"create\",\"name\":\"API_KEY\",\"length\":48}   -> {\"ok\":true,\"result\":{\"name\":\"API_KEY\"}}
+  {\"op\":\"info\",\"name\":\"API_KEY\",\"id\":7}          -> {\"id\":7,\"ok\":true,\"result\":{...}}
+
+Ops: create (length, charset, force), import (value, replace), get, info,
+list (bucket), delete. Failures return {\"ok\":false,\"error\":\"...\"} and the
+batch continues. `get` returns plaintext and requires --unsafe-display."
+    )]
+    Batch {
+        /// Allow `get` requests to return secret values (NOT for agent use)
+        #[arg(long)]
+        unsafe_display: bool,
+    },
+
+    /// Serve batch requests over a Unix socket, keeping the vault open
+    #[command(after_help = "Examples:
+  secret-agent daemon --socket /run/user/1000/secret-agent.sock &
+  echo '{\"op\":\"info\",\"name\":\"API_KEY\"}' | nc -U /run/user/1000/secret-agent.sock
+
+Speaks the same NDJSON protocol as `batch`, plus {\"op\":\"status\"}, which reports
+whether the keys are locked. Only clients running as the same user are served;
+the socket is created with mode 0600.")]
+    Daemon {
+        /// Path of the Unix socket to listen on
+        #[arg(long, value_name = "PATH")]
+        socket: String,
+
+        /// Drop the in-memory keys after this many seconds without requests;
+        /// the next request re-derives them
+        #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+        idle_timeout: Option<u64>,
+
+        /// Allow `get` requests to return secret values (NOT for agent use)
+        #[arg(long)]
+        unsafe_display: bool,
+    },
+
+    /// Export all secrets to a file encrypted with a separate bundle passphrase
+    #[command(after_help = "Examples:
+  secret-agent export-bundle --file secrets.age
+  secret-agent import-bundle --file secrets.age
+
+The bundle passphrase is prompted for (or read from SECRET_AGENT_BUNDLE_PASSPHRASE)
+and is independent of the vault master key, so bundles can move between machines.")]
+    ExportBundle {
+        /// Destination bundle file
+        #[arg(short, long)]
+        file: String,
+
+        /// Overwrite the destination if it exists
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Import secrets from a bundle created by export-bundle
+    ImportBundle {
+        /// Bundle file to read
+        #[arg(short, long)]
+        file: String,
+
+        /// Overwrite secrets that already exist (default: skip them)
+        #[arg(long)]
+        replace: bool,
+    },
+
     /// Set up Claude Code integration (append usage reference to ~/.claude/CLAUDE.md)
     #[command(after_help = "Examples:
-  secret-agent setup              Append instructions to ~/.claude/CLAUDE.md
-  secret-agent setup --print      Print instructions to stdout")]
+  secret-agent setup                              Append instructions to ~/.claude/CLAUDE.md
+  secret-agent setup --target cursor              Write ~/.cursor/rules/secret-agent.mdc
+  secret-agent setup --target claude --target agents
+                                                  Also write ./AGENTS.md for this project
+  secret-agent setup --local                      Append to ./CLAUDE.md in this project
+  secret-agent setup --print                      Print instructions to stdout")]
     Setup {
         /// Print the instructions to stdout instead of modifying files
         #[arg(long)]
         print: bool,
+
+        /// Where to write the instructions: claude, cursor, or agents (./AGENTS.md).
+        /// Can be repeated.
+        #[arg(
+            long = "target",
+            value_name = "TARGET",
+            default_value = "claude",
+            value_parser = ["claude", "cursor", "agents"]
+        )]
+        targets: Vec<String>,
+
+        /// Write to the current project (./CLAUDE.md, ./.cursor/rules/) instead
+        /// of your home directory
+        #[arg(long)]
+        local: bool,
+    },
+
+    /// Remove secret-agent's data: the vault, master key and agent instructions
+    Uninstall {
+        /// Keep the vault and its master key; only remove the agent instructions
+        #[arg(long)]
+        keep_vault: bool,
+
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
     },
 }
 
@@ -192,6 +732,14 @@ pub enum EnvAction {
         /// Export all secrets from the vault
         #[arg(long)]
         all: bool,
+
+        /// Write `export NAME="value"` lines (always quoted) for sourcing in a shell
+        #[arg(long)]
+        export: bool,
+
+        /// Write the file even if its content would not change
+        #[arg(long)]
+        always_write: bool,
     },
 
     /// Read secrets from a .env file into the vault
@@ -199,5 +747,18 @@ pub enum EnvAction {
         /// Source .env file to read
         #[arg(short, long)]
         file: String,
+
+        /// File of SOURCE=TARGET lines renaming .env names on import
+        /// (targets may include a bucket, e.g. `DB_PW=prod/DB_PASSWORD`)
+        #[arg(long, value_name = "FILE")]
+        map: Option<String>,
+
+        /// With --map, skip names the map doesn't mention
+        #[arg(long, requires = "map")]
+        only_mapped: bool,
+
+        /// After importing, list names in the file that share the same value
+        #[arg(long)]
+        report_dups: bool,
     },
 }
@@ -0,0 +1,42 @@
+use crate::prompt;
+use crate::vault::Vault;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+pub fn backup(file: &str, force: bool, quiet: bool) -> Result<()> {
+    let path = Path::new(file);
+    if path.exists() && !force {
+        anyhow::bail!("{} already exists (use --force to overwrite)", file);
+    }
+
+    let vault = Vault::open().context("failed to open vault")?;
+    vault
+        .backup_to(path)
+        .with_context(|| format!("failed to back up vault to {}", file))?;
+
+    if !quiet {
+        println!("Backed up vault to {} (values remain encrypted)", file);
+    }
+    Ok(())
+}
+
+pub fn restore(file: &str, yes: bool, quiet: bool) -> Result<()> {
+    let path = Path::new(file);
+    if !path.exists() {
+        anyhow::bail!("backup file not found: {}", file);
+    }
+
+    if !yes && !prompt::confirm("Replace ALL secrets in the current vault with this backup?")? {
+        anyhow::bail!("restore cancelled");
+    }
+
+    let mut vault = Vault::open().context("failed to open vault")?;
+    vault
+        .restore_from(path)
+        .with_context(|| format!("failed to restore vault from {}", file))?;
+
+    if !quiet {
+        println!("Restored vault from {}", file);
+    }
+    Ok(())
+}
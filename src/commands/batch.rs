@@ -0,0 +1,137 @@
+use crate::commands::create::generate_value;
+use crate::commands::list::metadata_json;
+use crate::vault::Vault;
+use anyhow::{Context, Result};
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+
+/// One request line on stdin
+#[derive(Deserialize)]
+struct Request {
+    /// Echoed back so callers can match responses to requests
+    #[serde(default)]
+    id: Option<Value>,
+    #[serde(flatten)]
+    op: Op,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+enum Op {
+    Create {
+        name: String,
+        length: Option<usize>,
+        charset: Option<String>,
+        #[serde(default)]
+        force: bool,
+    },
+    Import {
+        name: String,
+        value: String,
+        #[serde(default)]
+        replace: bool,
+    },
+    Get {
+        name: String,
+    },
+    Info {
+        name: String,
+    },
+    List {
+        bucket: Option<String>,
+    },
+    Delete {
+        name: String,
+    },
+}
+
+/// Read NDJSON requests from stdin and write one JSON response per line,
+/// reusing a single open vault (and its derived key) for every operation
+pub fn run(unsafe_display: bool) -> Result<()> {
+    let vault = Vault::open().context("failed to open vault")?;
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    for line in stdin.lock().lines() {
+        let line = line.context("failed to read from stdin")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        writeln!(out, "{}", handle_line(&vault, &line, unsafe_display))?;
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Parse and run one request line, producing its response (never fails)
+pub(crate) fn handle_line(vault: &Vault, line: &str, unsafe_display: bool) -> Value {
+    match serde_json::from_str::<Request>(line) {
+        Ok(request) => {
+            let mut response = match execute(vault, request.op, unsafe_display) {
+                Ok(result) => json!({ "ok": true, "result": result }),
+                Err(e) => json!({ "ok": false, "error": format!("{:#}", e) }),
+            };
+            if let Some(id) = request.id {
+                response["id"] = id;
+            }
+            response
+        }
+        Err(e) => json!({ "ok": false, "error": format!("invalid request: {}", e) }),
+    }
+}
+
+fn execute(vault: &Vault, op: Op, unsafe_display: bool) -> Result<Value> {
+    match op {
+        Op::Create {
+            name,
+            length,
+            charset,
+            force,
+        } => {
+            let value = generate_value(length, charset.as_deref(), None)?;
+            if force {
+                vault.create_or_update(&name, &value)?;
+            } else {
+                vault.create(&name, &value)?;
+            }
+            Ok(json!({ "name": name }))
+        }
+        Op::Import {
+            name,
+            value,
+            replace,
+        } => {
+            if value.is_empty() {
+                anyhow::bail!("secret value cannot be empty");
+            }
+            if replace {
+                vault.create_or_update(&name, &value)?;
+            } else {
+                vault.create(&name, &value)?;
+            }
+            Ok(json!({ "name": name }))
+        }
+        Op::Get { name } => {
+            if !unsafe_display {
+                anyhow::bail!("get returns plaintext; start with --unsafe-display to allow it");
+            }
+            let value = vault.get(&name)?;
+            Ok(json!({ "name": name, "value": value.expose_secret() }))
+        }
+        Op::Info { name } => Ok(metadata_json(&vault.get_metadata(&name)?)),
+        Op::List { bucket } => {
+            let secrets = vault.list_by_bucket(bucket.as_deref())?;
+            Ok(Value::Array(secrets.iter().map(metadata_json).collect()))
+        }
+        Op::Delete { name } => {
+            vault.delete(&name)?;
+            Ok(json!({ "name": name }))
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use crate::vault::Vault;
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+/// Shown in place of a bucket for secrets that have none
+const NO_BUCKET: &str = "(none)";
+
+pub fn run(json: bool) -> Result<()> {
+    let vault = Vault::open().context("failed to open vault")?;
+    let summary = vault.bucket_summary().context("failed to list buckets")?;
+
+    if json {
+        println!("{}", buckets_json(&summary));
+        return Ok(());
+    }
+
+    if summary.is_empty() {
+        println!("No secrets stored.");
+        return Ok(());
+    }
+    println!("{:<32} COUNT", "BUCKET");
+    for (bucket, count) in &summary {
+        println!("{:<32} {}", bucket.as_deref().unwrap_or(NO_BUCKET), count);
+    }
+    Ok(())
+}
+
+fn buckets_json(summary: &[(Option<String>, usize)]) -> Value {
+    summary
+        .iter()
+        .map(|(bucket, count)| {
+            json!({ "bucket": bucket.as_deref().unwrap_or(NO_BUCKET), "count": count })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets_json() {
+        let summary = vec![(Some("prod".to_string()), 5), (None, 2)];
+        assert_eq!(
+            buckets_json(&summary).to_string(),
+            r#"[{"bucket":"prod","count":5},{"bucket":"(none)","count":2}]"#
+        );
+    }
+}
@@ -0,0 +1,392 @@
+use crate::crypto;
+use crate::vault::Vault;
+use anyhow::{Context, Result};
+use base64::Engine;
+use secrecy::ExposeSecret;
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const BUNDLE_VERSION: u32 = 1;
+const PASSPHRASE_ENV: &str = "SECRET_AGENT_BUNDLE_PASSPHRASE";
+
+// A bundle is the JSON object `{"version":1,"secrets":[{"name":..,"value":..},..]}`,
+// encrypted as a whole with the bundle passphrase. It is streamed through age in
+// both directions, one entry at a time, so memory stays flat for huge vaults.
+// Binary secrets carry `"binary":true` with their bytes base64-encoded in `value`.
+
+#[derive(Serialize, Deserialize)]
+struct BundleEntry {
+    name: String,
+    value: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    binary: bool,
+}
+
+pub fn export(file: &str, force: bool, quiet: bool) -> Result<()> {
+    if Path::new(file).exists() && !force {
+        anyhow::bail!("{} already exists (use --force to overwrite)", file);
+    }
+
+    let vault = Vault::open().context("failed to open vault")?;
+    let passphrase = bundle_passphrase(true)?;
+
+    let out = create_private(file).with_context(|| format!("failed to write {}", file))?;
+    let written = crypto::encrypt_writer(BufWriter::new(out), &passphrase)
+        .context("failed to encrypt bundle")
+        .and_then(|mut writer| {
+            let count = write_bundle(&vault, &mut writer)?;
+            writer.finish()?.flush()?;
+            Ok(count)
+        })
+        .with_context(|| format!("failed to write {}", file));
+    let count = match written {
+        Ok(count) => count,
+        Err(e) => {
+            // Don't leave a truncated bundle behind
+            let _ = std::fs::remove_file(file);
+            return Err(e);
+        }
+    };
+
+    if !quiet {
+        println!("Exported {} secrets to {}", count, file);
+    }
+    Ok(())
+}
+
+pub fn import(file: &str, replace: bool, quiet: bool) -> Result<()> {
+    let encrypted = File::open(file).with_context(|| format!("failed to read {}", file))?;
+
+    let passphrase = bundle_passphrase(false)?;
+    let plaintext = crypto::decrypt_reader(BufReader::new(encrypted), &passphrase)
+        .context("failed to decrypt bundle (wrong passphrase?)")?;
+
+    let vault = Vault::open().context("failed to open vault")?;
+    let (imported, skipped) = read_bundle(&vault, plaintext, replace, quiet)?;
+
+    if !quiet {
+        println!(
+            "Imported {} secrets from {} ({} skipped)",
+            imported, file, skipped
+        );
+    }
+    Ok(())
+}
+
+/// Write every secret as bundle JSON, decrypting one at a time. Returns the count.
+fn write_bundle(vault: &Vault, out: &mut impl Write) -> Result<usize> {
+    let secrets = vault.list().context("failed to list secrets")?;
+
+    write!(out, "{{\"version\":{},\"secrets\":[", BUNDLE_VERSION)?;
+    for (i, secret) in secrets.iter().enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        let (value, binary) = vault
+            .get_bytes(&secret.name)
+            .with_context(|| format!("failed to read secret: {}", secret.name))?;
+        let value = if binary {
+            base64::engine::general_purpose::STANDARD.encode(value.expose_secret())
+        } else {
+            String::from_utf8(value.expose_secret().to_vec())
+                .with_context(|| format!("secret '{}' is not UTF-8 text", secret.name))?
+        };
+        let entry = BundleEntry {
+            name: secret.name.clone(),
+            value,
+            binary,
+        };
+        serde_json::to_writer(&mut *out, &entry)?;
+    }
+    out.write_all(b"]}")?;
+
+    Ok(secrets.len())
+}
+
+/// Import bundle JSON entry by entry as it is parsed. Everything is written
+/// in one transaction, so a truncated or corrupt bundle imports nothing.
+/// Returns (imported, skipped).
+fn read_bundle(
+    vault: &Vault,
+    plaintext: impl Read,
+    replace: bool,
+    quiet: bool,
+) -> Result<(usize, usize)> {
+    let tx = vault.transaction()?;
+    let mut importer = Importer {
+        vault,
+        replace,
+        quiet,
+        imported: 0,
+        skipped: 0,
+    };
+
+    let mut de = serde_json::Deserializer::from_reader(BufReader::new(plaintext));
+    (&mut importer)
+        .deserialize(&mut de)
+        .and_then(|()| de.end())
+        .context("invalid bundle contents")?;
+
+    tx.commit()?;
+    Ok((importer.imported, importer.skipped))
+}
+
+/// Stores each bundle entry as soon as the parser produces it
+struct Importer<'a> {
+    vault: &'a Vault,
+    replace: bool,
+    quiet: bool,
+    imported: usize,
+    skipped: usize,
+}
+
+impl Importer<'_> {
+    fn import(&mut self, entry: &BundleEntry) -> Result<()> {
+        if !self.replace && self.vault.exists(&entry.name)? {
+            if !self.quiet {
+                eprintln!("Skipping {} (already exists, use --replace)", entry.name);
+            }
+            self.skipped += 1;
+            return Ok(());
+        }
+        if entry.binary {
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(&entry.value)
+                .with_context(|| format!("invalid base64 for binary secret: {}", entry.name))?;
+            self.vault
+                .store_binary(&entry.name, &bytes, true)
+                .with_context(|| format!("failed to import secret: {}", entry.name))?;
+        } else {
+            self.vault
+                .create_or_update(&entry.name, &entry.value)
+                .with_context(|| format!("failed to import secret: {}", entry.name))?;
+        }
+        self.imported += 1;
+        Ok(())
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for &mut Importer<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for &mut Importer<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a secret-agent bundle")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut version = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "version" => version = Some(map.next_value::<u32>()?),
+                "secrets" => {
+                    // Entries are imported as they are read, so the version must come first
+                    check_version(version)?;
+                    map.next_value_seed(Entries(&mut *self))?;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        check_version(version)
+    }
+}
+
+fn check_version<E: de::Error>(version: Option<u32>) -> Result<(), E> {
+    match version {
+        Some(BUNDLE_VERSION) => Ok(()),
+        Some(v) => Err(E::custom(format!("unsupported bundle version: {}", v))),
+        None => Err(E::missing_field("version")),
+    }
+}
+
+/// The `secrets` array, imported element by element
+struct Entries<'a, 'b>(&'a mut Importer<'b>);
+
+impl<'de> DeserializeSeed<'de> for Entries<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Entries<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of secrets")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(entry) = seq.next_element::<BundleEntry>()? {
+            self.0
+                .import(&entry)
+                .map_err(|e| de::Error::custom(format!("{:#}", e)))?;
+        }
+        Ok(())
+    }
+}
+
+/// The bundle passphrase, from SECRET_AGENT_BUNDLE_PASSPHRASE or a prompt.
+/// It is unrelated to the vault master key.
+fn bundle_passphrase(confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        if passphrase.is_empty() {
+            anyhow::bail!("{} is set but empty", PASSPHRASE_ENV);
+        }
+        return Ok(passphrase);
+    }
+
+    if !atty::is(atty::Stream::Stdin) {
+        anyhow::bail!(
+            "bundle passphrase required: run interactively or set {}",
+            PASSPHRASE_ENV
+        );
+    }
+
+    let passphrase =
+        rpassword::prompt_password("Bundle passphrase: ").context("failed to read passphrase")?;
+    if passphrase.is_empty() {
+        anyhow::bail!("passphrase cannot be empty");
+    }
+
+    if confirm {
+        let again = rpassword::prompt_password("Confirm passphrase: ")
+            .context("failed to read passphrase")?;
+        if again != passphrase {
+            anyhow::bail!("passphrases do not match");
+        }
+    }
+
+    Ok(passphrase)
+}
+
+fn create_private(file: &str) -> std::io::Result<File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.create(true).write(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keychain::KeySource;
+    use tempfile::TempDir;
+
+    fn open_vault(dir: &TempDir, name: &str) -> Vault {
+        let key = KeySource::Passphrase("test-passphrase".into());
+        Vault::open_at(&dir.path().join(name), key).unwrap()
+    }
+
+    /// Export through the age stream writer into `out`
+    fn export_to(vault: &Vault, out: &mut Vec<u8>) -> usize {
+        let mut writer = crypto::encrypt_writer(out, "bundle-passphrase").unwrap();
+        let count = write_bundle(vault, &mut writer).unwrap();
+        writer.finish().unwrap();
+        count
+    }
+
+    #[test]
+    fn test_large_bundle_streams_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let source = open_vault(&dir, "source.db");
+        let value = "x".repeat(16 * 1024);
+        let tx = source.transaction().unwrap();
+        for i in 0..100 {
+            source
+                .create(&format!("KEY_{:04}", i), &format!("{}-{}", value, i))
+                .unwrap();
+        }
+        tx.commit().unwrap();
+
+        let mut bundle = Vec::new();
+        assert_eq!(export_to(&source, &mut bundle), 100);
+        // Spans many 64 KiB age chunks
+        assert!(bundle.len() > 1_600_000);
+
+        let target = open_vault(&dir, "target.db");
+        let plaintext = crypto::decrypt_reader(bundle.as_slice(), "bundle-passphrase").unwrap();
+        assert_eq!(
+            read_bundle(&target, plaintext, false, true).unwrap(),
+            (100, 0)
+        );
+
+        let expected = source.get_all().unwrap();
+        let actual = target.get_all().unwrap();
+        assert_eq!(actual.len(), expected.len());
+        for ((name_a, value_a), (name_b, value_b)) in expected.iter().zip(&actual) {
+            assert_eq!(name_a, name_b);
+            assert_eq!(value_a.expose_secret(), value_b.expose_secret());
+        }
+    }
+
+    #[test]
+    fn test_binary_secret_roundtrips_through_bundle() {
+        let dir = TempDir::new().unwrap();
+        let source = open_vault(&dir, "source.db");
+        let bytes = [0x00, 0xde, 0xad, 0xbe, 0xef, 0xff];
+        source.store_binary("BLOB", &bytes, false).unwrap();
+        source.create("TEXT", "plain").unwrap();
+
+        let mut json = Vec::new();
+        assert_eq!(write_bundle(&source, &mut json).unwrap(), 2);
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains(r#"{"name":"BLOB","value":"AN6tvu//","binary":true}"#));
+        assert!(json.contains(r#"{"name":"TEXT","value":"plain"}"#));
+
+        let target = open_vault(&dir, "target.db");
+        assert_eq!(
+            read_bundle(&target, json.as_bytes(), false, true).unwrap(),
+            (2, 0)
+        );
+        let (value, binary) = target.get_bytes("BLOB").unwrap();
+        assert!(binary);
+        assert_eq!(value.expose_secret(), &bytes);
+        assert_eq!(target.get("TEXT").unwrap().expose_secret(), "plain");
+    }
+
+    #[test]
+    fn test_truncated_bundle_imports_nothing() {
+        let dir = TempDir::new().unwrap();
+        let source = open_vault(&dir, "source.db");
+        source.create("FIRST", "one").unwrap();
+        source.create("SECOND", "two").unwrap();
+
+        let mut json = Vec::new();
+        write_bundle(&source, &mut json).unwrap();
+        json.truncate(json.len() - 10);
+
+        let target = open_vault(&dir, "target.db");
+        assert!(read_bundle(&target, json.as_slice(), false, true).is_err());
+        assert!(target.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_bundle_version_checked() {
+        let dir = TempDir::new().unwrap();
+        let target = open_vault(&dir, "target.db");
+
+        let json = br#"{"version":2,"secrets":[{"name":"KEY","value":"v"}]}"#;
+        let err = read_bundle(&target, json.as_slice(), false, true).unwrap_err();
+        assert!(format!("{:#}", err).contains("unsupported bundle version: 2"));
+        assert!(!target.exists("KEY").unwrap());
+    }
+}
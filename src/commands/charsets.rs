@@ -0,0 +1,64 @@
+use crate::secret_gen::{self, Charset};
+use anyhow::Result;
+
+/// Length of each charset's example value
+const EXAMPLE_LENGTH: usize = 16;
+
+pub fn run() -> Result<()> {
+    print!("{}", listing());
+    Ok(())
+}
+
+/// One row per charset with its name, a sample value and what it draws from
+fn listing() -> String {
+    let mut out = format!("{:<28} {:<38} CHARACTERS\n", "NAME", "EXAMPLE");
+    for charset in Charset::ALL {
+        out.push_str(&format!(
+            "{:<28} {:<38} {}\n",
+            display_name(charset),
+            secret_gen::generate(EXAMPLE_LENGTH, charset),
+            charset.description()
+        ));
+    }
+    out
+}
+
+/// The name, followed by any aliases in parentheses
+fn display_name(charset: Charset) -> String {
+    match charset.aliases() {
+        [] => charset.name().to_string(),
+        aliases => format!("{} ({})", charset.name(), aliases.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing_includes_every_charset() {
+        let listing = listing();
+        let names: Vec<&str> = listing
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_whitespace().next())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "alphanumeric",
+                "ascii",
+                "hex",
+                "base64",
+                "printable",
+                "yaml-safe",
+                "numeric",
+                "base58",
+                "uuid"
+            ]
+        );
+        for name in names {
+            assert!(name.parse::<Charset>().is_ok());
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use crate::vault::Vault;
+use anyhow::{Context, Result};
+
+pub fn run(quiet: bool) -> Result<()> {
+    let vault = Vault::open().context("failed to open vault")?;
+    let (before, after) = vault.compact().context("failed to compact vault")?;
+
+    if !quiet {
+        println!(
+            "Compacted vault: {} -> {} bytes ({} reclaimed)",
+            before,
+            after,
+            before.saturating_sub(after)
+        );
+    }
+    Ok(())
+}
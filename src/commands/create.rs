@@ -1,16 +1,39 @@
-use crate::secret_gen::{self, Charset};
-use crate::vault::Vault;
+use crate::policy::Policy;
+use crate::secret_gen::{self, Charset, Encoding};
+use crate::vault::{self, Vault};
 use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
 
-pub fn run(name: &str, length: usize, charset: &str, force: bool, quiet: bool) -> Result<()> {
-    let charset: Charset = charset
-        .parse()
-        .map_err(|e: String| anyhow::anyhow!(e))
-        .context("invalid charset")?;
+const DEFAULT_LENGTH: usize = 32;
+const DEFAULT_CHARSET: &str = "alphanumeric";
 
-    let vault = Vault::open().context("failed to open vault")?;
+/// How `create` generates the value; unset options come from the policy file
+pub struct ValueOptions {
+    /// Number of characters
+    pub length: Option<usize>,
+    /// Charset name, parsed as a `Charset`
+    pub charset: Option<String>,
+    /// Characters removed from the charset before sampling
+    pub exclude: Option<String>,
+    /// Encode this many random bytes instead of sampling characters
+    pub bytes: Option<usize>,
+    /// Encoding name for `bytes`, parsed as an `Encoding`
+    pub encoding: String,
+}
+
+pub fn run(name: &str, opts: &ValueOptions, force: bool, quiet: bool) -> Result<()> {
+    vault::validate_full_name(name).context("invalid secret name")?;
 
-    let value = secret_gen::generate(length, charset);
+    let value = match opts.bytes {
+        Some(bytes) => generate_encoded_value(bytes, &opts.encoding)?,
+        None => generate_value(
+            opts.length,
+            opts.charset.as_deref(),
+            opts.exclude.as_deref(),
+        )?,
+    };
+
+    let vault = Vault::open().context("failed to open vault")?;
 
     if force {
         vault
@@ -27,3 +50,96 @@ pub fn run(name: &str, length: usize, charset: &str, force: bool, quiet: bool) -
     }
     Ok(())
 }
+
+/// Generate a value, filling unset options from the nearest policy file
+pub(crate) fn generate_value(
+    length: Option<usize>,
+    charset: Option<&str>,
+    exclude: Option<&str>,
+) -> Result<String> {
+    let (policy_path, policy) = discover_policy()?;
+
+    // Explicit flags win over the policy, which wins over built-in defaults
+    let charset: Charset = charset
+        .or(policy.charset.as_deref())
+        .unwrap_or(DEFAULT_CHARSET)
+        .parse()
+        .map_err(|e: String| anyhow::anyhow!(e))
+        .context("invalid charset")?;
+    verbose!("charset: {}", charset.name());
+    let mut length = length.or(policy.length).unwrap_or(DEFAULT_LENGTH);
+    // A uuid's length is fixed, whatever was asked for
+    if !matches!(charset, Charset::Uuid) {
+        length = checked_length(length, "length")?;
+    }
+
+    let Some(exclude) = exclude else {
+        check_entropy_floor(
+            secret_gen::entropy_bits(length, charset),
+            &policy,
+            policy_path.as_deref(),
+        )?;
+        return Ok(secret_gen::generate(length, charset));
+    };
+
+    let bits = secret_gen::entropy_bits_excluding(length, charset, exclude)
+        .map_err(|e| anyhow::anyhow!(e))
+        .context("invalid --exclude")?;
+    check_entropy_floor(bits, &policy, policy_path.as_deref())?;
+    secret_gen::generate_excluding(length, charset, exclude).map_err(|e| anyhow::anyhow!(e))
+}
+
+/// Encode `bytes` random bytes (`create --bytes`). The policy's charset and
+/// length don't apply, but its entropy floor does.
+fn generate_encoded_value(bytes: usize, encoding: &str) -> Result<String> {
+    let bytes = checked_length(bytes, "byte count")?;
+    let encoding: Encoding = encoding
+        .parse()
+        .map_err(|e: String| anyhow::anyhow!(e))
+        .context("invalid encoding")?;
+
+    let (policy_path, policy) = discover_policy()?;
+    check_entropy_floor(bytes as f64 * 8.0, &policy, policy_path.as_deref())?;
+
+    Ok(secret_gen::generate_encoded(bytes, encoding))
+}
+
+/// Reject 0 and cap anything above `secret_gen::MAX_LENGTH`, with a warning
+fn checked_length(length: usize, what: &str) -> Result<usize> {
+    secret_gen::validate_length(length).map_err(|e| anyhow::anyhow!(e))?;
+    if length > secret_gen::MAX_LENGTH {
+        eprintln!(
+            "warning: {} {} is above the maximum of {}; using {}",
+            what,
+            length,
+            secret_gen::MAX_LENGTH,
+            secret_gen::MAX_LENGTH
+        );
+        return Ok(secret_gen::MAX_LENGTH);
+    }
+    Ok(length)
+}
+
+/// The nearest policy file, or the default policy if there is none
+fn discover_policy() -> Result<(Option<PathBuf>, Policy)> {
+    let cwd = std::env::current_dir().context("failed to read current directory")?;
+    let policy = Policy::discover(&cwd).context("failed to load policy file")?;
+    Ok(match policy {
+        Some((path, policy)) => (Some(path), policy),
+        None => (None, Policy::default()),
+    })
+}
+
+fn check_entropy_floor(bits: f64, policy: &Policy, policy_path: Option<&Path>) -> Result<()> {
+    if let (Some(floor), Some(path)) = (policy.min_entropy_bits, policy_path) {
+        if bits < floor {
+            anyhow::bail!(
+                "secret would have {:.0} bits of entropy, below the {} bit floor set by {}",
+                bits,
+                floor,
+                path.display()
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,194 @@
+//! Serve batch requests over a Unix domain socket.
+//!
+//! The daemon opens the vault (and derives its key) once, then answers the
+//! same NDJSON requests as `batch` for any number of connections. Only
+//! clients running as the daemon's own user are served.
+//!
+//! With an idle timeout, the open vault is dropped (zeroizing the master and
+//! derived keys) after a period without requests and reopened on demand.
+
+use anyhow::Result;
+use std::time::Duration;
+
+#[cfg(unix)]
+mod imp {
+    use crate::commands::batch;
+    use crate::peer;
+    use crate::vault::Vault;
+    use anyhow::{Context, Result};
+    use serde_json::{json, Value};
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// The open vault, if unlocked, plus what the idle timer needs
+    struct Session {
+        vault: Option<Vault>,
+        last_activity: Instant,
+        /// How many times the vault has been opened (key derived)
+        unlocks: u64,
+    }
+
+    impl Session {
+        fn vault(&mut self) -> Result<&Vault> {
+            if self.vault.is_none() {
+                self.vault = Some(Vault::open().context("failed to open vault")?);
+                self.unlocks += 1;
+            }
+            Ok(self.vault.as_ref().expect("vault opened above"))
+        }
+    }
+
+    pub fn run(
+        socket: &str,
+        idle_timeout: Option<Duration>,
+        unsafe_display: bool,
+        quiet: bool,
+    ) -> Result<()> {
+        let path = Path::new(socket);
+        prepare_socket_path(path)?;
+
+        let mut session = Session {
+            vault: None,
+            last_activity: Instant::now(),
+            unlocks: 0,
+        };
+        session.vault()?;
+        let session = Arc::new(Mutex::new(session));
+
+        let listener =
+            UnixListener::bind(path).with_context(|| format!("failed to bind {}", socket))?;
+        restrict_permissions(path)?;
+
+        if let Some(timeout) = idle_timeout {
+            spawn_idle_timer(Arc::clone(&session), timeout, quiet);
+        }
+
+        if !quiet {
+            eprintln!("Listening on {}", socket);
+        }
+
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("warning: failed to accept connection: {}", e);
+                    continue;
+                }
+            };
+            // One thread per connection, so an idle or slow client can't
+            // hold up the others; the session lock is only held per request
+            let session = Arc::clone(&session);
+            std::thread::spawn(move || {
+                if let Err(e) = serve(&session, stream, unsafe_display) {
+                    eprintln!("warning: connection failed: {:#}", e);
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Drop the vault once no request has arrived for `timeout`
+    fn spawn_idle_timer(session: Arc<Mutex<Session>>, timeout: Duration, quiet: bool) {
+        let tick = timeout.min(Duration::from_secs(1)) / 2;
+        std::thread::spawn(move || loop {
+            std::thread::sleep(tick);
+            let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+            if session.vault.is_some() && session.last_activity.elapsed() >= timeout {
+                // Dropping the vault zeroizes the master key and derived identity
+                session.vault = None;
+                if !quiet {
+                    eprintln!("Locked after {}s idle", timeout.as_secs());
+                }
+            }
+        });
+    }
+
+    /// Refuse to replace a live socket; clean up one left by a dead daemon
+    fn prepare_socket_path(path: &Path) -> Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+        if UnixStream::connect(path).is_ok() {
+            anyhow::bail!("a daemon is already listening on {}", path.display());
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("failed to remove stale socket {}", path.display()))
+    }
+
+    fn restrict_permissions(path: &Path) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("failed to set permissions on {}", path.display()))
+    }
+
+    fn serve(session: &Mutex<Session>, stream: UnixStream, unsafe_display: bool) -> Result<()> {
+        let mut writer = stream.try_clone()?;
+
+        if let Err(e) = peer::check(&stream) {
+            writeln!(writer, "{}", json!({ "ok": false, "error": e.to_string() }))?;
+            return Ok(());
+        }
+
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+            let response = match status_request(&line) {
+                Some(id) => {
+                    let mut response = json!({
+                        "ok": true,
+                        "result": { "locked": session.vault.is_none(), "unlocks": session.unlocks },
+                    });
+                    if let Some(id) = id {
+                        response["id"] = id;
+                    }
+                    response
+                }
+                None => {
+                    session.last_activity = Instant::now();
+                    match session.vault() {
+                        Ok(vault) => batch::handle_line(vault, &line, unsafe_display),
+                        Err(e) => json!({ "ok": false, "error": format!("{:#}", e) }),
+                    }
+                }
+            };
+            drop(session);
+            writeln!(writer, "{}", response)?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// A daemon-only `{"op":"status"}` request, returning its optional id.
+    /// Status checks don't count as activity, so polling never keeps the key resident.
+    fn status_request(line: &str) -> Option<Option<Value>> {
+        let value: Value = serde_json::from_str(line).ok()?;
+        (value["op"] == "status").then(|| value.get("id").cloned())
+    }
+}
+
+#[cfg(unix)]
+pub fn run(
+    socket: &str,
+    idle_timeout: Option<Duration>,
+    unsafe_display: bool,
+    quiet: bool,
+) -> Result<()> {
+    imp::run(socket, idle_timeout, unsafe_display, quiet)
+}
+
+#[cfg(not(unix))]
+pub fn run(
+    _socket: &str,
+    _idle_timeout: Option<Duration>,
+    _unsafe_display: bool,
+    _quiet: bool,
+) -> Result<()> {
+    anyhow::bail!("daemon mode requires Unix domain sockets")
+}
@@ -1,13 +1,67 @@
+use crate::prompt;
 use crate::vault::Vault;
 use anyhow::{Context, Result};
 
-pub fn run(name: &str, quiet: bool) -> Result<()> {
+/// Delete each named secret, returning the names that were actually deleted
+pub fn run(names: &[String], if_exists: bool, quiet: bool) -> Result<Vec<String>> {
     let vault = Vault::open().context("failed to open vault")?;
 
-    vault.delete(name).context("failed to delete secret")?;
+    let results = vault
+        .delete_many(names, if_exists)
+        .context("failed to delete secret")?;
 
+    let mut deleted = Vec::new();
+    for (name, existed) in names.iter().zip(results) {
+        if existed {
+            if !quiet {
+                println!("Deleted secret: {}", name);
+            }
+            deleted.push(name.clone());
+        } else if !quiet {
+            println!("Not found (skipped): {}", name);
+        }
+    }
+
+    if !quiet && names.len() > 1 {
+        println!("Deleted {} of {} secrets", deleted.len(), names.len());
+    }
+    Ok(deleted)
+}
+
+/// Delete every secret in `bucket` after confirming the count and names,
+/// returning the names deleted
+pub fn run_bucket(bucket: &str, yes: bool, quiet: bool) -> Result<Vec<String>> {
+    let vault = Vault::open().context("failed to open vault")?;
+
+    let names: Vec<String> = vault
+        .list_by_bucket(Some(bucket))
+        .context("failed to list secrets")?
+        .into_iter()
+        .map(|secret| secret.name)
+        .collect();
+    if names.is_empty() {
+        if !quiet {
+            println!("No secrets in bucket: {}", bucket);
+        }
+        return Ok(names);
+    }
+
+    if !yes {
+        eprintln!("{}", names.join("\n"));
+        if !prompt::confirm(&format!(
+            "Delete these {} secrets in bucket '{}'?",
+            names.len(),
+            bucket
+        ))? {
+            anyhow::bail!("delete cancelled");
+        }
+    }
+
+    let deleted = vault
+        .delete_bucket(bucket)
+        .context("failed to delete bucket")?;
     if !quiet {
-        println!("Deleted secret: {}", name);
+        println!("Deleted {} secrets in bucket: {}", deleted, bucket);
     }
-    Ok(())
+    Ok(names)
 }
@@ -1,15 +1,42 @@
-use crate::vault::Vault;
+use crate::fs_util::write_if_changed;
+use crate::vault::{self, Vault};
 use anyhow::{Context, Result};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-pub fn export(file: &str, names: &[String], all: bool, quiet: bool) -> Result<()> {
+pub fn export(
+    file: &str,
+    names: &[String],
+    all: bool,
+    export: bool,
+    always_write: bool,
+    quiet: bool,
+) -> Result<()> {
     let vault = Vault::open().context("failed to open vault")?;
 
-    let secrets_to_export: Vec<String> = if all {
-        vault.list()?.into_iter().map(|s| s.name).collect()
+    let secrets_to_export: Vec<(String, SecretString)> = if all {
+        // A dotenv file can't hold raw bytes, so binary secrets are left out
+        for name in vault.binary_names().context("failed to list secrets")? {
+            eprintln!(
+                "warning: skipping binary secret '{}' (read it with get --raw)",
+                name
+            );
+        }
+        // Fast path: one query, parallel decryption
+        vault.get_all().context("failed to read secrets")?
     } else {
-        names.to_vec()
+        names
+            .iter()
+            .map(|name| {
+                vault
+                    .get(name)
+                    .map(|value| (name.clone(), value))
+                    .with_context(|| format!("failed to get secret '{}'", name))
+            })
+            .collect::<Result<_>>()?
     };
 
     if secrets_to_export.is_empty() {
@@ -22,24 +49,46 @@ pub fn export(file: &str, names: &[String], all: bool, quiet: bool) -> Result<()
     let path = Path::new(file);
     let mut lines: Vec<String> = Vec::new();
 
-    for name in &secrets_to_export {
-        let value = vault
-            .get(name)
-            .with_context(|| format!("failed to get secret '{}'", name))?;
-        lines.push(format!("{}={}", name, quote_env_value(&value)));
+    for (name, value) in &secrets_to_export {
+        let value = value.expose_secret();
+        lines.push(if export {
+            // Always quoted, so the file can be sourced by any shell
+            format!("export {}={}", name, double_quote_env_value(value))
+        } else {
+            format!("{}={}", name, quote_env_value(value))
+        });
     }
 
     let content = lines.join("\n") + "\n";
-    fs::write(path, content)
+    let written = write_if_changed(path, &content, always_write)
         .with_context(|| format!("failed to write file: {}", path.display()))?;
 
     if !quiet {
-        println!("Exported {} secrets to {}", secrets_to_export.len(), file);
+        if written {
+            println!("Exported {} secrets to {}", secrets_to_export.len(), file);
+        } else {
+            println!("{} already up to date", file);
+        }
     }
     Ok(())
 }
 
-pub fn import(file: &str, quiet: bool) -> Result<()> {
+pub fn import(
+    file: &str,
+    map: Option<&str>,
+    only_mapped: bool,
+    report_dups: bool,
+    quiet: bool,
+) -> Result<()> {
+    let renames = match map {
+        Some(map) => {
+            let content = fs::read_to_string(map)
+                .with_context(|| format!("failed to read map file: {}", map))?;
+            parse_rename_map(&content).with_context(|| format!("invalid map file: {}", map))?
+        }
+        None => HashMap::new(),
+    };
+
     let vault = Vault::open().context("failed to open vault")?;
 
     let content =
@@ -47,6 +96,7 @@ pub fn import(file: &str, quiet: bool) -> Result<()> {
 
     let mut imported = Vec::new();
     let mut skipped = Vec::new();
+    let mut value_hashes = Vec::new();
 
     for line in content.lines() {
         let line = line.trim();
@@ -58,6 +108,15 @@ pub fn import(file: &str, quiet: bool) -> Result<()> {
 
         // Parse NAME=value
         if let Some((name, value)) = parse_env_line(line) {
+            let name = match renames.get(&name) {
+                Some(target) => target.clone(),
+                None if only_mapped => continue,
+                None => name,
+            };
+            if report_dups {
+                value_hashes.push((name.clone(), Sha256::digest(value.as_bytes())));
+            }
+
             // Check if secret already exists
             if vault.exists(&name)? {
                 skipped.push(name);
@@ -92,10 +151,64 @@ pub fn import(file: &str, quiet: bool) -> Result<()> {
         }
     }
 
+    if report_dups {
+        let groups = duplicate_groups(&value_hashes);
+        if groups.is_empty() {
+            println!("No duplicate values in {}", file);
+        }
+        for names in groups {
+            println!("Duplicate values: {}", names.join(", "));
+        }
+    }
+
     Ok(())
 }
 
-fn parse_env_line(line: &str) -> Option<(String, String)> {
+/// Parse `SOURCE=TARGET` lines (blank lines and `#` comments allowed) into a
+/// map from .env names to secret names. Targets may include a bucket.
+fn parse_rename_map(content: &str) -> Result<HashMap<String, String>> {
+    let mut renames = HashMap::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (source, target) = line
+            .split_once('=')
+            .map(|(s, t)| (s.trim(), t.trim()))
+            .filter(|(s, t)| !s.is_empty() && !t.is_empty())
+            .with_context(|| format!("line {}: expected SOURCE=TARGET", i + 1))?;
+        vault::validate_full_name(target).with_context(|| format!("line {}", i + 1))?;
+        if renames
+            .insert(source.to_string(), target.to_string())
+            .is_some()
+        {
+            anyhow::bail!("line {}: {} is mapped more than once", i + 1, source);
+        }
+    }
+    Ok(renames)
+}
+
+/// Group names that share the same value hash, in file order.
+/// Only groups with more than one name are returned.
+fn duplicate_groups<H: PartialEq>(value_hashes: &[(String, H)]) -> Vec<Vec<String>> {
+    let mut groups: Vec<(&H, Vec<String>)> = Vec::new();
+
+    for (name, hash) in value_hashes {
+        match groups.iter_mut().find(|(h, _)| *h == hash) {
+            Some((_, names)) => names.push(name.clone()),
+            None => groups.push((hash, vec![name.clone()])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(_, names)| names)
+        .filter(|names| names.len() > 1)
+        .collect()
+}
+
+pub(crate) fn parse_env_line(line: &str) -> Option<(String, String)> {
     // Handle "export NAME=value" format
     let line = line.strip_prefix("export ").unwrap_or(line);
 
@@ -115,42 +228,70 @@ fn unquote_env_value(value: &str) -> String {
     let value = value.trim();
 
     // Handle quoted strings
-    if (value.starts_with('"') && value.ends_with('"'))
-        || (value.starts_with('\'') && value.ends_with('\''))
+    if value.len() >= 2
+        && ((value.starts_with('"') && value.ends_with('"'))
+            || (value.starts_with('\'') && value.ends_with('\'')))
     {
-        let inner = &value[1..value.len() - 1];
-        // Unescape common sequences
-        return inner
-            .replace("\\n", "\n")
-            .replace("\\\"", "\"")
-            .replace("\\'", "'")
-            .replace("\\$", "$")
-            .replace("\\\\", "\\");
+        return unescape_env_value(&value[1..value.len() - 1]);
     }
 
     value.to_string()
 }
 
-fn quote_env_value(value: &str) -> String {
+/// Undo `double_quote_env_value` in a single pass, so an escaped backslash
+/// followed by `n` stays a backslash and an `n`. Unknown escapes are kept.
+fn unescape_env_value(inner: &str) -> String {
+    let mut result = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some(escaped @ ('"' | '\'' | '$' | '\\')) => result.push(escaped),
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Quote value for .env file if needed
+pub(crate) fn quote_env_value(value: &str) -> String {
     // If value contains spaces, quotes, or special chars, wrap in quotes
     if value.contains(' ')
         || value.contains('"')
         || value.contains('\'')
         || value.contains('$')
         || value.contains('\n')
+        || value.contains('\r')
         || value.contains('#')
+        // Import trims unquoted values
+        || value.trim() != value
     {
-        let escaped = value
-            .replace('\\', "\\\\")
-            .replace('"', "\\\"")
-            .replace('$', "\\$")
-            .replace('\n', "\\n");
-        format!("\"{}\"", escaped)
+        double_quote_env_value(value)
     } else {
         value.to_string()
     }
 }
 
+/// Wrap a value in double quotes, escaping what the shell would expand
+fn double_quote_env_value(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "\\$")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+    format!("\"{}\"", escaped)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -177,6 +318,33 @@ mod tests {
         assert_eq!(parse_env_line("invalid line"), None);
     }
 
+    #[test]
+    fn test_parse_rename_map() {
+        let renames = parse_rename_map(
+            "# vendor names\nSTRIPE_SK = STRIPE_SECRET_KEY\n\nDB_PW=prod/DB_PASSWORD\n",
+        )
+        .unwrap();
+        assert_eq!(renames["STRIPE_SK"], "STRIPE_SECRET_KEY");
+        assert_eq!(renames["DB_PW"], "prod/DB_PASSWORD");
+        assert_eq!(renames.len(), 2);
+
+        assert!(parse_rename_map("NO_TARGET=\n").is_err());
+        assert!(parse_rename_map("A=B\nA=C\n").is_err());
+        let err = parse_rename_map("OK=FINE\nBAD=has space\n").unwrap_err();
+        assert!(format!("{:#}", err).contains("line 2"));
+    }
+
+    #[test]
+    fn test_duplicate_groups() {
+        let hashes = vec![
+            ("A".to_string(), Sha256::digest(b"same")),
+            ("B".to_string(), Sha256::digest(b"unique")),
+            ("C".to_string(), Sha256::digest(b"same")),
+        ];
+
+        assert_eq!(duplicate_groups(&hashes), vec![vec!["A", "C"]]);
+    }
+
     #[test]
     fn test_unquote_env_value() {
         assert_eq!(unquote_env_value("simple"), "simple");
@@ -186,6 +354,57 @@ mod tests {
         assert_eq!(unquote_env_value("\"with\\\"quote\""), "with\"quote");
     }
 
+    #[test]
+    fn test_export_import_round_trip() {
+        let mut values: Vec<String> = [
+            "a=b",
+            "==",
+            "trailing=",
+            "#not a comment",
+            "x # y",
+            "multi\nline\n",
+            "crlf\r\nend",
+            "literal \\n not a newline",
+            "back\\slash\\",
+            "\\\"",
+            "\"",
+            "'",
+            "\"quoted\"",
+            "'single'",
+            "$HOME ${X} `cmd`",
+            "\tleading tab",
+            "trailing space ",
+            "unicode é ✓",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        // Random values over an alphabet of characters that need care
+        let alphabet = [
+            'a', 'n', '=', '#', '\n', '\r', '"', '\'', '\\', '$', ' ', '\t', 'é',
+        ];
+        for _ in 0..500 {
+            let len = rand::random::<usize>() % 12 + 1;
+            values.push(
+                (0..len)
+                    .map(|_| alphabet[rand::random::<usize>() % alphabet.len()])
+                    .collect(),
+            );
+        }
+
+        for value in &values {
+            for line in [
+                format!("KEY={}", quote_env_value(value)),
+                format!("export KEY={}", double_quote_env_value(value)),
+            ] {
+                assert!(!line.contains('\n'), "{:?} spans lines", line);
+                let parsed = parse_env_line(line.trim()).map(|(_, v)| v);
+                assert_eq!(parsed.as_ref(), Some(value), "via {:?}", line);
+            }
+        }
+    }
+
     #[test]
     fn test_quote_env_value() {
         assert_eq!(quote_env_value("simple"), "simple");
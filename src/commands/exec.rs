@@ -1,15 +1,49 @@
+use crate::commands::env::{parse_env_line, quote_env_value};
+use crate::config::Config;
+use crate::env_fd;
 use crate::error::Error;
 use crate::sanitize;
-use crate::vault::{secret_name_only, Vault};
+use crate::signals;
+use crate::vault::{secret_name_only, with_default_bucket, Vault};
 use anyhow::{Context, Result};
 use once_cell::sync::Lazy;
 use regex::Regex;
+use secrecy::zeroize::Zeroize;
+use secrecy::ExposeSecret;
+use serde_json::json;
 use std::collections::HashMap;
-use std::process::Command;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+/// Prefix of the variables carrying `{{NAME}}` values into `sh -c` commands
+const TEMPLATE_ENV_PREFIX: &str = "SECRET_AGENT_TPL_";
+
+/// The shell or command couldn't be started at all, as opposed to running
+/// and failing. Exits 127, like a shell's "command not found".
+#[derive(Debug, thiserror::Error)]
+#[error("could not start '{program}': {reason}")]
+pub struct SpawnError {
+    program: String,
+    reason: std::io::Error,
+}
+
+impl SpawnError {
+    pub const EXIT_CODE: i32 = 127;
+}
+
+/// Holds the command's exit code for the `--on-exit` hook
+const EXIT_CODE_VAR: &str = "SECRET_AGENT_EXIT_CODE";
+
+/// Points the child at the `--write-env-file` dotenv file
+const ENV_FILE_VAR: &str = "SECRET_AGENT_ENV_FILE";
 
 static PLACEHOLDER_RE: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"\{\{(\w+)\}\}").expect("invalid placeholder regex"));
 
+static DOTENV_REF_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\$\{(\w+)\}").expect("invalid dotenv reference regex"));
+
 /// Parse an env spec like "SECRET_NAME", "bucket/SECRET_NAME", or "bucket/SECRET_NAME:ENV_VAR"
 /// Returns (secret_path, env_var_name)
 /// - "API_KEY" -> ("API_KEY", "API_KEY")
@@ -42,52 +76,294 @@ fn shell_quote(s: &str) -> String {
     format!("'{}'", s.replace('\'', "'\\''"))
 }
 
-pub fn run(env_secrets: &[String], command_parts: &[String]) -> Result<i32> {
-    let vault = Vault::open().context("failed to open vault")?;
+/// Options for `exec` beyond the command itself
+#[derive(Default)]
+pub struct ExecOptions {
+    /// `--env` specs: SECRET, bucket/SECRET, or SECRET:VAR
+    pub env_secrets: Vec<String>,
+    /// Bucket prepended to `--env` specs that don't name one
+    pub bucket: Option<String>,
+    /// `--secret-arg` specs (N=NAME) injecting a secret into argv[N]
+    pub secret_args: Vec<String>,
+    /// Buckets whose secrets are all injected, named without the bucket prefix
+    pub env_buckets: Vec<String>,
+    /// JSON array of argv to run instead of the positional command
+    pub args_file: Option<String>,
+    /// Run the command directly instead of through `sh -c`
+    pub no_shell: bool,
+    /// Shell to run the command with instead of `sh`
+    pub shell: Option<String>,
+    /// .env file of non-secret variables to set for the child
+    pub dotenv: Option<String>,
+    /// Resolve `${VAR}` references in dotenv values
+    pub dotenv_expand: bool,
+    /// Also write the sanitized output to this file
+    pub tee: Option<String>,
+    /// Append to the tee file instead of truncating it
+    pub tee_append: bool,
+    /// Emit NDJSON lifecycle events on stderr
+    pub events: bool,
+    /// Remove ANSI escape sequences from the child's output
+    pub strip_ansi: bool,
+    /// Host environment variables whose values are redacted from output
+    pub redact_env: Vec<String>,
+    /// File to render `template` into for the duration of the command
+    pub render_to: Option<String>,
+    /// Template with `{{NAME}}` placeholders for `render_to`
+    pub template: Option<String>,
+    /// Also write the injected env vars to this dotenv file for the command's duration
+    pub write_env_file: Option<String>,
+    /// Pass `--env` secrets through an inherited pipe instead of the environment
+    pub env_fd: bool,
+    /// Shell command run after the child exits, without any secrets
+    pub on_exit: Option<String>,
+    /// Print the resolved command and env with secrets masked instead of running it
+    pub dry_run: bool,
+    /// Don't report why a failed command stopped
+    pub quiet: bool,
+}
 
-    // Build the command string, properly quoting arguments that need it
-    let command = command_parts
-        .iter()
-        .map(|s| shell_quote(s))
-        .collect::<Vec<_>>()
-        .join(" ");
+pub fn run(opts: &ExecOptions, command_parts: &[String]) -> Result<i32> {
+    let config = Config::load().context("failed to load config")?;
+    let vault = Vault::open().context("failed to open vault")?;
 
     // Collect secrets needed for --env flags
     let mut env_vars: HashMap<String, String> = HashMap::new();
     let mut all_secrets: HashMap<String, String> = HashMap::new();
 
-    for spec in env_secrets {
+    for spec in &opts.env_secrets {
         let (secret_name, env_var_name) = parse_env_spec(spec);
-        let value = vault.get(&secret_name).map_err(|e| match e {
-            Error::SecretNotFound(_) => {
-                anyhow::anyhow!("secret '{}' not found in vault", secret_name)
-            }
-            _ => anyhow::anyhow!("failed to get secret '{}': {}", secret_name, e),
-        })?;
+        let secret_name = with_default_bucket(secret_name, opts.bucket.as_deref());
+        let value = fetch_secret(&vault, &secret_name)?;
         env_vars.insert(env_var_name, value.clone());
         all_secrets.insert(secret_name, value);
     }
 
-    // Parse placeholders from command (for backwards compatibility)
-    let placeholder_names = parse_placeholders(&command);
+    // Inject every secret in each --env-bucket; explicit --env specs win on collision
+    for bucket in &opts.env_buckets {
+        let secrets = vault
+            .list_by_bucket(Some(bucket))
+            .with_context(|| format!("failed to list bucket '{}'", bucket))?;
+        if secrets.is_empty() {
+            eprintln!("warning: bucket '{}' has no secrets", bucket);
+        }
 
-    for name in &placeholder_names {
-        if !all_secrets.contains_key(name) {
-            let value = vault.get(name).map_err(|e| match e {
-                Error::SecretNotFound(_) => {
-                    anyhow::anyhow!("secret '{}' not found in vault", name)
-                }
-                _ => anyhow::anyhow!("failed to get secret '{}': {}", name, e),
-            })?;
-            all_secrets.insert(name.clone(), value);
+        for secret in secrets {
+            let env_var_name = secret_name_only(&secret.name).to_string();
+            if env_vars.contains_key(&env_var_name) {
+                eprintln!(
+                    "warning: {} from bucket '{}' conflicts with an already injected {}; keeping the earlier value",
+                    secret.name, bucket, env_var_name
+                );
+                continue;
+            }
+            let value = fetch_secret(&vault, &secret.name)?;
+            env_vars.insert(env_var_name, value.clone());
+            all_secrets.insert(secret.name, value);
+        }
+    }
+
+    // Non-secret variables from --dotenv, optionally expanded against the environment
+    let dotenv_vars = match &opts.dotenv {
+        Some(path) => {
+            let vars = read_dotenv(path)?;
+            if opts.dotenv_expand {
+                expand_dotenv(&vars, &env_vars)?
+            } else {
+                vars
+            }
+        }
+        None => Vec::new(),
+    };
+
+    // Direct argv (args file or --no-shell): each argument is injected individually
+    let direct_args = match &opts.args_file {
+        Some(path) => Some(read_args_file(path)?),
+        None if opts.no_shell || !opts.secret_args.is_empty() => Some(command_parts.to_vec()),
+        None => None,
+    };
+
+    // The templated shell command holds plaintext; kept until the child is spawned
+    let mut injected_command = String::new();
+    // The command as --dry-run shows it, with [REDACTED:NAME] for each secret
+    let masked_command;
+
+    let mut cmd = if let Some(args) = direct_args {
+        for arg in &args {
+            load_placeholder_secrets(&vault, arg, &mut all_secrets)?;
+        }
+        let mut injected_args = inject_args(&args, &all_secrets);
+        let mut masked_args = inject_args(&args, &masked(&all_secrets));
+        // After templating, so a secret value is never itself expanded
+        for spec in &opts.secret_args {
+            let (index, name) = parse_secret_arg(spec)?;
+            let value = fetch_secret(&vault, &name)?;
+            replace_arg(&mut injected_args, index, &value)?;
+            replace_arg(&mut masked_args, index, &redaction(&name))?;
+            all_secrets.insert(name, value);
+        }
+        masked_command = masked_args
+            .iter()
+            .map(|s| shell_quote(s))
+            .collect::<Vec<_>>()
+            .join(" ");
+        direct_command(&injected_args)?
+    } else {
+        // Build the command string, properly quoting arguments that need it
+        let command = command_parts
+            .iter()
+            .map(|s| shell_quote(s))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        // Parse placeholders from command (for backwards compatibility)
+        load_placeholder_secrets(&vault, &command, &mut all_secrets)?;
+        masked_command = inject_secrets(&command, &masked(&all_secrets));
+
+        // {{PLACEHOLDER}}s become "$VAR" references so values stay out of the shell's argv
+        let template_env;
+        (injected_command, template_env) = template_env_refs(&command, &all_secrets);
+        let shell = opts.shell.as_deref().unwrap_or("sh");
+        if !template_env.is_empty() && !is_posix_shell(shell) {
+            anyhow::bail!(
+                "{{{{NAME}}}} placeholders need a POSIX shell like sh, bash or zsh, not '{}'; \
+                 use --no-shell, --args-file or --env instead",
+                shell
+            );
+        }
+        let mut cmd = shell_command(shell, &injected_command);
+        cmd.envs(template_env);
+        cmd
+    };
+
+    if opts.dry_run {
+        print_dry_run(&masked_command, &env_vars, &dotenv_vars, &all_secrets);
+        return Ok(0);
+    }
+
+    // Dotenv values go in first so explicitly injected secrets take precedence
+    cmd.envs(dotenv_vars);
+
+    // Removed when dropped, after the command has exited
+    let _rendered = match (&opts.template, &opts.render_to) {
+        (Some(template), Some(path)) => {
+            let template = std::fs::read_to_string(template)
+                .with_context(|| format!("failed to read template: {}", template))?;
+            load_placeholder_secrets(&vault, &template, &mut all_secrets)?;
+            let content = inject_args(&[template], &all_secrets).remove(0);
+            Some(RenderedFile::create(Path::new(path), &content)?)
+        }
+        _ => None,
+    };
+
+    // Also removed after the command exits
+    let _env_file = match &opts.write_env_file {
+        Some(path) => {
+            cmd.env(ENV_FILE_VAR, path);
+            Some(RenderedFile::create(
+                Path::new(path),
+                &dotenv_content(&env_vars),
+            )?)
+        }
+        None => None,
+    };
+
+    // Inherited host values to redact; added only after templating so they
+    // can never be injected through {{NAME}}
+    for var_name in &opts.redact_env {
+        if let Ok(value) = std::env::var(var_name) {
+            sanitize::remember(var_name, &value);
+            all_secrets.entry(var_name.clone()).or_insert(value);
         }
     }
 
-    // Inject secrets into command string (for {{PLACEHOLDER}} syntax)
-    let injected_command = inject_secrets(&command, &all_secrets);
+    verbose!(
+        "loaded {} secrets ({} as env vars)",
+        all_secrets.len(),
+        env_vars.len()
+    );
 
     // Execute with env vars
-    execute_command(&injected_command, &env_vars, &all_secrets)
+    let result = execute_command(cmd, opts, &config.exec.deny_env, &env_vars, &all_secrets);
+    injected_command.zeroize();
+    result
+}
+
+/// How a secret appears in sanitized output
+fn redaction(name: &str) -> String {
+    format!("[REDACTED:{}]", name)
+}
+
+/// Each secret's name mapped to its redaction marker
+fn masked(secrets: &HashMap<String, String>) -> HashMap<String, String> {
+    secrets
+        .keys()
+        .map(|name| (name.clone(), redaction(name)))
+        .collect()
+}
+
+/// `--dry-run` output: the command and the variables it would get, with
+/// every secret masked. Dotenv values aren't secrets and are shown as is.
+fn print_dry_run(
+    command: &str,
+    env_vars: &HashMap<String, String>,
+    dotenv_vars: &[(String, String)],
+    secrets: &HashMap<String, String>,
+) {
+    println!("Command: {}", command);
+
+    let mut names: Vec<&String> = env_vars.keys().collect();
+    names.sort();
+    for name in names {
+        println!("Env: {}={}", name, redaction(name));
+    }
+    for (name, value) in dotenv_vars {
+        if !env_vars.contains_key(name) {
+            // --dotenv-expand may have substituted vault secrets into it
+            println!("Env: {}={}", name, sanitize::sanitize(value, secrets));
+        }
+    }
+}
+
+fn fetch_secret(vault: &Vault, name: &str) -> Result<String> {
+    let value = vault.get(name).map_err(|e| match e {
+        Error::SecretNotFound(_) => anyhow::anyhow!("secret '{}' not found in vault", name),
+        _ => anyhow::anyhow!("failed to get secret '{}': {}", name, e),
+    })?;
+    // Exposed here because templating, env injection and the sanitizer all
+    // need the plaintext
+    let value = value.expose_secret().to_string();
+    sanitize::remember(name, &value);
+    Ok(value)
+}
+
+/// Load any secrets referenced as {{NAME}} in `text` that aren't loaded yet
+fn load_placeholder_secrets(
+    vault: &Vault,
+    text: &str,
+    secrets: &mut HashMap<String, String>,
+) -> Result<()> {
+    for name in parse_placeholders(text) {
+        if let std::collections::hash_map::Entry::Vacant(entry) = secrets.entry(name) {
+            let value = fetch_secret(vault, entry.key())?;
+            entry.insert(value);
+        }
+    }
+    Ok(())
+}
+
+/// Read a JSON array of argument strings, e.g. `["curl", "-H", "Auth: {{API_KEY}}"]`
+fn read_args_file(path: &str) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read args file: {}", path))?;
+    let args: Vec<String> = serde_json::from_str(&content)
+        .with_context(|| format!("args file must be a JSON array of strings: {}", path))?;
+
+    if args.is_empty() {
+        anyhow::bail!("args file is empty: {}", path);
+    }
+
+    Ok(args)
 }
 
 fn parse_placeholders(command: &str) -> Vec<String> {
@@ -126,20 +402,352 @@ fn inject_secrets(command: &str, secrets: &HashMap<String, String>) -> String {
     result
 }
 
-fn execute_command(
+/// Quoting context at a point in a shell command
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ShellQuote {
+    None,
+    Single,
+    Double,
+}
+
+impl ShellQuote {
+    /// The context after reading `text` starting in this one
+    fn after(self, text: &str) -> Self {
+        let mut quote = self;
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            quote = match (quote, c) {
+                (ShellQuote::None | ShellQuote::Double, '\\') => {
+                    chars.next();
+                    quote
+                }
+                (ShellQuote::None, '\'') => ShellQuote::Single,
+                (ShellQuote::None, '"') => ShellQuote::Double,
+                (ShellQuote::Single, '\'') | (ShellQuote::Double, '"') => ShellQuote::None,
+                _ => quote,
+            };
+        }
+        quote
+    }
+}
+
+/// Shells that don't understand the `"${VAR}"` references placeholders
+/// become, or the POSIX quoting around them
+const NON_POSIX_SHELLS: &[&str] = &["fish", "pwsh", "powershell", "cmd", "nu", "elvish", "xonsh"];
+
+/// Whether `shell` (a name or path) can run a command from `template_env_refs`
+fn is_posix_shell(shell: &str) -> bool {
+    let name = Path::new(shell)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(shell);
+    !NON_POSIX_SHELLS.contains(&name.to_ascii_lowercase().as_str())
+}
+
+/// Rewrite `{{NAME}}` placeholders in a shell command into references to
+/// environment variables, so values never appear in the shell's argv (which
+/// `ps` shows to other users). Returns the command and the variables to set.
+/// A secret whose name can't be an environment variable name is substituted
+/// literally instead.
+fn template_env_refs(
     command: &str,
+    secrets: &HashMap<String, String>,
+) -> (String, Vec<(String, String)>) {
+    let mut result = String::with_capacity(command.len());
+    let mut env: Vec<(String, String)> = Vec::new();
+    let mut quote = ShellQuote::None;
+    let mut last = 0;
+
+    for cap in PLACEHOLDER_RE.captures_iter(command) {
+        let placeholder = cap.get(0).expect("whole match");
+        let before = &command[last..placeholder.start()];
+        quote = quote.after(before);
+        result.push_str(before);
+        last = placeholder.end();
+
+        let name = &cap[1];
+        let Some(value) = secrets.get(name) else {
+            result.push_str(placeholder.as_str());
+            continue;
+        };
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            result.push_str(&inject_secrets(placeholder.as_str(), secrets));
+            continue;
+        }
+
+        let var = format!("{}{}", TEMPLATE_ENV_PREFIX, name);
+        result.push_str(&match quote {
+            ShellQuote::None => format!("\"${{{}}}\"", var),
+            ShellQuote::Double => format!("${{{}}}", var),
+            ShellQuote::Single => format!("'\"${{{}}}\"'", var),
+        });
+        if !env.iter().any(|(existing, _)| *existing == var) {
+            env.push((var, value.clone()));
+        }
+    }
+
+    result.push_str(&command[last..]);
+    (result, env)
+}
+
+/// `NAME=value` lines for `--write-env-file`, sorted by name
+fn dotenv_content(env_vars: &HashMap<String, String>) -> String {
+    let mut names: Vec<&String> = env_vars.keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .map(|name| format!("{}={}\n", name, quote_env_value(&env_vars[name])))
+        .collect()
+}
+
+/// A file holding rendered secrets, overwritten and deleted on drop
+struct RenderedFile {
+    path: PathBuf,
+    len: usize,
+}
+
+impl RenderedFile {
+    /// Write `content` to a new file only we can read. An existing file is
+    /// never replaced, so a planted symlink can't redirect the secrets.
+    fn create(path: &Path, content: &str) -> Result<Self> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        let rendered = Self {
+            path: path.to_path_buf(),
+            len: content.len(),
+        };
+        file.write_all(content.as_bytes())
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(rendered)
+    }
+}
+
+impl Drop for RenderedFile {
+    fn drop(&mut self) {
+        // Overwrite before unlinking so the secrets don't linger in freed blocks
+        if let Ok(mut file) = std::fs::OpenOptions::new().write(true).open(&self.path) {
+            let _ = file.write_all(&vec![0; self.len]);
+            let _ = file.sync_all();
+        }
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            eprintln!("warning: failed to remove {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Read NAME=value pairs from a .env file, in file order
+fn read_dotenv(path: &str) -> Result<Vec<(String, String)>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read dotenv file: {}", path))?;
+
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(parse_env_line)
+        .collect())
+}
+
+/// Resolve `${VAR}` references in dotenv values.
+/// Lookup order: injected vault secrets, other dotenv entries, then the host environment.
+/// Undefined references expand to an empty string; reference cycles are an error.
+fn expand_dotenv(
+    vars: &[(String, String)],
+    env_vars: &HashMap<String, String>,
+) -> Result<Vec<(String, String)>> {
+    let raw: HashMap<&str, &str> = vars
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    let mut resolved: HashMap<String, String> = HashMap::new();
+
+    vars.iter()
+        .map(|(name, _)| {
+            let value = resolve_dotenv_var(name, &raw, env_vars, &mut resolved, &mut Vec::new())?;
+            Ok((name.clone(), value))
+        })
+        .collect()
+}
+
+fn resolve_dotenv_var(
+    name: &str,
+    raw: &HashMap<&str, &str>,
+    env_vars: &HashMap<String, String>,
+    resolved: &mut HashMap<String, String>,
+    stack: &mut Vec<String>,
+) -> Result<String> {
+    if let Some(value) = resolved.get(name) {
+        return Ok(value.clone());
+    }
+    if stack.iter().any(|n| n == name) {
+        stack.push(name.to_string());
+        anyhow::bail!("dotenv reference cycle: {}", stack.join(" -> "));
+    }
+
+    let Some(template) = raw.get(name) else {
+        return Ok(String::new());
+    };
+
+    stack.push(name.to_string());
+    let mut value = String::new();
+    let mut last = 0;
+    for cap in DOTENV_REF_RE.captures_iter(template) {
+        let whole = cap.get(0).expect("capture 0 always exists");
+        let reference = &cap[1];
+        value.push_str(&template[last..whole.start()]);
+
+        if let Some(secret) = env_vars.get(reference) {
+            value.push_str(secret);
+        } else if raw.contains_key(reference) {
+            value.push_str(&resolve_dotenv_var(
+                reference, raw, env_vars, resolved, stack,
+            )?);
+        } else {
+            value.push_str(&std::env::var(reference).unwrap_or_default());
+        }
+        last = whole.end();
+    }
+    value.push_str(&template[last..]);
+    stack.pop();
+
+    resolved.insert(name.to_string(), value.clone());
+    Ok(value)
+}
+
+/// Inject secrets into each argument individually (no shell quoting involved)
+fn inject_args(args: &[String], secrets: &HashMap<String, String>) -> Vec<String> {
+    args.iter()
+        .map(|arg| {
+            let mut result = arg.clone();
+            for (name, value) in secrets {
+                result = result.replace(&format!("{{{{{}}}}}", name), value);
+            }
+            result
+        })
+        .collect()
+}
+
+/// Token in an argument that `--secret-arg` replaces instead of the whole argument
+const SECRET_ARG_TOKEN: &str = "__SECRET__";
+
+/// Parse a `--secret-arg` spec like "3=DB_PASS"
+fn parse_secret_arg(spec: &str) -> Result<(usize, String)> {
+    let (index, name) = spec
+        .split_once('=')
+        .with_context(|| format!("invalid --secret-arg '{}': expected N=NAME", spec))?;
+    let index = index
+        .parse()
+        .with_context(|| format!("invalid --secret-arg '{}': N must be a number", spec))?;
+    if name.is_empty() {
+        anyhow::bail!("invalid --secret-arg '{}': missing secret name", spec);
+    }
+    Ok((index, name.to_string()))
+}
+
+/// Put `value` into argv[index]: in place of `__SECRET__` if the argument
+/// contains it (e.g. `--password=__SECRET__`), otherwise as the whole argument
+fn replace_arg(argv: &mut [String], index: usize, value: &str) -> Result<()> {
+    let len = argv.len();
+    let arg = argv.get_mut(index).with_context(|| {
+        format!(
+            "--secret-arg position {} is out of range (command has {} arguments)",
+            index, len
+        )
+    })?;
+
+    if arg.contains(SECRET_ARG_TOKEN) {
+        *arg = arg.replace(SECRET_ARG_TOKEN, value);
+    } else {
+        *arg = value.to_string();
+    }
+    Ok(())
+}
+
+fn shell_command(shell: &str, command: &str) -> Command {
+    let mut cmd = Command::new(shell);
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+/// Run the first argument as the program and the rest as literal argv
+fn direct_command(argv: &[String]) -> Result<Command> {
+    let (program, args) = argv.split_first().context("no command given")?;
+    let mut cmd = Command::new(program);
+    cmd.args(args);
+    Ok(cmd)
+}
+
+fn execute_command(
+    mut cmd: Command,
+    opts: &ExecOptions,
+    deny_env: &[String],
     env_vars: &HashMap<String, String>,
     secrets: &HashMap<String, String>,
 ) -> Result<i32> {
-    let mut cmd = Command::new("sh");
-    cmd.arg("-c").arg(command);
+    // Strip denylisted host variables (explicitly injected secrets still win)
+    for var_name in deny_env {
+        cmd.env_remove(var_name);
+    }
 
-    // Inject environment variables
-    for (var_name, value) in env_vars {
-        cmd.env(var_name, value);
+    // Inject environment variables, or hand them over through a pipe
+    let env_fd = if opts.env_fd {
+        Some(env_fd::attach(&mut cmd, env_vars).context("failed to set up --env-fd pipe")?)
+    } else {
+        for (var_name, value) in env_vars {
+            cmd.env(var_name, value);
+        }
+        None
+    };
+
+    // Never inherit stdout or stderr: a shell that fails to parse the command
+    // echoes it, injected secrets included, and only piped output is sanitized
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    signals::isolate(&mut cmd);
+
+    // Before spawning: the child ignores the terminal's Ctrl-C from its own
+    // process group, so a signal that killed us first would orphan it
+    let forwarder = signals::catch().context("failed to install signal handlers")?;
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(reason) => {
+            forwarder.finish();
+            return Err(SpawnError {
+                program: cmd.get_program().to_string_lossy().into_owned(),
+                reason,
+            }
+            .into());
+        }
+    };
+    forwarder.attach(child.id());
+    if let Some(env_fd) = env_fd {
+        env_fd.send();
+    }
+
+    if opts.events {
+        let mut names: Vec<&String> = env_vars.keys().chain(secrets.keys()).collect();
+        names.sort();
+        names.dedup();
+        emit_event(json!({ "event": "start", "pid": child.id(), "secrets": names }));
     }
 
-    let output = cmd.output().context("failed to execute command")?;
+    // Drain both pipes concurrently so a chatty child can't deadlock on a full pipe
+    let stdout_reader = read_pipe(child.stdout.take());
+    let stderr_reader = read_pipe(child.stderr.take());
+
+    let status = child.wait().context("failed to wait for command")?;
+    let received_signal = forwarder.finish();
+    let stdout_bytes = stdout_reader.join().unwrap_or_default();
+    let stderr_bytes = stderr_reader.join().unwrap_or_default();
 
     // Combine all secret values for sanitization
     let mut all_secret_values = secrets.clone();
@@ -149,19 +757,144 @@ fn execute_command(
     }
 
     // Sanitize and print stdout
-    let stdout = sanitize::sanitize_bytes(&output.stdout, &all_secret_values);
+    let (mut stdout, stdout_redactions) =
+        sanitize::sanitize_bytes(&stdout_bytes, &all_secret_values);
+    if opts.strip_ansi {
+        stdout = sanitize::strip_ansi(&stdout);
+    }
     if !stdout.is_empty() {
         print!("{}", stdout);
     }
 
     // Sanitize and print stderr
-    let stderr = sanitize::sanitize_bytes(&output.stderr, &all_secret_values);
+    let (mut stderr, stderr_redactions) =
+        sanitize::sanitize_bytes(&stderr_bytes, &all_secret_values);
+    if opts.strip_ansi {
+        stderr = sanitize::strip_ansi(&stderr);
+    }
     if !stderr.is_empty() {
         eprint!("{}", stderr);
     }
 
-    // Return exit code
-    Ok(output.status.code().unwrap_or(1))
+    // Save a copy of the (already sanitized) output
+    if let Some(path) = &opts.tee {
+        write_tee(path, opts.tee_append, &stdout, &stderr)?;
+    }
+
+    if !opts.quiet && !status.success() {
+        eprintln!("command {}", termination_reason(&status));
+    }
+
+    // We were interrupted: report it the conventional way once the child is done
+    let code = match received_signal {
+        Some(signal) => signals::exit_code_for(signal),
+        None => exit_code(&status),
+    };
+
+    if let Some(hook) = &opts.on_exit {
+        run_exit_hook(hook, code, deny_env, &all_secret_values);
+    }
+
+    if opts.events {
+        emit_event(json!({ "event": "redaction", "count": stdout_redactions + stderr_redactions }));
+        emit_event(json!({ "event": "exit", "code": code }));
+    }
+
+    Ok(code)
+}
+
+/// Run the `--on-exit` hook with the child's exit code in
+/// SECRET_AGENT_EXIT_CODE. It gets the host environment (minus denylisted
+/// variables) but none of the injected secrets, and its output is sanitized
+/// like the child's. A failing hook is reported but doesn't change our exit code.
+fn run_exit_hook(hook: &str, code: i32, deny_env: &[String], secrets: &HashMap<String, String>) {
+    let mut cmd = shell_command("sh", hook);
+    for var_name in deny_env {
+        cmd.env_remove(var_name);
+    }
+    cmd.env(EXIT_CODE_VAR, code.to_string())
+        .stdin(Stdio::null());
+
+    let output = match cmd.output() {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("warning: failed to run --on-exit hook: {}", e);
+            return;
+        }
+    };
+
+    let (stdout, _) = sanitize::sanitize_bytes(&output.stdout, secrets);
+    print!("{}", stdout);
+    let (stderr, _) = sanitize::sanitize_bytes(&output.stderr, secrets);
+    eprint!("{}", stderr);
+
+    if !output.status.success() {
+        eprintln!(
+            "warning: --on-exit hook {}",
+            termination_reason(&output.status)
+        );
+    }
+}
+
+/// Write one NDJSON event line to stderr
+fn emit_event(event: serde_json::Value) {
+    eprintln!("{}", event);
+}
+
+/// Write sanitized stdout followed by sanitized stderr to the tee file
+fn write_tee(path: &str, append: bool, stdout: &str, stderr: &str) -> Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .with_context(|| format!("failed to open tee file: {}", path))?;
+
+    file.write_all(stdout.as_bytes())
+        .and_then(|_| file.write_all(stderr.as_bytes()))
+        .with_context(|| format!("failed to write tee file: {}", path))
+}
+
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// Why the child stopped: "exited with code N" or "killed by SIGTERM"
+fn termination_reason(status: &ExitStatus) -> String {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            let core = if status.core_dumped() {
+                " (core dumped)"
+            } else {
+                ""
+            };
+            return format!("killed by {}{}", signals::name(signal), core);
+        }
+    }
+    match status.code() {
+        Some(code) => format!("exited with code {}", code),
+        None => "exited abnormally".to_string(),
+    }
+}
+
+fn exit_code(status: &ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return signals::exit_code_for(signal);
+        }
+    }
+    status.code().unwrap_or(1)
 }
 
 #[cfg(test)]
@@ -189,6 +922,13 @@ mod tests {
         assert_eq!(var, "API_KEY"); // env var is just the name, not bucket/name
     }
 
+    #[test]
+    fn test_parse_env_spec_with_nested_bucket() {
+        let (secret, var) = parse_env_spec("company/prod/service/KEY");
+        assert_eq!(secret, "company/prod/service/KEY");
+        assert_eq!(var, "KEY");
+    }
+
     #[test]
     fn test_parse_env_spec_with_bucket_renamed() {
         let (secret, var) = parse_env_spec("prod/SECRET:MY_VAR");
@@ -196,6 +936,42 @@ mod tests {
         assert_eq!(var, "MY_VAR");
     }
 
+    #[test]
+    fn test_parse_secret_arg() {
+        assert_eq!(
+            parse_secret_arg("3=DB_PASS").unwrap(),
+            (3, "DB_PASS".to_string())
+        );
+        assert_eq!(
+            parse_secret_arg("1=prod/DB_PASS").unwrap(),
+            (1, "prod/DB_PASS".to_string())
+        );
+        assert!(parse_secret_arg("DB_PASS").is_err());
+        assert!(parse_secret_arg("x=DB_PASS").is_err());
+        assert!(parse_secret_arg("2=").is_err());
+    }
+
+    #[test]
+    fn test_replace_arg() {
+        let mut argv: Vec<String> = [
+            "mysql",
+            "-u",
+            "root",
+            "PLACEHOLDER",
+            "--password=__SECRET__",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        replace_arg(&mut argv, 3, "whole").unwrap();
+        replace_arg(&mut argv, 4, "s3cr3t").unwrap();
+
+        assert_eq!(argv[3], "whole");
+        assert_eq!(argv[4], "--password=s3cr3t");
+        assert!(replace_arg(&mut argv, 5, "x").is_err());
+    }
+
     #[test]
     fn test_parse_placeholders() {
         let cmd = "curl -H 'Auth: {{API_KEY}}' --data '{{DATA}}'";
@@ -229,6 +1005,147 @@ mod tests {
         assert_eq!(result, "curl https://example.com/api -H 'Auth: sk-12345'");
     }
 
+    #[test]
+    fn test_template_env_refs_by_quote_context() {
+        let mut secrets = HashMap::new();
+        secrets.insert("API_KEY".to_string(), "sk-12345".to_string());
+
+        let (command, env) = template_env_refs(
+            "echo {{API_KEY}} \"x{{API_KEY}}\" 'Auth: {{API_KEY}}'",
+            &secrets,
+        );
+
+        assert_eq!(
+            command,
+            "echo \"${SECRET_AGENT_TPL_API_KEY}\" \"x${SECRET_AGENT_TPL_API_KEY}\" \
+             'Auth: '\"${SECRET_AGENT_TPL_API_KEY}\"''"
+        );
+        assert_eq!(
+            env,
+            [(
+                "SECRET_AGENT_TPL_API_KEY".to_string(),
+                "sk-12345".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_template_env_refs_quoted_args() {
+        // What `exec sh -c 'echo "{{KEY}}"'` builds: the placeholder sits in
+        // double quotes inside a single-quoted argument
+        let command = ["sh", "-c", "echo \"{{KEY}}\" it's"]
+            .iter()
+            .map(|s| shell_quote(s))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut secrets = HashMap::new();
+        secrets.insert("KEY".to_string(), "my-secret-value".to_string());
+
+        let (rewritten, _) = template_env_refs(&command, &secrets);
+
+        assert_eq!(
+            rewritten,
+            "sh -c 'echo \"'\"${SECRET_AGENT_TPL_KEY}\"'\" it'\\''s'"
+        );
+        assert!(!rewritten.contains("my-secret-value"));
+    }
+
+    #[test]
+    fn test_is_posix_shell() {
+        assert!(is_posix_shell("sh"));
+        assert!(is_posix_shell("/bin/bash"));
+        assert!(is_posix_shell("/usr/local/bin/zsh"));
+        assert!(!is_posix_shell("fish"));
+        assert!(!is_posix_shell("/usr/bin/fish"));
+        assert!(!is_posix_shell("pwsh"));
+        assert!(!is_posix_shell("PowerShell.exe"));
+    }
+
+    #[test]
+    fn test_template_env_refs_literal_fallback() {
+        let mut secrets = HashMap::new();
+        secrets.insert("clé".to_string(), "value".to_string());
+
+        let (command, env) = template_env_refs("echo {{clé}} {{MISSING}}", &secrets);
+
+        assert_eq!(command, "echo value {{MISSING}}");
+        assert!(env.is_empty());
+    }
+
+    #[test]
+    fn test_rendered_file_is_private_and_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.conf");
+
+        let rendered = RenderedFile::create(&path, "password=hunter2\n").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "password=hunter2\n"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // Never replaces an existing file
+        assert!(RenderedFile::create(&path, "other").is_err());
+
+        drop(rendered);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_inject_args() {
+        let mut secrets = HashMap::new();
+        secrets.insert("API_KEY".to_string(), "sk-12345".to_string());
+
+        let args = [
+            "curl".to_string(),
+            "-H".to_string(),
+            "Auth: {{API_KEY}}; rm -rf /".to_string(),
+        ];
+        let result = inject_args(&args, &secrets);
+
+        assert_eq!(result, vec!["curl", "-H", "Auth: sk-12345; rm -rf /"]);
+    }
+
+    #[test]
+    fn test_expand_dotenv() {
+        let vars = vec![
+            ("HOST".to_string(), "localhost".to_string()),
+            (
+                "URL".to_string(),
+                "http://${HOST}:${PORT}/?key=${API_KEY}".to_string(),
+            ),
+            ("PORT".to_string(), "8080".to_string()),
+        ];
+        let mut env_vars = HashMap::new();
+        env_vars.insert("API_KEY".to_string(), "sk-12345".to_string());
+
+        let expanded = expand_dotenv(&vars, &env_vars).unwrap();
+
+        assert_eq!(
+            expanded[1],
+            (
+                "URL".to_string(),
+                "http://localhost:8080/?key=sk-12345".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_expand_dotenv_cycle() {
+        let vars = vec![
+            ("A".to_string(), "${B}".to_string()),
+            ("B".to_string(), "x${A}".to_string()),
+        ];
+
+        let err = expand_dotenv(&vars, &HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("cycle"));
+    }
+
     #[test]
     fn test_shell_quote_simple() {
         assert_eq!(shell_quote("hello"), "hello");
@@ -249,7 +1166,7 @@ mod tests {
         // When user runs: secret-agent exec sh -c 'echo "{{KEY}}"'
         // The shell passes: ["sh", "-c", "echo \"{{KEY}}\""]
         // We need to reconstruct: sh -c 'echo "{{KEY}}"'
-        let parts = vec![
+        let parts = [
             "sh".to_string(),
             "-c".to_string(),
             "echo \"{{KEY}}\"".to_string(),
@@ -301,7 +1218,7 @@ mod tests {
 
     #[test]
     fn test_command_reconstruction_simple() {
-        let parts = vec!["echo".to_string(), "hello".to_string()];
+        let parts = ["echo".to_string(), "hello".to_string()];
         let command: String = parts
             .iter()
             .map(|s| shell_quote(s))
@@ -312,7 +1229,7 @@ mod tests {
 
     #[test]
     fn test_command_reconstruction_with_flags() {
-        let parts = vec![
+        let parts = [
             "curl".to_string(),
             "-X".to_string(),
             "POST".to_string(),
@@ -330,7 +1247,7 @@ mod tests {
     fn test_command_reconstruction_with_json() {
         // When user runs: secret-agent exec curl -d '{"key": "value"}'
         // Shell passes: ["curl", "-d", "{\"key\": \"value\"}"]
-        let parts = vec![
+        let parts = [
             "curl".to_string(),
             "-d".to_string(),
             "{\"key\": \"value\"}".to_string(),
@@ -348,7 +1265,7 @@ mod tests {
         // The exact use case that was broken:
         // secret-agent exec sh -c 'echo "{{KEY}}" | vercel env add KEY production'
         // Shell passes: ["sh", "-c", "echo \"{{KEY}}\" | vercel env add KEY production"]
-        let parts = vec![
+        let parts = [
             "sh".to_string(),
             "-c".to_string(),
             "echo \"{{KEY}}\" | vercel env add KEY production".to_string(),
@@ -367,7 +1284,7 @@ mod tests {
     #[test]
     fn test_parse_placeholders_in_quoted_command() {
         // After shell_quote, placeholders should still be findable
-        let parts = vec![
+        let parts = [
             "sh".to_string(),
             "-c".to_string(),
             "echo \"{{API_KEY}}\"".to_string(),
@@ -383,7 +1300,7 @@ mod tests {
 
     #[test]
     fn test_inject_secrets_in_quoted_command() {
-        let parts = vec![
+        let parts = [
             "sh".to_string(),
             "-c".to_string(),
             "echo \"{{API_KEY}}\"".to_string(),
@@ -404,7 +1321,7 @@ mod tests {
     #[test]
     fn test_full_pipeline_sh_c_echo() {
         // Simulate the full pipeline for: secret-agent exec sh -c 'echo "{{KEY}}"'
-        let command_parts = vec![
+        let command_parts = [
             "sh".to_string(),
             "-c".to_string(),
             "echo \"{{KEY}}\"".to_string(),
@@ -454,7 +1371,7 @@ mod tests {
     #[test]
     fn test_full_pipeline_complex_command() {
         // Simulate: secret-agent exec sh -c 'curl -H "Auth: {{TOKEN}}" https://api.com | jq .data'
-        let command_parts = vec![
+        let command_parts = [
             "sh".to_string(),
             "-c".to_string(),
             "curl -H \"Auth: {{TOKEN}}\" https://api.com | jq .data".to_string(),
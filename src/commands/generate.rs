@@ -0,0 +1,20 @@
+use crate::commands::get::unescape_separator;
+use crate::secret_gen::{self, Charset};
+use anyhow::{Context, Result};
+
+/// Print random values without storing them
+pub fn run(count: usize, length: usize, charset: &str, separator: &str) -> Result<()> {
+    let charset: Charset = charset
+        .parse()
+        .map_err(|e: String| anyhow::anyhow!(e))
+        .context("invalid charset")?;
+    verbose!("charset: {}", charset.name());
+    secret_gen::validate_length(length).map_err(|e| anyhow::anyhow!(e))?;
+
+    let values: Vec<String> = (0..count)
+        .map(|_| secret_gen::generate(length, charset))
+        .collect();
+
+    println!("{}", values.join(&unescape_separator(separator)));
+    Ok(())
+}
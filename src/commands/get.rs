@@ -1,8 +1,247 @@
+use crate::signals;
 use crate::vault::Vault;
 use anyhow::{Context, Result};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+use std::io::{self, BufRead, Write};
+use std::process::{Command, Stdio};
+use std::time::Duration;
 
-pub fn run(name: &str, clipboard: bool, unsafe_display: bool, quiet: bool) -> Result<()> {
-    if !clipboard && !unsafe_display {
+/// Where `get --clipboard` puts values; a trait so tests can observe it
+pub trait ClipboardSink {
+    fn set_text(&mut self, text: &str) -> Result<()>;
+    fn get_text(&mut self) -> Result<String>;
+    fn clear(&mut self) -> Result<()>;
+}
+
+struct SystemClipboard(arboard::Clipboard);
+
+impl SystemClipboard {
+    fn new() -> Result<Self> {
+        Ok(Self(
+            arboard::Clipboard::new().context("failed to access clipboard")?,
+        ))
+    }
+}
+
+/// Variables naming external clipboard programs, the fallback for
+/// `--clipboard-cmd` and `--clipboard-paste-cmd`
+const COPY_CMD_ENV: &str = "SECRET_AGENT_CLIPBOARD_CMD";
+const PASTE_CMD_ENV: &str = "SECRET_AGENT_CLIPBOARD_PASTE_CMD";
+
+/// Which clipboard `--clipboard` talks to
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClipboardBackend {
+    /// The desktop clipboard, through arboard
+    System,
+    /// External programs run with `sh -c`: `copy` reads the value on stdin
+    /// (`wl-copy`, `clip.exe`), `paste` prints it (`wl-paste`)
+    Command {
+        copy: Option<String>,
+        paste: Option<String>,
+    },
+}
+
+impl ClipboardBackend {
+    /// Use the given commands, falling back to SECRET_AGENT_CLIPBOARD_CMD and
+    /// SECRET_AGENT_CLIPBOARD_PASTE_CMD, and to arboard if neither is set
+    pub fn resolve(copy: Option<String>, paste: Option<String>) -> Self {
+        let from_env = |var| std::env::var(var).ok().filter(|v: &String| !v.is_empty());
+        let copy = copy.or_else(|| from_env(COPY_CMD_ENV));
+        let paste = paste.or_else(|| from_env(PASTE_CMD_ENV));
+        if copy.is_none() && paste.is_none() {
+            ClipboardBackend::System
+        } else {
+            ClipboardBackend::Command { copy, paste }
+        }
+    }
+
+    pub fn open(&self) -> Result<Box<dyn ClipboardSink>> {
+        Ok(match self {
+            ClipboardBackend::System => Box::new(SystemClipboard::new()?),
+            ClipboardBackend::Command { copy, paste } => Box::new(CommandClipboard {
+                copy: copy.clone(),
+                paste: paste.clone(),
+            }),
+        })
+    }
+
+    /// Pass the commands on to a child (the `--clear-after` cleaner)
+    fn export_to(&self, cmd: &mut Command) {
+        if let ClipboardBackend::Command { copy, paste } = self {
+            for (var, value) in [(COPY_CMD_ENV, copy), (PASTE_CMD_ENV, paste)] {
+                match value {
+                    Some(value) => cmd.env(var, value),
+                    None => cmd.env_remove(var),
+                };
+            }
+        }
+    }
+}
+
+/// Clipboard driven by external copy/paste programs
+struct CommandClipboard {
+    copy: Option<String>,
+    paste: Option<String>,
+}
+
+impl ClipboardSink for CommandClipboard {
+    fn set_text(&mut self, text: &str) -> Result<()> {
+        let copy = self
+            .copy
+            .as_deref()
+            .context("no clipboard copy command (set --clipboard-cmd)")?;
+        let mut child = Command::new("sh")
+            .args(["-c", copy])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .with_context(|| format!("failed to run clipboard command '{}'", copy))?;
+        // Dropping stdin after the write sends EOF
+        let written = child
+            .stdin
+            .take()
+            .context("clipboard command has no stdin")?
+            .write_all(text.as_bytes());
+        let status = child.wait()?;
+        written.with_context(|| format!("failed to write to clipboard command '{}'", copy))?;
+        if !status.success() {
+            anyhow::bail!("clipboard command '{}' failed ({})", copy, status);
+        }
+        Ok(())
+    }
+
+    fn get_text(&mut self) -> Result<String> {
+        let paste = self
+            .paste
+            .as_deref()
+            .context("no clipboard paste command (set --clipboard-paste-cmd)")?;
+        let output = Command::new("sh")
+            .args(["-c", paste])
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()
+            .with_context(|| format!("failed to run clipboard paste command '{}'", paste))?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "clipboard paste command '{}' failed ({})",
+                paste,
+                output.status
+            );
+        }
+        String::from_utf8(output.stdout).context("clipboard contents are not UTF-8 text")
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.set_text("")
+    }
+}
+
+impl ClipboardSink for SystemClipboard {
+    fn set_text(&mut self, text: &str) -> Result<()> {
+        self.0
+            .set_text(text)
+            .context("failed to copy secret to clipboard")
+    }
+
+    fn get_text(&mut self) -> Result<String> {
+        self.0.get_text().context("failed to read clipboard")
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.0.clear().context("failed to clear clipboard")
+    }
+}
+
+/// Where `--unsafe-display` writes values
+#[derive(Debug, PartialEq)]
+enum Destination {
+    Stdout,
+    Stderr,
+    Fd(i32),
+}
+
+impl std::str::FromStr for Destination {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "stdout" => Ok(Self::Stdout),
+            "stderr" => Ok(Self::Stderr),
+            _ => s
+                .strip_prefix("fd:")
+                .and_then(|fd| fd.parse().ok())
+                .filter(|fd| *fd >= 0)
+                .map(Self::Fd)
+                .with_context(|| format!("invalid --to '{}' (use stdout, stderr or fd:N)", s)),
+        }
+    }
+}
+
+impl Destination {
+    fn write_values(&self, values: &[SecretString]) -> Result<()> {
+        let mut text = String::new();
+        for value in values {
+            text.push_str(value.expose_secret());
+            text.push('\n');
+        }
+        self.write_bytes(text.as_bytes())
+    }
+
+    fn write_bytes(&self, bytes: &[u8]) -> Result<()> {
+        match self {
+            Self::Stdout => io::stdout().write_all(bytes)?,
+            Self::Stderr => io::stderr().write_all(bytes)?,
+            Self::Fd(fd) => write_to_fd(*fd, bytes)?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn write_to_fd(fd: i32, bytes: &[u8]) -> Result<()> {
+    use std::os::unix::io::FromRawFd;
+    // SAFETY: the caller passed us this descriptor; ManuallyDrop leaves it open
+    let mut file = std::mem::ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(fd) });
+    file.write_all(bytes)
+        .with_context(|| format!("failed to write to fd {}", fd))
+}
+
+#[cfg(not(unix))]
+fn write_to_fd(_fd: i32, _bytes: &[u8]) -> Result<()> {
+    anyhow::bail!("--to fd:N is only supported on Unix")
+}
+
+/// `get --unsafe-display --raw`: write a secret's exact stored bytes, with
+/// no trailing newline. Text secrets need `force`.
+pub fn run_raw(name: &str, to: &str, force: bool) -> Result<()> {
+    let destination: Destination = to.parse()?;
+
+    let vault = Vault::open().context("failed to open vault")?;
+    let (value, binary) = vault
+        .get_bytes(name)
+        .with_context(|| format!("failed to get secret '{}'", name))?;
+    if !binary && !force {
+        anyhow::bail!(
+            "secret '{}' is text, not binary; pass --force to print its raw bytes anyway",
+            name
+        );
+    }
+
+    eprintln!("WARNING: Displaying secret value. Do not use in agent contexts.");
+    destination.write_bytes(value.expose_secret())
+}
+
+pub fn run(
+    names: &[String],
+    clipboard: Option<&ClipboardBackend>,
+    unsafe_display: Option<&str>,
+    join: Option<&str>,
+    allow_unsafe: bool,
+    clear_after: Option<u64>,
+    quiet: bool,
+) -> Result<()> {
+    if clipboard.is_none() && unsafe_display.is_none() {
         anyhow::bail!(
             "You must use --clipboard or --unsafe-display to retrieve a secret.\n\
              --clipboard copies to clipboard (safe for agents)\n\
@@ -10,20 +249,295 @@ pub fn run(name: &str, clipboard: bool, unsafe_display: bool, quiet: bool) -> Re
         );
     }
 
+    if join.is_some() && !allow_unsafe {
+        anyhow::bail!(
+            "--join composes several plaintext values into one clipboard entry; \
+             pass --unsafe to confirm"
+        );
+    }
+
+    let destination = unsafe_display.map(str::parse::<Destination>).transpose()?;
+
     let vault = Vault::open().context("failed to open vault")?;
-    let value = vault.get(name).context("failed to get secret")?;
+    let values = names
+        .iter()
+        .map(|name| {
+            vault
+                .get(name)
+                .with_context(|| format!("failed to get secret '{}'", name))
+        })
+        .collect::<Result<Vec<_>>>()?;
 
-    if clipboard {
-        let mut cb = arboard::Clipboard::new().context("failed to access clipboard")?;
-        cb.set_text(&value)
-            .context("failed to copy secret to clipboard")?;
-        if !quiet {
-            println!("Copied {} to clipboard", name);
+    if let Some(backend) = clipboard {
+        let mut cb = backend.open()?;
+        let copied = match join {
+            Some(separator) => {
+                let joined = copy_joined(cb.as_mut(), &values, &unescape_separator(separator))?;
+                if !quiet {
+                    println!("Copied {} to clipboard", names.join(", "));
+                }
+                vec![joined]
+            }
+            None => {
+                copy_sequentially(cb.as_mut(), names, &values, quiet)?;
+                values
+                    .iter()
+                    .map(|value| value.expose_secret().to_string())
+                    .collect()
+            }
+        };
+
+        if let Some(secs) = clear_after {
+            if let ClipboardBackend::Command { paste: None, .. } = backend {
+                eprintln!(
+                    "warning: --clear-after needs --clipboard-paste-cmd to check the clipboard; it won't be cleared"
+                );
+            }
+            // Each cleaner only clears if the clipboard still holds its value,
+            // so only the last copy can match
+            for text in &copied {
+                schedule_clear(backend, text, secs)?;
+            }
+            if !quiet {
+                println!("Clipboard will be cleared in {}s", secs);
+            }
         }
-    } else {
+    } else if let Some(destination) = destination {
         eprintln!("WARNING: Displaying secret value. Do not use in agent contexts.");
-        println!("{}", value);
+        destination.write_values(&values)?;
+    }
+
+    Ok(())
+}
+
+/// Copy all values as one clipboard entry, returning what was copied
+fn copy_joined(
+    sink: &mut dyn ClipboardSink,
+    values: &[SecretString],
+    separator: &str,
+) -> Result<String> {
+    let joined = values
+        .iter()
+        .map(|value| value.expose_secret())
+        .collect::<Vec<_>>()
+        .join(separator);
+    sink.set_text(&joined)?;
+    Ok(joined)
+}
+
+/// Start a detached `clear-clipboard` process. It gets a hash of the value
+/// over a stdin pipe, never in its argv: an unsalted hash in the process list
+/// would let any local user brute-force a short secret like a PIN.
+// The cleaner is meant to outlive us; it is reparented to init when we exit
+#[allow(clippy::zombie_processes)]
+fn schedule_clear(backend: &ClipboardBackend, text: &str, secs: u64) -> Result<()> {
+    let exe = std::env::current_exe().context("failed to locate secret-agent binary")?;
+    let mut cmd = Command::new(exe);
+    backend.export_to(&mut cmd);
+    cmd.args(["clear-clipboard", "--after"])
+        .arg(secs.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // Keep the cleaner alive if the terminal sends Ctrl-C to our group
+    signals::isolate(&mut cmd);
+    let mut child = cmd.spawn().context("failed to schedule clipboard clear")?;
+    // Dropping stdin after the write closes the pipe
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    writeln!(stdin, "{}", clipboard_digest(text)).context("failed to schedule clipboard clear")?;
+    Ok(())
+}
+
+/// Hidden `clear-clipboard` entry point run by `schedule_clear`
+pub fn clear_later(after: u64) -> Result<()> {
+    let sha256 = read_digest(io::stdin().lock())?;
+    std::thread::sleep(Duration::from_secs(after));
+    let mut cb = ClipboardBackend::resolve(None, None).open()?;
+    clear_if_unchanged(cb.as_mut(), &sha256)?;
+    Ok(())
+}
+
+/// The hex SHA-256 `schedule_clear` writes to the cleaner's stdin
+fn read_digest(mut input: impl BufRead) -> Result<String> {
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    let digest = line.trim();
+    if digest.len() != 64 || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+        anyhow::bail!("expected a SHA-256 digest on stdin");
+    }
+    Ok(digest.to_string())
+}
+
+/// Clear the clipboard only if it still holds the value we copied, so
+/// anything the user copied in the meantime is left alone
+fn clear_if_unchanged(sink: &mut dyn ClipboardSink, sha256: &str) -> Result<bool> {
+    match sink.get_text() {
+        Ok(current) if clipboard_digest(&current) == sha256 => {
+            sink.clear()?;
+            Ok(true)
+        }
+        _ => Ok(false),
     }
+}
+
+fn clipboard_digest(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
 
+/// Copy each value in turn, waiting for Enter before replacing it with the next
+fn copy_sequentially(
+    sink: &mut dyn ClipboardSink,
+    names: &[String],
+    values: &[SecretString],
+    quiet: bool,
+) -> Result<()> {
+    let stdin = io::stdin();
+    for (i, (name, value)) in names.iter().zip(values).enumerate() {
+        if i > 0 {
+            eprint!("Press Enter to copy {} ", name);
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                anyhow::bail!("stdin closed before copying {}", name);
+            }
+        }
+        sink.set_text(value.expose_secret())?;
+        if !quiet {
+            println!("Copied {} to clipboard", name);
+        }
+    }
     Ok(())
 }
+
+/// Interpret `\n`, `\t`, and `\\` in a `--join` separator
+pub(crate) fn unescape_separator(separator: &str) -> String {
+    let mut result = String::with_capacity(separator.len());
+    let mut chars = separator.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('\\') => result.push('\\'),
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records every value set; the last one is the current content
+    #[derive(Default)]
+    struct FakeClipboard {
+        contents: Vec<String>,
+        cleared: bool,
+    }
+
+    impl ClipboardSink for FakeClipboard {
+        fn set_text(&mut self, text: &str) -> Result<()> {
+            self.contents.push(text.to_string());
+            Ok(())
+        }
+
+        fn get_text(&mut self) -> Result<String> {
+            match (self.cleared, self.contents.last()) {
+                (false, Some(text)) => Ok(text.clone()),
+                _ => anyhow::bail!("clipboard is empty"),
+            }
+        }
+
+        fn clear(&mut self) -> Result<()> {
+            self.cleared = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_copy_joined() {
+        let mut cb = FakeClipboard::default();
+        let values = vec![SecretString::from("user"), SecretString::from("pass")];
+
+        copy_joined(&mut cb, &values, &unescape_separator("\\n")).unwrap();
+
+        assert_eq!(cb.contents, ["user\npass"]);
+    }
+
+    #[test]
+    fn test_clear_if_unchanged_clears_our_value() {
+        let mut cb = FakeClipboard::default();
+        cb.set_text("secret-value").unwrap();
+
+        assert!(clear_if_unchanged(&mut cb, &clipboard_digest("secret-value")).unwrap());
+        assert!(cb.cleared);
+    }
+
+    #[test]
+    fn test_clear_if_unchanged_keeps_newer_copy() {
+        let mut cb = FakeClipboard::default();
+        cb.set_text("secret-value").unwrap();
+        cb.set_text("something the user copied later").unwrap();
+
+        assert!(!clear_if_unchanged(&mut cb, &clipboard_digest("secret-value")).unwrap());
+        assert!(!cb.cleared);
+    }
+
+    #[test]
+    fn test_read_digest() {
+        let digest = clipboard_digest("secret-value");
+        let input = format!("{}\n", digest);
+        assert_eq!(read_digest(input.as_bytes()).unwrap(), digest);
+        assert!(read_digest("".as_bytes()).is_err());
+        assert!(read_digest("secret-value\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_clear_if_unchanged_empty_clipboard() {
+        let mut cb = FakeClipboard::default();
+        assert!(!clear_if_unchanged(&mut cb, &clipboard_digest("secret-value")).unwrap());
+    }
+
+    #[test]
+    fn test_copy_single_value_sequentially() {
+        let mut cb = FakeClipboard::default();
+        let names = vec!["A".to_string()];
+        let values = vec![SecretString::from("value-a")];
+
+        copy_sequentially(&mut cb, &names, &values, true).unwrap();
+
+        assert_eq!(cb.contents, ["value-a"]);
+    }
+
+    #[test]
+    fn test_parse_destination() {
+        assert_eq!(
+            "stdout".parse::<Destination>().unwrap(),
+            Destination::Stdout
+        );
+        assert_eq!(
+            "stderr".parse::<Destination>().unwrap(),
+            Destination::Stderr
+        );
+        assert_eq!("fd:3".parse::<Destination>().unwrap(), Destination::Fd(3));
+        assert!("fd:-1".parse::<Destination>().is_err());
+        assert!("fd:".parse::<Destination>().is_err());
+        assert!("file".parse::<Destination>().is_err());
+    }
+
+    #[test]
+    fn test_unescape_separator() {
+        assert_eq!(unescape_separator(","), ",");
+        assert_eq!(unescape_separator("\\n"), "\n");
+        assert_eq!(unescape_separator("\\t|\\\\"), "\t|\\");
+        assert_eq!(unescape_separator("\\x"), "\\x");
+        assert_eq!(unescape_separator("end\\"), "end\\");
+    }
+}
@@ -1,21 +1,103 @@
-use crate::vault::Vault;
+use crate::commands::get::{ClipboardBackend, ClipboardSink};
+use crate::vault::{self, Vault};
 use anyhow::{Context, Result};
+use secrecy::zeroize::Zeroize;
+use secrecy::{ExposeSecret, SecretString};
 use std::io::{self, Read};
 
-pub fn run(name: &str, clipboard: bool, replace: bool, quiet: bool) -> Result<()> {
+/// Where `import` reads the value from and how it stores it
+#[derive(Default)]
+pub struct ImportOptions {
+    /// Read from the clipboard instead of stdin
+    pub clipboard: bool,
+    /// With `clipboard`, read the primary selection (Linux only)
+    pub primary: bool,
+    /// With `clipboard`, programs to paste (and clear) with instead of arboard
+    pub clipboard_paste_cmd: Option<String>,
+    pub clipboard_cmd: Option<String>,
+    /// With `clipboard`, drop a trailing URL line
+    pub strip_trailing_url: bool,
+    /// Store the input exactly, without trimming surrounding whitespace
+    pub raw: bool,
+    /// Store stdin as raw bytes, marked binary (not necessarily UTF-8)
+    pub binary: bool,
+    /// Overwrite an existing secret
+    pub replace: bool,
+    /// Read the value back after storing it and compare
+    pub verify: bool,
+    /// With `verify`, leave a mismatched row in place for inspection
+    pub no_rollback: bool,
+}
+
+/// The value read back after `import --verify` differs from what was imported
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "verification failed: stored value for '{name}' does not match the imported value ({outcome})"
+)]
+pub struct VerifyError {
+    name: String,
+    outcome: &'static str,
+}
+
+impl VerifyError {
+    pub const EXIT_CODE: i32 = 3;
+}
+
+pub fn run(name: &str, opts: &ImportOptions, quiet: bool) -> Result<()> {
+    // Check the name before consuming (and clearing) the clipboard or stdin
+    vault::validate_full_name(name).context("invalid secret name")?;
+    check_primary_supported(opts.primary)?;
+    let backend =
+        ClipboardBackend::resolve(opts.clipboard_cmd.clone(), opts.clipboard_paste_cmd.clone());
+    if opts.clipboard && opts.primary && backend != ClipboardBackend::System {
+        anyhow::bail!("--primary can't be combined with a clipboard command");
+    }
+
     let vault = Vault::open().context("failed to open vault")?;
 
-    let value = if clipboard {
-        read_from_clipboard()?
+    if opts.binary {
+        let mut bytes = Vec::new();
+        io::stdin()
+            .lock()
+            .read_to_end(&mut bytes)
+            .context("failed to read from stdin")?;
+        let stored = if bytes.is_empty() {
+            Err(anyhow::anyhow!("secret value cannot be empty"))
+        } else {
+            vault
+                .store_binary(name, &bytes, opts.replace)
+                .context("failed to import secret")
+        };
+        bytes.zeroize();
+        stored?;
+        if !quiet {
+            println!("Imported binary secret: {}", name);
+        }
+        return Ok(());
+    }
+
+    let value = if opts.clipboard {
+        let mut sink: Box<dyn ClipboardSink> = match backend {
+            ClipboardBackend::System => Box::new(Selection::new(opts.primary)?),
+            command => command.open()?,
+        };
+        read_from_clipboard(sink.as_mut(), opts.strip_trailing_url, opts.raw)?
     } else {
-        read_secret_value()?
+        read_secret_value(opts.raw)?
     };
 
     if value.is_empty() {
         anyhow::bail!("secret value cannot be empty");
     }
 
-    if replace {
+    // Kept so a failed verification can put the old value back
+    let previous = if opts.verify && opts.replace && vault.exists(name)? {
+        Some(vault.get(name)?)
+    } else {
+        None
+    };
+
+    if opts.replace {
         vault
             .create_or_update(name, &value)
             .context("failed to import secret")?;
@@ -25,31 +107,206 @@ pub fn run(name: &str, clipboard: bool, replace: bool, quiet: bool) -> Result<()
             .context("failed to import secret")?;
     }
 
+    if opts.verify {
+        verify(&vault, name, &value, previous, !opts.no_rollback)?;
+    }
+
+    // A soft check for pasting the same value under two names
+    let hash = vault.value_hash(&value);
+    for other in vault.find_by_value_hash(&hash)? {
+        if other != name {
+            eprintln!("warning: value matches existing secret '{}'", other);
+        }
+    }
+
     if !quiet {
         println!("Imported secret: {}", name);
     }
     Ok(())
 }
 
-fn read_from_clipboard() -> Result<String> {
-    let mut clipboard = arboard::Clipboard::new().context("failed to access clipboard")?;
+/// Read `name` back and compare it with `expected`. On a mismatch, unless
+/// `rollback` is off, restore `previous` or delete the new row.
+fn verify(
+    vault: &Vault,
+    name: &str,
+    expected: &str,
+    previous: Option<SecretString>,
+    rollback: bool,
+) -> Result<()> {
+    let stored = vault
+        .get(name)
+        .context("failed to read back imported secret")?;
+    if stored.expose_secret() == expected {
+        return Ok(());
+    }
+
+    if rollback {
+        match previous {
+            Some(old) => vault.update(name, old.expose_secret())?,
+            None => vault.delete(name)?,
+        }
+    }
+    Err(VerifyError {
+        name: name.to_string(),
+        outcome: if rollback {
+            "import rolled back"
+        } else {
+            "row kept for inspection"
+        },
+    }
+    .into())
+}
+
+/// The clipboard, or the primary selection with `--primary`
+struct Selection {
+    clipboard: arboard::Clipboard,
+    primary: bool,
+}
+
+impl Selection {
+    fn new(primary: bool) -> Result<Self> {
+        Ok(Self {
+            clipboard: arboard::Clipboard::new().context("failed to access clipboard")?,
+            primary,
+        })
+    }
+}
+
+impl ClipboardSink for Selection {
+    fn set_text(&mut self, text: &str) -> Result<()> {
+        self.clipboard
+            .set_text(text)
+            .context("failed to write clipboard")
+    }
+
+    fn get_text(&mut self) -> Result<String> {
+        read_selection(&mut self.clipboard, self.primary)
+            .context("failed to read from clipboard (is it empty or non-text?)")
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        clear_selection(&mut self.clipboard, self.primary).context("failed to clear clipboard")
+    }
+}
 
-    let value = clipboard
-        .get_text()
-        .context("failed to read from clipboard (is it empty or non-text?)")?;
+/// Read and clear the clipboard. Surrounding whitespace is trimmed unless `raw`.
+fn read_from_clipboard(sink: &mut dyn ClipboardSink, strip_url: bool, raw: bool) -> Result<String> {
+    let value = sink.get_text()?;
 
     // Clear clipboard after reading for security
-    let _ = clipboard.clear();
+    let _ = sink.clear();
 
-    let trimmed = value.trim().to_string();
+    if raw {
+        if value.is_empty() {
+            anyhow::bail!("clipboard is empty");
+        }
+        return Ok(value);
+    }
+
+    let mut trimmed = value.trim();
+    if strip_url {
+        trimmed = strip_trailing_url(trimmed);
+    }
     if trimmed.is_empty() {
         anyhow::bail!("clipboard is empty");
     }
 
-    Ok(trimmed)
+    Ok(trimmed.to_string())
+}
+
+/// Drop a final line that is nothing but a URL, as browsers append when
+/// copying from a page. A value that is only a URL is left alone.
+fn strip_trailing_url(value: &str) -> &str {
+    let Some((head, last)) = value.rsplit_once('\n') else {
+        return value;
+    };
+    let last = last.trim();
+    let is_url = (last.starts_with("https://") || last.starts_with("http://"))
+        && !last.contains(char::is_whitespace);
+    if is_url && !head.trim().is_empty() {
+        head.trim_end()
+    } else {
+        value
+    }
+}
+
+/// The primary selection (middle-click buffer) only exists on X11/Wayland
+fn check_primary_supported(primary: bool) -> Result<()> {
+    if primary
+        && !cfg!(all(
+            unix,
+            not(any(
+                target_os = "macos",
+                target_os = "android",
+                target_os = "emscripten"
+            ))
+        ))
+    {
+        anyhow::bail!("--primary is only supported on Linux (X11/Wayland)");
+    }
+    Ok(())
 }
 
-fn read_secret_value() -> Result<String> {
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+))]
+fn read_selection(
+    clipboard: &mut arboard::Clipboard,
+    primary: bool,
+) -> Result<String, arboard::Error> {
+    use arboard::{GetExtLinux, LinuxClipboardKind};
+    let kind = if primary {
+        LinuxClipboardKind::Primary
+    } else {
+        LinuxClipboardKind::Clipboard
+    };
+    clipboard.get().clipboard(kind).text()
+}
+
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+)))]
+fn read_selection(
+    clipboard: &mut arboard::Clipboard,
+    _primary: bool,
+) -> Result<String, arboard::Error> {
+    clipboard.get_text()
+}
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+))]
+fn clear_selection(
+    clipboard: &mut arboard::Clipboard,
+    primary: bool,
+) -> Result<(), arboard::Error> {
+    use arboard::{ClearExtLinux, LinuxClipboardKind};
+    let kind = if primary {
+        LinuxClipboardKind::Primary
+    } else {
+        LinuxClipboardKind::Clipboard
+    };
+    clipboard.clear_with().clipboard(kind)
+}
+
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+)))]
+fn clear_selection(
+    clipboard: &mut arboard::Clipboard,
+    _primary: bool,
+) -> Result<(), arboard::Error> {
+    clipboard.clear()
+}
+
+/// Read the value from a hidden prompt or piped stdin. Piped input loses its
+/// trailing whitespace (including the final newline) unless `raw`.
+fn read_secret_value(raw: bool) -> Result<String> {
     // Check if stdin is a TTY (interactive) or piped
     if atty::is(atty::Stream::Stdin) {
         // Interactive prompt with hidden input
@@ -65,7 +322,153 @@ fn read_secret_value() -> Result<String> {
             .read_to_string(&mut value)
             .context("failed to read from stdin")?;
 
+        if raw {
+            return Ok(value);
+        }
         // Trim trailing whitespace
         Ok(value.trim_end().to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keychain::KeySource;
+
+    struct FakeClipboard {
+        text: Option<String>,
+    }
+
+    impl ClipboardSink for FakeClipboard {
+        fn set_text(&mut self, text: &str) -> Result<()> {
+            self.text = Some(text.to_string());
+            Ok(())
+        }
+
+        fn get_text(&mut self) -> Result<String> {
+            self.text.clone().context("clipboard is empty")
+        }
+
+        fn clear(&mut self) -> Result<()> {
+            self.text = None;
+            Ok(())
+        }
+    }
+
+    fn pasted(text: &str) -> FakeClipboard {
+        FakeClipboard {
+            text: Some(text.to_string()),
+        }
+    }
+
+    fn test_vault() -> (Vault, tempfile::TempDir) {
+        let dir = tempfile::TempDir::new().unwrap();
+        let key = KeySource::Passphrase("test-passphrase".into());
+        let vault = Vault::open_at(&dir.path().join("vault.db"), key).unwrap();
+        (vault, dir)
+    }
+
+    #[test]
+    fn test_verify_matching_value() {
+        let (vault, _dir) = test_vault();
+        vault.create("VERIFIED", "value").unwrap();
+        verify(&vault, "VERIFIED", "value", None, true).unwrap();
+        assert!(vault.exists("VERIFIED").unwrap());
+    }
+
+    #[test]
+    fn test_verify_mismatch_rolls_back() {
+        let (vault, _dir) = test_vault();
+
+        // A stored value that differs from what was "imported"
+        vault.create("NEW", "corrupted").unwrap();
+        let err = verify(&vault, "NEW", "value", None, true).unwrap_err();
+        assert!(err.is::<VerifyError>());
+        assert!(!vault.exists("NEW").unwrap());
+
+        vault.create("REPLACED", "corrupted").unwrap();
+        let previous = Some(SecretString::from("old"));
+        verify(&vault, "REPLACED", "value", previous, true).unwrap_err();
+        assert_eq!(vault.get("REPLACED").unwrap().expose_secret(), "old");
+    }
+
+    #[test]
+    fn test_verify_mismatch_without_rollback_keeps_row() {
+        let (vault, _dir) = test_vault();
+        vault.create("KEPT", "corrupted").unwrap();
+
+        let err = verify(&vault, "KEPT", "value", None, false).unwrap_err();
+        assert!(err.to_string().contains("row kept for inspection"));
+        assert_eq!(vault.get("KEPT").unwrap().expose_secret(), "corrupted");
+    }
+
+    #[test]
+    fn test_strip_trailing_url_keeps_only_token() {
+        let mut cb = pasted("sk-abc123\nhttps://platform.example.com/api-keys\n");
+        assert_eq!(
+            read_from_clipboard(&mut cb, true, false).unwrap(),
+            "sk-abc123"
+        );
+        assert!(cb.text.is_none());
+    }
+
+    #[test]
+    fn test_raw_clipboard_keeps_whitespace() {
+        let mut cb = pasted("  padded  \n");
+        assert_eq!(
+            read_from_clipboard(&mut cb, false, true).unwrap(),
+            "  padded  \n"
+        );
+        assert!(cb.text.is_none());
+    }
+
+    #[test]
+    fn test_trailing_url_kept_without_flag() {
+        let mut cb = pasted("sk-abc123\r\nhttps://platform.example.com/api-keys");
+        assert_eq!(
+            read_from_clipboard(&mut cb, false, false).unwrap(),
+            "sk-abc123\r\nhttps://platform.example.com/api-keys"
+        );
+    }
+
+    #[test]
+    fn test_strip_trailing_url_leaves_other_values() {
+        assert_eq!(
+            strip_trailing_url("https://hooks.example.com/T0/B1"),
+            "https://hooks.example.com/T0/B1"
+        );
+        assert_eq!(
+            strip_trailing_url("line one\nline two"),
+            "line one\nline two"
+        );
+        assert_eq!(
+            strip_trailing_url("token\nsee https://example.com"),
+            "token\nsee https://example.com"
+        );
+        assert_eq!(strip_trailing_url("tok\r\nhttp://x.test/\r"), "tok");
+    }
+
+    #[test]
+    fn test_default_clipboard_always_supported() {
+        assert!(check_primary_supported(false).is_ok());
+    }
+
+    #[cfg(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+    ))]
+    #[test]
+    fn test_primary_supported_on_linux() {
+        assert!(check_primary_supported(true).is_ok());
+    }
+
+    #[cfg(not(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+    )))]
+    #[test]
+    fn test_primary_unsupported_elsewhere() {
+        let err = check_primary_supported(true).unwrap_err();
+        assert!(err.to_string().contains("only supported on Linux"));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::vault::{parse_bucket_name, Secret, Vault};
+use anyhow::{Context, Result};
+
+/// Metadata fields that can be selected with --field
+const FIELDS: &[&str] = &["name", "bucket", "created_at", "updated_at", "created_by"];
+
+pub fn run(name: &str, field: Option<&str>) -> Result<()> {
+    if let Some(field) = field {
+        validate_field(field)?;
+    }
+
+    let vault = Vault::open().context("failed to open vault")?;
+    let secret = vault
+        .get_metadata(name)
+        .context("failed to get secret metadata")?;
+
+    match field {
+        Some(field) => println!("{}", field_value(&secret, field)),
+        None => {
+            for field in FIELDS {
+                println!(
+                    "{:<11} {}",
+                    format!("{}:", field),
+                    field_value(&secret, field)
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_field(field: &str) -> Result<()> {
+    if field == "value" {
+        anyhow::bail!(
+            "info never shows secret values; use `get --clipboard` or `get --unsafe-display`"
+        );
+    }
+    if !FIELDS.contains(&field) {
+        anyhow::bail!(
+            "unknown field: {} (expected one of: {})",
+            field,
+            FIELDS.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn field_value(secret: &Secret, field: &str) -> String {
+    match field {
+        "name" => secret.name.clone(),
+        "bucket" => parse_bucket_name(&secret.name)
+            .0
+            .unwrap_or_default()
+            .to_string(),
+        "created_at" => secret.created_at.to_rfc3339(),
+        "updated_at" => secret.updated_at.to_rfc3339(),
+        "created_by" => secret.created_by.clone().unwrap_or_default(),
+        _ => unreachable!("field validated before lookup"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_field() {
+        for field in FIELDS {
+            assert!(validate_field(field).is_ok());
+        }
+        assert!(validate_field("value").is_err());
+        assert!(validate_field("bogus").is_err());
+    }
+}
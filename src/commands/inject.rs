@@ -1,61 +1,170 @@
+use crate::commands::env::quote_env_value;
+use crate::fs_util::write_if_changed;
+use crate::sanitize;
 use crate::vault::{secret_name_only, Vault};
 use anyhow::{Context, Result};
+use secrecy::ExposeSecret;
 use std::fs;
+use std::ops::Range;
 use std::path::Path;
 
-pub fn run(
-    name: &str,
-    file: &str,
-    placeholder: Option<&str>,
-    env_format: bool,
-    export: bool,
-    quiet: bool,
-) -> Result<()> {
+/// How `inject` writes the secret into the file
+#[derive(Default)]
+pub struct InjectOptions {
+    /// String to replace with the value
+    pub placeholder: Option<String>,
+    /// Write a NAME=value line instead of replacing a placeholder
+    pub env_format: bool,
+    /// Prefix the NAME=value line with `export `
+    pub export: bool,
+    /// Write the file even if its content would not change
+    pub always_write: bool,
+    /// Only edit the section following this marker line
+    pub after_marker: Option<String>,
+    /// Whether `env_format` may add a new line, replace an existing one, or both
+    pub mode: EnvMode,
+}
+
+/// Which NAME=value writes `--env-format` allows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EnvMode {
+    /// Replace the line if present, otherwise add it
+    #[default]
+    Upsert,
+    /// Fail unless the variable is already set (catches typos)
+    UpdateOnly,
+    /// Fail if the variable is already set
+    CreateOnly,
+}
+
+pub fn run(name: &str, file: &str, opts: &InjectOptions, quiet: bool) -> Result<()> {
+    let after_marker = opts.after_marker.as_deref();
     let vault = Vault::open().context("failed to open vault")?;
     let value = vault.get(name).context("failed to get secret")?;
+    sanitize::remember(name, value.expose_secret());
 
     let path = Path::new(file);
 
     // Use just the secret name (without bucket) for env var name
     let env_var_name = secret_name_only(name);
 
-    if env_format {
+    let written = if opts.env_format {
         // Append or update NAME=value line
-        inject_env_format(path, env_var_name, &value, export)?;
-    } else if let Some(placeholder) = placeholder {
+        inject_env_format(
+            path,
+            env_var_name,
+            value.expose_secret(),
+            opts.export,
+            after_marker,
+            opts.mode,
+            opts.always_write,
+        )?
+    } else if let Some(placeholder) = &opts.placeholder {
         // Replace placeholder in file
-        inject_placeholder(path, placeholder, &value)?;
+        inject_placeholder(
+            path,
+            placeholder,
+            value.expose_secret(),
+            after_marker,
+            opts.always_write,
+        )?
     } else {
         anyhow::bail!("either --placeholder or --env-format is required");
-    }
+    };
 
     if !quiet {
-        println!("Injected {} into {}", name, file);
+        if written {
+            println!("Injected {} into {}", name, file);
+        } else {
+            println!("{} already up to date in {}", name, file);
+        }
     }
     Ok(())
 }
 
-fn inject_placeholder(path: &Path, placeholder: &str, value: &str) -> Result<()> {
+/// Lines of the section following the line that is exactly `marker`
+/// (ignoring surrounding whitespace), up to the next `[section]` header
+fn section_lines<S: AsRef<str>>(lines: &[S], marker: &str) -> Option<Range<usize>> {
+    let start = lines.iter().position(|l| l.as_ref().trim() == marker)? + 1;
+    let end = lines[start..]
+        .iter()
+        .position(|l| is_section_header(l.as_ref()))
+        .map_or(lines.len(), |i| start + i);
+    Some(start..end)
+}
+
+fn is_section_header(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with('[') && line.ends_with(']')
+}
+
+fn marker_not_found(marker: &str, path: &Path) -> anyhow::Error {
+    anyhow::anyhow!("marker '{}' not found in file: {}", marker, path.display())
+}
+
+fn inject_placeholder(
+    path: &Path,
+    placeholder: &str,
+    value: &str,
+    after_marker: Option<&str>,
+    always_write: bool,
+) -> Result<bool> {
     let content = fs::read_to_string(path)
         .with_context(|| format!("failed to read file: {}", path.display()))?;
 
-    if !content.contains(placeholder) {
-        anyhow::bail!(
-            "placeholder '{}' not found in file: {}",
-            placeholder,
-            path.display()
-        );
-    }
-
-    let new_content = content.replace(placeholder, value);
+    // Keep line endings intact so untouched lines are written back byte for byte
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let section = match after_marker {
+        Some(marker) => {
+            section_lines(&lines, marker).ok_or_else(|| marker_not_found(marker, path))?
+        }
+        None => 0..lines.len(),
+    };
 
-    fs::write(path, new_content)
-        .with_context(|| format!("failed to write file: {}", path.display()))?;
+    if !lines[section.clone()]
+        .iter()
+        .any(|l| l.contains(placeholder))
+    {
+        match after_marker {
+            Some(marker) => anyhow::bail!(
+                "placeholder '{}' not found after marker '{}' in file: {}",
+                placeholder,
+                marker,
+                path.display()
+            ),
+            None => anyhow::bail!(
+                "placeholder '{}' not found in file: {}",
+                placeholder,
+                path.display()
+            ),
+        }
+    }
 
-    Ok(())
+    let new_content: String = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            if section.contains(&i) {
+                line.replace(placeholder, value)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+
+    write_if_changed(path, &new_content, always_write)
+        .with_context(|| format!("failed to write file: {}", path.display()))
 }
 
-fn inject_env_format(path: &Path, name: &str, value: &str, export: bool) -> Result<()> {
+fn inject_env_format(
+    path: &Path,
+    name: &str,
+    value: &str,
+    export: bool,
+    after_marker: Option<&str>,
+    mode: EnvMode,
+    always_write: bool,
+) -> Result<bool> {
     let mut content = if path.exists() {
         fs::read_to_string(path)
             .with_context(|| format!("failed to read file: {}", path.display()))?
@@ -63,65 +172,82 @@ fn inject_env_format(path: &Path, name: &str, value: &str, export: bool) -> Resu
         String::new()
     };
 
-    // Build the line format
-    let prefix = if export { "export " } else { "" };
     let quoted_value = quote_env_value(value);
-    let new_line = format!("{}{}={}", prefix, name, quoted_value);
+    let assignment = format!("{}={}", name, quoted_value);
+
+    // Keep line endings intact so comments, blank lines and untouched
+    // assignments are written back byte for byte
+    let mut new_lines: Vec<String> = content.split_inclusive('\n').map(str::to_string).collect();
+    let newline = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let section = match after_marker {
+        Some(marker) => {
+            section_lines(&new_lines, marker).ok_or_else(|| marker_not_found(marker, path))?
+        }
+        None => 0..new_lines.len(),
+    };
 
     // Check if the variable already exists
     let var_pattern = format!("{}=", name);
-    let export_pattern = format!("export {}=", name);
     let mut found = false;
-    let mut new_lines: Vec<String> = Vec::new();
-
-    for line in content.lines() {
-        if line.starts_with(&var_pattern) || line.starts_with(&export_pattern) {
-            // Replace existing line
-            new_lines.push(new_line.clone());
+    for line in &mut new_lines[section.clone()] {
+        let body = line.trim_end_matches(['\n', '\r']);
+        let ending = &line[body.len()..];
+        let unindented = body.trim_start();
+        let indent = &body[..body.len() - unindented.len()];
+        let (exported, rest) = match unindented.strip_prefix("export ") {
+            Some(rest) => (true, rest.trim_start()),
+            None => (false, unindented),
+        };
+        if rest.starts_with(&var_pattern) {
+            // Replace the assignment, keeping the line's own `export `
+            let prefix = if export || exported { "export " } else { "" };
+            *line = format!("{}{}{}{}", indent, prefix, assignment, ending);
             found = true;
-        } else {
-            new_lines.push(line.to_string());
         }
     }
 
+    let place = match after_marker {
+        Some(marker) => format!("after marker '{}' in {}", marker, path.display()),
+        None => format!("in {}", path.display()),
+    };
+    match (mode, found) {
+        (EnvMode::UpdateOnly, false) => {
+            anyhow::bail!("{} is not set {} (--update-only)", name, place)
+        }
+        (EnvMode::CreateOnly, true) => {
+            anyhow::bail!("{} is already set {} (--create-only)", name, place)
+        }
+        _ => {}
+    }
+
     if !found {
-        // Append new line
-        new_lines.push(new_line);
+        // Append new line, at the end of the marked section if there is one
+        let at = new_lines[section.clone()]
+            .iter()
+            .rposition(|l| !l.trim().is_empty())
+            .map_or(section.start, |i| section.start + i + 1);
+        if let Some(previous) = at.checked_sub(1).map(|i| &mut new_lines[i]) {
+            if !previous.ends_with('\n') {
+                previous.push_str(newline);
+            }
+        }
+        let prefix = if export { "export " } else { "" };
+        new_lines.insert(at, format!("{}{}{}", prefix, assignment, newline));
     }
 
-    content = new_lines.join("\n");
+    content = new_lines.concat();
 
     // Ensure file ends with newline
     if !content.ends_with('\n') {
-        content.push('\n');
+        content.push_str(newline);
     }
 
-    fs::write(path, content)
-        .with_context(|| format!("failed to write file: {}", path.display()))?;
-
-    Ok(())
-}
-
-/// Quote value for .env file if needed
-fn quote_env_value(value: &str) -> String {
-    // If value contains spaces, quotes, or special chars, wrap in quotes
-    if value.contains(' ')
-        || value.contains('"')
-        || value.contains('\'')
-        || value.contains('$')
-        || value.contains('\n')
-        || value.contains('#')
-    {
-        // Use double quotes and escape special characters
-        let escaped = value
-            .replace('\\', "\\\\")
-            .replace('"', "\\\"")
-            .replace('$', "\\$")
-            .replace('\n', "\\n");
-        format!("\"{}\"", escaped)
-    } else {
-        value.to_string()
-    }
+    write_if_changed(path, &content, always_write)
+        .with_context(|| format!("failed to write file: {}", path.display()))
 }
 
 #[cfg(test)]
@@ -135,7 +261,7 @@ mod tests {
         let mut file = NamedTempFile::new().unwrap();
         writeln!(file, "password={{{{DB_PASS}}}}").unwrap();
 
-        inject_placeholder(file.path(), "{{DB_PASS}}", "secret123").unwrap();
+        inject_placeholder(file.path(), "{{DB_PASS}}", "secret123", None, false).unwrap();
 
         let content = fs::read_to_string(file.path()).unwrap();
         assert_eq!(content.trim(), "password=secret123");
@@ -146,7 +272,16 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
         let path = dir.path().join(".env");
 
-        inject_env_format(&path, "API_KEY", "sk-12345", false).unwrap();
+        inject_env_format(
+            &path,
+            "API_KEY",
+            "sk-12345",
+            false,
+            None,
+            EnvMode::Upsert,
+            false,
+        )
+        .unwrap();
 
         let content = fs::read_to_string(&path).unwrap();
         assert_eq!(content, "API_KEY=sk-12345\n");
@@ -157,7 +292,16 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
         let path = dir.path().join("env.sh");
 
-        inject_env_format(&path, "API_KEY", "sk-12345", true).unwrap();
+        inject_env_format(
+            &path,
+            "API_KEY",
+            "sk-12345",
+            true,
+            None,
+            EnvMode::Upsert,
+            false,
+        )
+        .unwrap();
 
         let content = fs::read_to_string(&path).unwrap();
         assert_eq!(content, "export API_KEY=sk-12345\n");
@@ -169,7 +313,16 @@ mod tests {
         writeln!(file, "API_KEY=old-value").unwrap();
         writeln!(file, "OTHER=keep").unwrap();
 
-        inject_env_format(file.path(), "API_KEY", "new-value", false).unwrap();
+        inject_env_format(
+            file.path(),
+            "API_KEY",
+            "new-value",
+            false,
+            None,
+            EnvMode::Upsert,
+            false,
+        )
+        .unwrap();
 
         let content = fs::read_to_string(file.path()).unwrap();
         assert!(content.contains("API_KEY=new-value"));
@@ -182,13 +335,204 @@ mod tests {
         let mut file = NamedTempFile::new().unwrap();
         writeln!(file, "EXISTING=value").unwrap();
 
-        inject_env_format(file.path(), "NEW_KEY", "new-value", false).unwrap();
+        inject_env_format(
+            file.path(),
+            "NEW_KEY",
+            "new-value",
+            false,
+            None,
+            EnvMode::Upsert,
+            false,
+        )
+        .unwrap();
 
         let content = fs::read_to_string(file.path()).unwrap();
         assert!(content.contains("EXISTING=value"));
         assert!(content.contains("NEW_KEY=new-value"));
     }
 
+    #[test]
+    fn test_inject_env_format_unchanged_skips_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+
+        assert!(inject_env_format(
+            &path,
+            "API_KEY",
+            "sk-12345",
+            false,
+            None,
+            EnvMode::Upsert,
+            false
+        )
+        .unwrap());
+        let mtime = fs::metadata(&path).unwrap().modified().unwrap();
+
+        assert!(!inject_env_format(
+            &path,
+            "API_KEY",
+            "sk-12345",
+            false,
+            None,
+            EnvMode::Upsert,
+            false
+        )
+        .unwrap());
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), mtime);
+
+        assert!(inject_env_format(
+            &path,
+            "API_KEY",
+            "sk-12345",
+            false,
+            None,
+            EnvMode::Upsert,
+            true
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_inject_env_format_keeps_export_and_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        let original = "# Database\nexport FOO=old\n\n  # indented comment\nBAR=keep\n\n";
+        fs::write(&path, original).unwrap();
+
+        inject_env_format(&path, "FOO", "new", false, None, EnvMode::Upsert, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            original.replace("export FOO=old", "export FOO=new")
+        );
+
+        // Appending keeps the trailing blank line where it was
+        inject_env_format(&path, "BAZ", "added", false, None, EnvMode::Upsert, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# Database\nexport FOO=new\n\n  # indented comment\nBAR=keep\nBAZ=added\n\n"
+        );
+    }
+
+    #[test]
+    fn test_inject_env_format_keeps_crlf() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        fs::write(&path, "# comment\r\nFOO=old\r\nLAST=x").unwrap();
+
+        inject_env_format(&path, "FOO", "new", false, None, EnvMode::Upsert, false).unwrap();
+        inject_env_format(&path, "ADDED", "y", false, None, EnvMode::Upsert, false).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# comment\r\nFOO=new\r\nLAST=x\r\nADDED=y\r\n"
+        );
+    }
+
+    #[test]
+    fn test_inject_env_format_update_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        fs::write(&path, "API_KEY=old\n").unwrap();
+
+        let mode = EnvMode::UpdateOnly;
+        inject_env_format(&path, "API_KEY", "new", false, None, mode, false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "API_KEY=new\n");
+
+        let err = inject_env_format(&path, "API_KYE", "new", false, None, mode, false).unwrap_err();
+        assert!(err.to_string().contains("API_KYE is not set"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "API_KEY=new\n");
+    }
+
+    #[test]
+    fn test_inject_env_format_create_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        fs::write(&path, "API_KEY=old\n").unwrap();
+
+        let mode = EnvMode::CreateOnly;
+        inject_env_format(&path, "TOKEN", "tok", false, None, mode, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "API_KEY=old\nTOKEN=tok\n"
+        );
+
+        let err = inject_env_format(&path, "API_KEY", "new", false, None, mode, false).unwrap_err();
+        assert!(err.to_string().contains("API_KEY is already set"));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "API_KEY=old\nTOKEN=tok\n"
+        );
+    }
+
+    const SECTIONS: &str = "[development]\nAPI_KEY=dev-key\nurl=__URL__\n\n[production]\nAPI_KEY=prod-key\nurl=__URL__\n\n[test]\nurl=__URL__\n";
+
+    #[test]
+    fn test_inject_placeholder_after_marker() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.ini");
+        fs::write(&path, SECTIONS).unwrap();
+
+        inject_placeholder(&path, "__URL__", "secret", Some("[production]"), false).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            SECTIONS.replace(
+                "[production]\nAPI_KEY=prod-key\nurl=__URL__",
+                "[production]\nAPI_KEY=prod-key\nurl=secret"
+            )
+        );
+    }
+
+    #[test]
+    fn test_inject_env_format_after_marker() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.ini");
+        fs::write(&path, SECTIONS).unwrap();
+
+        inject_env_format(
+            &path,
+            "API_KEY",
+            "new-key",
+            false,
+            Some("[production]"),
+            EnvMode::Upsert,
+            false,
+        )
+        .unwrap();
+        inject_env_format(
+            &path,
+            "TOKEN",
+            "tok",
+            false,
+            Some("[test]"),
+            EnvMode::Upsert,
+            false,
+        )
+        .unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("[development]\nAPI_KEY=dev-key\n"));
+        assert!(content.contains("[production]\nAPI_KEY=new-key\n"));
+        assert!(content.ends_with("[test]\nurl=__URL__\nTOKEN=tok\n"));
+    }
+
+    #[test]
+    fn test_inject_missing_marker_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.ini");
+        fs::write(&path, SECTIONS).unwrap();
+
+        let err =
+            inject_placeholder(&path, "__URL__", "secret", Some("[staging]"), false).unwrap_err();
+        assert!(err.to_string().contains("marker '[staging]' not found"));
+
+        // The placeholder has to be inside the marked section
+        fs::write(&path, "url=__URL__\n[production]\nurl=set\n").unwrap();
+        let err = inject_placeholder(&path, "__URL__", "secret", Some("[production]"), false)
+            .unwrap_err();
+        assert!(err.to_string().contains("not found after marker"));
+    }
+
     #[test]
     fn test_quote_env_value() {
         assert_eq!(quote_env_value("simple"), "simple");
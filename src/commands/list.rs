@@ -1,13 +1,37 @@
-use crate::vault::Vault;
+use crate::vault::{parse_bucket_name, Secret, Vault};
 use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
 
-pub fn run(bucket: Option<&str>) -> Result<()> {
+/// Shape of `list --json-envelope` output; bumped when fields change incompatibly
+const JSON_SCHEMA: u32 = 1;
+
+pub fn run(
+    bucket: Option<&str>,
+    format: &str,
+    long: bool,
+    json: bool,
+    envelope: bool,
+) -> Result<()> {
     let vault = Vault::open().context("failed to open vault")?;
 
     let secrets = vault
         .list_by_bucket(bucket)
         .context("failed to list secrets")?;
 
+    if json || envelope {
+        println!("{}", secrets_json(&secrets, envelope));
+        return Ok(());
+    }
+
+    match format {
+        "table" => print_table(&vault, &secrets, bucket, long),
+        "csv" => print_csv(&secrets),
+        _ => anyhow::bail!("unknown format: {} (expected table or csv)", format),
+    }
+}
+
+fn print_table(vault: &Vault, secrets: &[Secret], bucket: Option<&str>, long: bool) -> Result<()> {
     if secrets.is_empty() {
         if let Some(b) = bucket {
             println!("No secrets in bucket '{}'.", b);
@@ -18,12 +42,78 @@ pub fn run(bucket: Option<&str>) -> Result<()> {
     }
 
     // Print header
-    println!("{:<32} CREATED", "NAME");
+    if long {
+        println!(
+            "{:<32} {:<19} {:>6} {:<14} CREATED BY",
+            "NAME", "CREATED", "LENGTH", "CLASS"
+        );
+    } else {
+        println!("{:<32} CREATED", "NAME");
+    }
+
+    // Values are decrypted only to measure them; they are never printed
+    let shapes = if long {
+        vault.value_shapes().context("failed to read secrets")?
+    } else {
+        HashMap::new()
+    };
 
     for secret in secrets {
         let created = secret.created_at.format("%Y-%m-%d %H:%M:%S");
-        println!("{:<32} {}", secret.name, created);
+        if long {
+            let created_by = secret.created_by.as_deref().unwrap_or("-");
+            let (length, class) = match shapes.get(&secret.name).copied().flatten() {
+                Some((length, class)) => (length.to_string(), class),
+                None => ("?".to_string(), "unreadable"),
+            };
+            println!(
+                "{:<32} {} {:>6} {:<14} {}",
+                secret.name, created, length, class, created_by
+            );
+        } else {
+            println!("{:<32} {}", secret.name, created);
+        }
+    }
+
+    Ok(())
+}
+
+/// A JSON array of secret metadata, optionally wrapped as
+/// `{"schema": N, "secrets": [...]}` so consumers can detect the shape
+fn secrets_json(secrets: &[Secret], envelope: bool) -> Value {
+    let list = Value::Array(secrets.iter().map(metadata_json).collect());
+    if envelope {
+        json!({ "schema": JSON_SCHEMA, "secrets": list })
+    } else {
+        list
+    }
+}
+
+/// Metadata for one secret as JSON (never its value)
+pub(crate) fn metadata_json(secret: &Secret) -> Value {
+    json!({
+        "name": secret.name,
+        "bucket": parse_bucket_name(&secret.name).0,
+        "created_at": secret.created_at.to_rfc3339(),
+        "updated_at": secret.updated_at.to_rfc3339(),
+        "created_by": secret.created_by,
+    })
+}
+
+fn print_csv(secrets: &[Secret]) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(std::io::stdout());
+    writer.write_record(["name", "bucket", "created_at", "updated_at"])?;
+
+    for secret in secrets {
+        let (bucket, _) = parse_bucket_name(&secret.name);
+        writer.write_record([
+            secret.name.as_str(),
+            bucket.unwrap_or(""),
+            &secret.created_at.to_rfc3339(),
+            &secret.updated_at.to_rfc3339(),
+        ])?;
     }
 
+    writer.flush()?;
     Ok(())
 }
@@ -1,9 +1,22 @@
+pub mod backup;
+pub mod batch;
+pub mod buckets;
+pub mod bundle;
+pub mod charsets;
+pub mod compact;
 pub mod create;
+pub mod daemon;
 pub mod delete;
 pub mod env;
 pub mod exec;
+pub mod generate;
 pub mod get;
 pub mod import;
+pub mod info;
 pub mod inject;
 pub mod list;
+pub mod reencrypt;
+pub mod rename;
+pub mod session;
 pub mod setup;
+pub mod uninstall;
@@ -0,0 +1,93 @@
+use crate::prompt;
+use crate::vault::Vault;
+use anyhow::{Context, Result};
+
+const NEW_PASSPHRASE_ENV: &str = "SECRET_AGENT_NEW_PASSPHRASE";
+
+/// Re-encrypt one secret under a new master key, as one step of moving the
+/// vault off an old key gradually. The current key can't read it afterwards.
+/// With `dry_run`, only check that every secret decrypts with the current key
+/// or, if already re-encrypted, the new one.
+pub fn run(name: &str, yes: bool, dry_run: bool, quiet: bool) -> Result<()> {
+    let vault = Vault::open().context("failed to open vault")?;
+    if !vault.exists(name)? {
+        anyhow::bail!("secret '{}' not found", name);
+    }
+
+    let passphrase = new_passphrase()?;
+    if dry_run {
+        let new_key = vault
+            .key_for(&passphrase)
+            .context("failed to derive the new key")?;
+        let readability = vault
+            .verify_readable(&new_key)
+            .context("the vault has secrets neither key can read; nothing was re-encrypted")?;
+        if !quiet {
+            println!(
+                "{} secrets decrypt with the current key, {} already with the new key",
+                readability.current,
+                readability.migrated.len()
+            );
+            if readability.migrated.iter().any(|moved| moved == name) {
+                println!("'{}' is already re-encrypted; nothing to do", name);
+            } else {
+                println!("Would re-encrypt 1 secret ('{}')", name);
+            }
+        }
+        return Ok(());
+    }
+
+    if !yes
+        && !prompt::confirm(&format!(
+            "Re-encrypt '{}' so only the new master key can read it?",
+            name
+        ))?
+    {
+        anyhow::bail!("reencrypt cancelled");
+    }
+
+    let new_key = vault
+        .key_for(&passphrase)
+        .context("failed to derive the new key")?;
+    vault
+        .reencrypt(name, &new_key)
+        .with_context(|| format!("failed to re-encrypt secret '{}'", name))?;
+
+    if !quiet {
+        println!("Re-encrypted secret: {}", name);
+        eprintln!(
+            "Note: '{}' now needs the new master key; the rest of the vault still uses the current one",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// The new master key, from SECRET_AGENT_NEW_PASSPHRASE or a confirmed prompt
+fn new_passphrase() -> Result<String> {
+    if let Ok(passphrase) = std::env::var(NEW_PASSPHRASE_ENV) {
+        if passphrase.is_empty() {
+            anyhow::bail!("{} is set but empty", NEW_PASSPHRASE_ENV);
+        }
+        return Ok(passphrase);
+    }
+
+    if !atty::is(atty::Stream::Stdin) {
+        anyhow::bail!(
+            "new master key required: run interactively or set {}",
+            NEW_PASSPHRASE_ENV
+        );
+    }
+
+    let passphrase =
+        rpassword::prompt_password("New master key: ").context("failed to read passphrase")?;
+    if passphrase.is_empty() {
+        anyhow::bail!("passphrase cannot be empty");
+    }
+    let again = rpassword::prompt_password("Confirm new master key: ")
+        .context("failed to read passphrase")?;
+    if again != passphrase {
+        anyhow::bail!("passphrases do not match");
+    }
+    Ok(passphrase)
+}
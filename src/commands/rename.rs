@@ -0,0 +1,203 @@
+use crate::vault::{self, Vault};
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::HashMap;
+
+/// How `rename` picks its renames
+pub enum RenameSpec<'a> {
+    /// A single secret, renamed right away
+    Single { old: &'a str, new: &'a str },
+    /// A sed-style `s/PATTERN/REPLACEMENT/[g]` applied to every name;
+    /// only a preview unless `apply` is set
+    Regex { expr: &'a str, apply: bool },
+}
+
+/// Rename secrets, returning the new names of those actually renamed
+pub fn run(spec: &RenameSpec, quiet: bool) -> Result<Vec<String>> {
+    let vault = Vault::open().context("failed to open vault")?;
+
+    let (renames, apply) = match spec {
+        RenameSpec::Single { old, new } => (vec![(old.to_string(), new.to_string())], true),
+        RenameSpec::Regex { expr, apply } => {
+            let substitution = Substitution::parse(expr)?;
+            let names: Vec<String> = vault
+                .list()
+                .context("failed to list secrets")?
+                .into_iter()
+                .map(|secret| secret.name)
+                .collect();
+            let renames = substitution.renames(&names);
+            check_collisions(&renames, &names)?;
+            (renames, *apply)
+        }
+    };
+
+    if renames.is_empty() {
+        if !quiet {
+            println!("No secrets match");
+        }
+        return Ok(Vec::new());
+    }
+
+    if !apply {
+        for (old, new) in &renames {
+            println!("{} -> {}", old, new);
+        }
+        if !quiet {
+            println!(
+                "Would rename {} secrets (pass --apply to rename them)",
+                renames.len()
+            );
+        }
+        return Ok(Vec::new());
+    }
+
+    vault
+        .rename_many(&renames)
+        .context("failed to rename secrets")?;
+
+    if !quiet {
+        for (old, new) in &renames {
+            println!("Renamed {} -> {}", old, new);
+        }
+    }
+    Ok(renames.into_iter().map(|(_, new)| new).collect())
+}
+
+/// A parsed `s/PATTERN/REPLACEMENT/[g]`. Any delimiter can follow the `s`;
+/// the replacement uses regex syntax (`$1`, `${name}`).
+struct Substitution {
+    pattern: Regex,
+    replacement: String,
+    global: bool,
+}
+
+impl Substitution {
+    fn parse(expr: &str) -> Result<Self> {
+        let usage = || {
+            anyhow::anyhow!(
+                "invalid --regex '{}': expected s/PATTERN/REPLACEMENT/ (optionally with a g flag)",
+                expr
+            )
+        };
+
+        let rest = expr.strip_prefix('s').ok_or_else(usage)?;
+        let delimiter = rest.chars().next().ok_or_else(usage)?;
+        if delimiter.is_alphanumeric() || delimiter == '\\' {
+            return Err(usage());
+        }
+        let parts: Vec<&str> = rest[delimiter.len_utf8()..].split(delimiter).collect();
+        let [pattern, replacement, flags] = parts[..] else {
+            return Err(usage());
+        };
+        let global = match flags {
+            "" => false,
+            "g" => true,
+            _ => return Err(usage()),
+        };
+
+        let pattern = Regex::new(pattern)
+            .with_context(|| format!("invalid pattern in --regex '{}'", expr))?;
+        Ok(Substitution {
+            pattern,
+            replacement: replacement.to_string(),
+            global,
+        })
+    }
+
+    /// (old, new) for every name the substitution changes
+    fn renames(&self, names: &[String]) -> Vec<(String, String)> {
+        names
+            .iter()
+            .filter_map(|name| {
+                let new = if self.global {
+                    self.pattern.replace_all(name, self.replacement.as_str())
+                } else {
+                    self.pattern.replace(name, self.replacement.as_str())
+                };
+                (new != name.as_str()).then(|| (name.clone(), new.into_owned()))
+            })
+            .collect()
+    }
+}
+
+/// Reject the whole batch if a new name is invalid, taken by an existing
+/// secret, or produced by more than one rename
+fn check_collisions(renames: &[(String, String)], existing: &[String]) -> Result<()> {
+    let mut problems = Vec::new();
+    let mut sources: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (old, new) in renames {
+        sources.entry(new).or_default().push(old);
+    }
+
+    for (old, new) in renames {
+        if let Err(e) = vault::validate_full_name(new) {
+            problems.push(format!("{} -> {}: {}", old, new, e));
+        } else if existing.contains(new) {
+            problems.push(format!("{} -> {}: '{}' already exists", old, new, new));
+        }
+    }
+    let mut shared: Vec<_> = sources.iter().filter(|(_, olds)| olds.len() > 1).collect();
+    shared.sort();
+    for (new, olds) in shared {
+        problems.push(format!("{} all map to '{}'", olds.join(", "), new));
+    }
+
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "rename would cause collisions, nothing was renamed:\n  {}",
+            problems.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_substitution() {
+        let sub = Substitution::parse("s/^OLD_/NEW_/").unwrap();
+        assert_eq!(
+            sub.renames(&names(&["OLD_A", "KEEP", "prod/OLD_B"])),
+            [("OLD_A".to_string(), "NEW_A".to_string())]
+        );
+
+        // Another delimiter, capture groups and the g flag
+        let sub = Substitution::parse("s|(\\w+)/X|${1}/Y|g").unwrap();
+        assert_eq!(
+            sub.renames(&names(&["dev/X_X"])),
+            [("dev/X_X".to_string(), "dev/Y_X".to_string())]
+        );
+
+        for bad in ["OLD/NEW", "s/OLD/NEW", "s/OLD/NEW/x", "s/(/x/", "sXaXbX"] {
+            assert!(Substitution::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_check_collisions() {
+        let existing = names(&["OLD_A", "OLD_B", "NEW_B"]);
+        let renames = vec![
+            ("OLD_A".to_string(), "NEW_A".to_string()),
+            ("OLD_B".to_string(), "NEW_B".to_string()),
+        ];
+        let err = check_collisions(&renames, &existing).unwrap_err();
+        assert!(err.to_string().contains("'NEW_B' already exists"));
+
+        let renames = vec![
+            ("A_1".to_string(), "A".to_string()),
+            ("A_2".to_string(), "A".to_string()),
+        ];
+        let err = check_collisions(&renames, &[]).unwrap_err();
+        assert!(err.to_string().contains("A_1, A_2 all map to 'A'"));
+
+        let renames = vec![("OLD_A".to_string(), "NEW_A".to_string())];
+        assert!(check_collisions(&renames, &existing).is_ok());
+    }
+}
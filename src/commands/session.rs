@@ -0,0 +1,113 @@
+//! `unlock` / `lock`: cache the passphrase in a background agent
+
+#[cfg(unix)]
+use crate::agent;
+#[cfg(unix)]
+use anyhow::Context;
+use anyhow::Result;
+#[cfg(unix)]
+use std::path::Path;
+
+#[cfg(unix)]
+pub fn unlock(ttl: u64, quiet: bool) -> Result<()> {
+    use serde_json::json;
+
+    let path = agent::socket_path().context("could not determine home directory")?;
+    eprintln!("Enter the vault passphrase to keep unlocked for {}s:", ttl);
+    let key = crate::keychain::read_passphrase()?;
+
+    // Refresh a running agent, otherwise start one
+    let refreshed = agent::request(&path, &json!({ "op": "set", "key": key, "ttl": ttl }))
+        .is_ok_and(|response| response["ok"] == true);
+    if !refreshed {
+        start_agent(&path, &key, ttl)?;
+    }
+
+    if !quiet {
+        println!(
+            "Unlocked for {}s (run `secret-agent lock` to forget sooner)",
+            ttl
+        );
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+pub fn lock(quiet: bool) -> Result<()> {
+    let path = agent::socket_path().context("could not determine home directory")?;
+    let cleared = agent::request(&path, &serde_json::json!({ "op": "clear" })).is_ok();
+    if !quiet {
+        println!("{}", if cleared { "Locked" } else { "Already locked" });
+    }
+    Ok(())
+}
+
+/// Hidden `agent` entry point run by `unlock`; reads the key from stdin
+#[cfg(unix)]
+pub fn serve(ttl: u64) -> Result<()> {
+    use std::io::BufRead;
+
+    let path = agent::socket_path().context("could not determine home directory")?;
+    let mut key = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut key)
+        .context("failed to read key from stdin")?;
+    let key = secrecy::SecretString::from(key.trim_end_matches('\n'));
+    agent::serve(&path, key, std::time::Duration::from_secs(ttl))
+}
+
+/// Start a detached agent and wait until it answers
+// The agent is meant to outlive us; it is reparented to init when we exit
+#[cfg(unix)]
+#[allow(clippy::zombie_processes)]
+fn start_agent(path: &Path, key: &str, ttl: u64) -> Result<()> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    use std::time::{Duration, Instant};
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+
+    let exe = std::env::current_exe().context("failed to locate secret-agent binary")?;
+    let mut cmd = Command::new(exe);
+    cmd.args(["agent", "--ttl"])
+        .arg(ttl.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // Keep the agent alive if the terminal sends Ctrl-C to our group
+    crate::signals::isolate(&mut cmd);
+    let mut child = cmd.spawn().context("failed to start agent")?;
+    {
+        // The key goes over a pipe so it never appears in the process list
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        writeln!(stdin, "{}", key).context("failed to hand key to agent")?;
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+        if agent::request(path, &serde_json::json!({ "op": "get" })).is_ok() {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    anyhow::bail!("agent did not start listening on {}", path.display())
+}
+
+#[cfg(not(unix))]
+pub fn unlock(_ttl: u64, _quiet: bool) -> Result<()> {
+    anyhow::bail!("unlock requires Unix domain sockets")
+}
+
+#[cfg(not(unix))]
+pub fn lock(_quiet: bool) -> Result<()> {
+    anyhow::bail!("lock requires Unix domain sockets")
+}
+
+#[cfg(not(unix))]
+pub fn serve(_ttl: u64) -> Result<()> {
+    anyhow::bail!("the agent requires Unix domain sockets")
+}
@@ -1,6 +1,6 @@
 use anyhow::{Context, Result};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const MARKER: &str = "## Secrets Management (secret-agent)";
 
@@ -26,13 +26,22 @@ secret-agent exec --env MY_SECRET:OPENAI_API_KEY python app.py
 
 # Template secrets into command strings
 secret-agent exec curl -H 'Authorization: Bearer {{API_KEY}}' https://api.example.com
+
+# Skip the shell entirely: args are passed literally, {{}} still works per arg
+secret-agent exec --no-shell -- mysql --password={{DB_PASS}} -e 'SELECT 1; SELECT 2'
+
+# Put a secret into one argv slot (0 = program), no shell involved
+secret-agent exec --secret-arg 3=DB_PASS -- mysql -u root --password=__SECRET__
+
+# Read argv from a JSON array (each arg templated individually, no shell)
+secret-agent exec --args-file args.json
 ```
 
 ### Create secrets
 ```bash
 secret-agent create API_KEY                     # 32-char alphanumeric (default)
 secret-agent create API_KEY --length 64         # Custom length
-secret-agent create API_KEY --charset hex       # hex | base64 | ascii | alphanumeric
+secret-agent create API_KEY --charset hex       # hex | base64 | ascii | alphanumeric | printable | yaml-safe | numeric | base58 | uuid
 secret-agent create API_KEY --force             # Overwrite existing
 ```
 
@@ -41,6 +50,12 @@ secret-agent create API_KEY --force             # Overwrite existing
 # From clipboard (clears after reading)
 secret-agent import OPENAI_KEY --clipboard
 
+# Linux: read the primary (middle-click) selection instead
+secret-agent import OPENAI_KEY --clipboard --primary
+
+# Drop a URL line the browser appended to the copied token
+secret-agent import OPENAI_KEY --clipboard --strip-trailing-url
+
 # From stdin (single-line)
 echo "sk-..." | secret-agent import API_KEY
 
@@ -65,6 +80,7 @@ Note: `--env` is the correct way to use multiline secrets. The `{{PLACEHOLDER}}`
 secret-agent list                    # All secrets
 secret-agent list --bucket prod      # Only secrets in a bucket
 secret-agent delete OLD_SECRET       # Remove permanently
+secret-agent delete A B --if-exists  # Several at once, skipping missing ones
 ```
 
 ### Buckets for organizing secrets
@@ -79,6 +95,15 @@ secret-agent list --bucket prod
 
 # Bucket prefix is stripped for env vars
 secret-agent exec --env prod/API_KEY node app.js  # env var = API_KEY
+
+# Buckets can nest; --bucket company/prod also lists company/prod/api/...
+secret-agent exec --env company/prod/api/KEY ./run  # env var = KEY
+
+# Default bucket for -e specs without one (reads prod/API_KEY, prod/DB_PASS)
+secret-agent exec --bucket prod -e API_KEY -e DB_PASS -- ./app
+
+# Inject every secret in a bucket
+secret-agent exec --env-bucket dev -- npm run dev
 ```
 
 ### Write secrets to files
@@ -110,6 +135,9 @@ secret-agent env export --file .env --all
 # Agent can put a secret in the user's clipboard without ever seeing the value
 secret-agent get API_KEY --clipboard
 
+# Clear it again after 30s (left alone if something else was copied since)
+secret-agent get API_KEY --clipboard --clear-after 30
+
 # Works on macOS (pbcopy) and Linux (X11/Wayland)
 ```
 
@@ -121,6 +149,8 @@ secret-agent get API_KEY --unsafe-display
 
 ### Global flags
 - `-q, --quiet` — Suppress informational output (for scripting)
+- `--porcelain` — Print `ok NAME` on success for create/import/delete (implies `--quiet`)
+- `--no-tips` — Skip the setup tip on stderr without silencing other output (or set `SECRET_AGENT_NO_TIPS=1`)
 
 ### Avoiding Keychain Prompts
 
@@ -131,46 +161,162 @@ export SECRET_AGENT_USE_FILE=1
 This uses `~/.secret-agent/master.key` instead of the system keychain.
 "#;
 
-fn claude_md_path() -> Option<PathBuf> {
-    let home = dirs::home_dir()?;
-    Some(home.join(".claude").join("CLAUDE.md"))
+/// Where setup writes the instructions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    /// ~/.claude/CLAUDE.md, or ./CLAUDE.md with --local
+    Claude,
+    /// ~/.cursor/rules/secret-agent.mdc, or under ./.cursor with --local
+    Cursor,
+    /// AGENTS.md in the current project
+    Agents,
 }
 
-/// Returns true if setup has already been completed.
+impl Target {
+    const ALL: [Target; 3] = [Target::Claude, Target::Cursor, Target::Agents];
+
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "claude" => Ok(Target::Claude),
+            "cursor" => Ok(Target::Cursor),
+            "agents" => Ok(Target::Agents),
+            _ => anyhow::bail!(
+                "unknown setup target '{}' (use claude, cursor or agents)",
+                name
+            ),
+        }
+    }
+
+    /// The instructions file, given the home directory and the project
+    /// directory. `local` puts per-user files in the project instead.
+    fn path_in(self, home: &Path, project: &Path, local: bool) -> PathBuf {
+        match (self, local) {
+            (Target::Claude, false) => home.join(".claude").join("CLAUDE.md"),
+            (Target::Claude, true) => project.join("CLAUDE.md"),
+            (Target::Cursor, false) => home.join(".cursor").join("rules").join("secret-agent.mdc"),
+            (Target::Cursor, true) => project
+                .join(".cursor")
+                .join("rules")
+                .join("secret-agent.mdc"),
+            (Target::Agents, _) => project.join("AGENTS.md"),
+        }
+    }
+
+    fn path(self, local: bool) -> Option<PathBuf> {
+        let home = dirs::home_dir()?;
+        let project = std::env::current_dir().ok()?;
+        Some(self.path_in(&home, &project, local))
+    }
+}
+
+/// Returns true if setup has already been completed for any target,
+/// globally or in the current project.
 pub fn is_configured() -> bool {
-    let Some(path) = claude_md_path() else {
-        return false;
-    };
+    Target::ALL
+        .iter()
+        .flat_map(|target| [target.path(false), target.path(true)])
+        .flatten()
+        .any(|path| has_marker(&path))
+}
+
+fn has_marker(path: &Path) -> bool {
     match fs::read_to_string(path) {
         Ok(contents) => contents.contains(MARKER),
         Err(_) => false,
     }
 }
 
-pub fn run(print: bool, quiet: bool) -> Result<()> {
+pub fn run(print: bool, targets: &[String], local: bool, quiet: bool) -> Result<()> {
     if print {
         print!("{CLAUDE_INSTRUCTIONS}");
         return Ok(());
     }
 
-    let path = claude_md_path().context("Could not determine home directory")?;
+    for target in targets {
+        let path = Target::parse(target)?
+            .path(local)
+            .context("Could not determine home or current directory")?;
+        install_at(&path, quiet)?;
+    }
+    Ok(())
+}
+
+/// Remove the instructions from every target file that has them
+pub fn remove_instructions(quiet: bool) -> Result<()> {
+    let paths = Target::ALL
+        .iter()
+        .flat_map(|target| [target.path(false), target.path(true)])
+        .flatten();
+    for path in paths {
+        let Ok(contents) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let Some(remaining) = remove_block(&contents) else {
+            continue;
+        };
+        if remaining.is_empty() {
+            fs::remove_file(&path)
+        } else {
+            fs::write(&path, remaining)
+        }
+        .with_context(|| format!("Failed to update {}", path.display()))?;
+        if !quiet {
+            eprintln!("Removed secret-agent instructions from {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Cut the block starting at `MARKER` up to the next `#` or `##` heading
+/// outside a code fence (the block's own code samples have `# comments`)
+fn remove_block(contents: &str) -> Option<String> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let start = lines.iter().position(|line| line.trim_end() == MARKER)?;
+
+    let mut in_fence = false;
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| {
+            if line.trim_start().starts_with("```") {
+                in_fence = !in_fence;
+                return false;
+            }
+            !in_fence && (line.starts_with("# ") || line.starts_with("## "))
+        })
+        .map_or(lines.len(), |offset| start + 1 + offset);
 
-    // Create ~/.claude/ if needed
+    // Also drop the blank separator line added by install_at
+    let mut before = &lines[..start];
+    while before.last().is_some_and(|line| line.trim().is_empty()) {
+        before = &before[..before.len() - 1];
+    }
+    let after = &lines[end..];
+
+    let mut remaining = before.join("\n");
+    if !before.is_empty() && !after.is_empty() {
+        remaining.push_str("\n\n");
+    }
+    remaining.push_str(&after.join("\n"));
+    if !remaining.is_empty() {
+        remaining.push('\n');
+    }
+    Some(remaining)
+}
+
+/// Append the instructions to `path` unless they are already there
+fn install_at(path: &Path, quiet: bool) -> Result<()> {
+    // Create the parent directory (e.g. ~/.claude/) if needed
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)
             .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
     }
 
     // Check for existing instructions
-    if path.exists() {
-        let contents = fs::read_to_string(&path)
-            .with_context(|| format!("Failed to read {}", path.display()))?;
-        if contents.contains(MARKER) {
-            if !quiet {
-                eprintln!("Already configured in {}", path.display());
-            }
-            return Ok(());
+    if has_marker(path) {
+        if !quiet {
+            eprintln!("Already configured in {}", path.display());
         }
+        return Ok(());
     }
 
     // Append with a leading newline separator
@@ -180,10 +326,9 @@ pub fn run(print: bool, quiet: bool) -> Result<()> {
     fs::OpenOptions::new()
         .create(true)
         .append(true)
-        .open(&path)
-        .and_then(|_| {
+        .open(path)
+        .and_then(|mut f| {
             use std::io::Write;
-            let mut f = fs::OpenOptions::new().append(true).open(&path)?;
             f.write_all(content_to_append.as_bytes())
         })
         .with_context(|| format!("Failed to write to {}", path.display()))?;
@@ -202,41 +347,12 @@ mod tests {
     use tempfile::TempDir;
 
     // Helper to override the path for testing
-    fn run_with_path(print: bool, quiet: bool, path: &PathBuf) -> Result<()> {
+    fn run_with_path(print: bool, quiet: bool, path: &Path) -> Result<()> {
         if print {
             print!("{CLAUDE_INSTRUCTIONS}");
             return Ok(());
         }
-
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-
-        if path.exists() {
-            let contents = fs::read_to_string(path)?;
-            if contents.contains(MARKER) {
-                if !quiet {
-                    eprintln!("Already configured in {}", path.display());
-                }
-                return Ok(());
-            }
-        }
-
-        let mut content_to_append = String::from("\n");
-        content_to_append.push_str(CLAUDE_INSTRUCTIONS);
-
-        use std::io::Write;
-        let mut f = fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(path)?;
-        f.write_all(content_to_append.as_bytes())?;
-
-        if !quiet {
-            eprintln!("Added secret-agent instructions to {}", path.display());
-        }
-
-        Ok(())
+        install_at(path, quiet)
     }
 
     #[test]
@@ -283,6 +399,72 @@ mod tests {
         assert_eq!(first, second);
     }
 
+    #[test]
+    fn test_cursor_target_path() {
+        let home = Path::new("/home/me");
+        let project = Path::new("/work/app");
+
+        assert_eq!(
+            Target::parse("cursor")
+                .unwrap()
+                .path_in(home, project, false),
+            Path::new("/home/me/.cursor/rules/secret-agent.mdc")
+        );
+        assert_eq!(
+            Target::parse("agents")
+                .unwrap()
+                .path_in(home, project, false),
+            Path::new("/work/app/AGENTS.md")
+        );
+        assert_eq!(
+            Target::Claude.path_in(home, project, true),
+            Path::new("/work/app/CLAUDE.md")
+        );
+        assert!(Target::parse("vim").is_err());
+    }
+
+    #[test]
+    fn test_cursor_target_idempotent() {
+        let tmp = TempDir::new().unwrap();
+        let path = Target::Cursor.path_in(tmp.path(), tmp.path(), false);
+
+        install_at(&path, true).unwrap();
+        let first = fs::read_to_string(&path).unwrap();
+        assert!(first.contains(MARKER));
+
+        install_at(&path, true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), first);
+    }
+
+    #[test]
+    fn test_remove_block_keeps_surrounding_content() {
+        let contents = format!(
+            "# My notes\n\nKeep this.\n\n{}\n## Other tool\n\nKeep this too.\n",
+            CLAUDE_INSTRUCTIONS
+        );
+
+        let remaining = remove_block(&contents).unwrap();
+
+        assert_eq!(
+            remaining,
+            "# My notes\n\nKeep this.\n\n## Other tool\n\nKeep this too.\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_block_after_install() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("CLAUDE.md");
+        fs::write(&path, "# Existing content\n").unwrap();
+        install_at(&path, true).unwrap();
+
+        let remaining = remove_block(&fs::read_to_string(&path).unwrap()).unwrap();
+
+        assert_eq!(remaining, "# Existing content\n");
+        assert_eq!(remove_block(CLAUDE_INSTRUCTIONS).unwrap(), "");
+        assert!(remove_block("# No instructions here\n").is_none());
+    }
+
     #[test]
     fn test_instructions_content() {
         assert!(CLAUDE_INSTRUCTIONS.contains(MARKER));
@@ -0,0 +1,37 @@
+use crate::commands::setup;
+use crate::keychain;
+use crate::prompt;
+use crate::vault;
+use anyhow::{Context, Result};
+
+pub fn run(yes: bool, keep_vault: bool, quiet: bool) -> Result<()> {
+    let question = if keep_vault {
+        "Remove the secret-agent instructions from your agent config files?"
+    } else {
+        "Delete the vault, its master key and the agent instructions? Secrets cannot be recovered."
+    };
+    if !yes && !prompt::confirm(question)? {
+        anyhow::bail!("uninstall cancelled");
+    }
+
+    // The key stays with the vault; without it the kept secrets are unreadable
+    if !keep_vault {
+        let path = vault::get_vault_path().context("failed to locate vault")?;
+        for suffix in ["", "-wal", "-shm"] {
+            let file = format!("{}{}", path.display(), suffix);
+            match std::fs::remove_file(&file) {
+                Ok(()) if !quiet => println!("Deleted {}", file),
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e).with_context(|| format!("failed to delete {}", file)),
+            }
+        }
+        keychain::delete_master_key().context("failed to delete master key")?;
+        if !quiet {
+            println!("Deleted master key");
+        }
+    }
+
+    setup::remove_instructions(quiet)?;
+    Ok(())
+}
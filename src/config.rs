@@ -0,0 +1,110 @@
+use crate::error::{Error, Result};
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// User configuration loaded from ~/.secret-agent/config.toml
+///
+/// ```toml
+/// # Bucket for bucket-less names passed to `create` and `import`
+/// default_bucket = "dev"
+///
+/// [exec]
+/// deny_env = ["AWS_SECRET_ACCESS_KEY"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub default_bucket: Option<String>,
+    pub exec: ExecConfig,
+}
+
+const DEFAULT_BUCKET_ENV: &str = "SECRET_AGENT_DEFAULT_BUCKET";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecConfig {
+    /// Host environment variables stripped from every `exec` child
+    pub deny_env: Vec<String>,
+}
+
+impl Config {
+    /// Load the config file, or defaults if it doesn't exist
+    pub fn load() -> Result<Self> {
+        let path = get_config_path()?;
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        Self::parse(&content).map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))
+    }
+
+    fn parse(content: &str) -> std::result::Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    /// The bucket for new bucket-less names: SECRET_AGENT_DEFAULT_BUCKET,
+    /// else `default_bucket` from the config file
+    pub fn default_bucket() -> Result<Option<String>> {
+        match std::env::var(DEFAULT_BUCKET_ENV) {
+            Ok(bucket) if !bucket.is_empty() => Ok(Some(bucket)),
+            _ => Ok(Self::load()?.default_bucket.filter(|b| !b.is_empty())),
+        }
+    }
+}
+
+fn get_config_path() -> Result<PathBuf> {
+    // Allow override via environment variable (useful for testing)
+    if let Ok(path) = std::env::var("SECRET_AGENT_CONFIG_PATH") {
+        return Ok(PathBuf::from(path));
+    }
+
+    let home = dirs::home_dir().ok_or_else(|| {
+        Error::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "could not determine home directory",
+        ))
+    })?;
+
+    Ok(home.join(".secret-agent").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_deny_env() {
+        let config = Config::parse(
+            r#"
+            [exec]
+            deny_env = ["AWS_SECRET_ACCESS_KEY", "GITHUB_TOKEN"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.exec.deny_env,
+            vec!["AWS_SECRET_ACCESS_KEY", "GITHUB_TOKEN"]
+        );
+    }
+
+    #[test]
+    fn test_parse_default_bucket() {
+        let config = Config::parse("default_bucket = \"dev\"").unwrap();
+        assert_eq!(config.default_bucket.as_deref(), Some("dev"));
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        let config = Config::parse("").unwrap();
+        assert!(config.exec.deny_env.is_empty());
+        assert!(config.default_bucket.is_none());
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_keys() {
+        assert!(Config::parse("[exec]\ndeny = [\"X\"]").is_err());
+    }
+}
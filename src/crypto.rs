@@ -1,11 +1,167 @@
 use crate::error::{Error, Result};
 use age::secrecy::SecretString;
+use bech32::{ToBase32, Variant};
+use sha2::{Digest, Sha256};
 use std::io::{Read, Write};
+use std::str::FromStr;
 
-/// Encrypt plaintext using age with a passphrase (scrypt-based)
+/// scrypt cost (log2 of the iterations) for deriving the key of a new vault:
+/// 2^18 and 256 MiB, about a second, which is what age's own passphrase
+/// encryption calibrates to. The master key may be a human passphrase
+/// (SECRET_AGENT_PASSPHRASE), and the vault file is all an attacker needs to
+/// guess at it, so this must cost no less than the per-value scrypt it
+/// replaces. It is paid once per process rather than once per value.
+pub const VAULT_KEY_LOG_N: u8 = 18;
+
+#[cfg(test)]
+thread_local! {
+    /// How many times `VaultKey::derive` has run scrypt on this thread
+    pub static DERIVATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// How a vault stores its values, recorded in the vault so each one keeps
+/// the format it was created with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueFormat {
+    /// Each value is age-encrypted with the master key as a passphrase, which
+    /// runs scrypt per value. Vaults from before the vault key keep this
+    /// format, so older versions can still read them.
+    Passphrase,
+    /// Values are encrypted to the X25519 vault key
+    VaultKey,
+}
+
+/// Key for encrypting vault values, derived from the master key once per process.
+///
+/// Passphrase-encrypted age values run scrypt with a fresh random salt on every
+/// encrypt and decrypt, so its cost can't be shared between values. Instead the
+/// master key is stretched once (with a per-vault salt) into an age X25519
+/// identity, and values are encrypted to that identity, which is cheap.
+/// Values written with the passphrase recipient always decrypt, at the old
+/// per-value cost.
+pub struct VaultKey {
+    identity: age::x25519::Identity,
+    passphrase: SecretString,
+    hash_key: [u8; 32],
+    format: ValueFormat,
+}
+
+impl VaultKey {
+    /// Run scrypt over `master_key` and `salt` at a cost of 2^`log_n` to
+    /// derive the vault identity. `format` decides how `encrypt` writes.
+    pub fn derive(master_key: &str, salt: &[u8], log_n: u8, format: ValueFormat) -> Result<Self> {
+        #[cfg(test)]
+        DERIVATIONS.with(|n| n.set(n.get() + 1));
+        let params =
+            scrypt::Params::new(log_n, 8, 1, 32).map_err(|e| Error::Encryption(e.to_string()))?;
+        let mut key = [0u8; 32];
+        scrypt::scrypt(master_key.as_bytes(), salt, &params, &mut key)
+            .map_err(|e| Error::Encryption(e.to_string()))?;
+
+        // age only builds X25519 identities from their Bech32 string form
+        let encoded = bech32::encode("age-secret-key-", key.to_base32(), Variant::Bech32)
+            .map_err(|e| Error::Encryption(e.to_string()))?;
+        // A separate key for value hashes, so they reveal nothing without the master key
+        let hash_key = Sha256::new()
+            .chain_update(b"secret-agent value hash")
+            .chain_update(key)
+            .finalize()
+            .into();
+        key.fill(0);
+        let identity = age::x25519::Identity::from_str(&encoded.to_uppercase())
+            .map_err(|e| Error::Encryption(e.to_string()))?;
+
+        Ok(Self {
+            identity,
+            passphrase: SecretString::from(master_key.to_owned()),
+            hash_key,
+            format,
+        })
+    }
+
+    /// Keyed SHA-256 of a value, hex encoded, for finding equal values
+    /// without decrypting them
+    pub fn value_hash(&self, value: &[u8]) -> String {
+        let digest = Sha256::new()
+            .chain_update(self.hash_key)
+            .chain_update(value)
+            .finalize();
+        format!("{:x}", digest)
+    }
+
+    /// Encrypt a value in the vault's format: to the vault identity, or with
+    /// the master key as a passphrase for vaults that predate it
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        if self.format == ValueFormat::Passphrase {
+            let encryptor = age::Encryptor::with_user_passphrase(self.passphrase.clone());
+            return write_encrypted(encryptor, plaintext);
+        }
+        let recipient = self.identity.to_public();
+        let encryptor =
+            age::Encryptor::with_recipients(std::iter::once(&recipient as &dyn age::Recipient))
+                .map_err(|e| Error::Encryption(e.to_string()))?;
+        write_encrypted(encryptor, plaintext)
+    }
+
+    /// Decrypt a value written by `encrypt`, or by the older passphrase scheme
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let decryptor =
+            age::Decryptor::new(ciphertext).map_err(|e| Error::Decryption(e.to_string()))?;
+
+        if decryptor.is_scrypt() {
+            let identity = age::scrypt::Identity::new(self.passphrase.clone());
+            read_decrypted(decryptor, &identity)
+        } else {
+            read_decrypted(decryptor, &self.identity)
+        }
+    }
+}
+
+impl Drop for VaultKey {
+    fn drop(&mut self) {
+        self.hash_key.fill(0);
+    }
+}
+
+/// Encrypt plaintext using age with a passphrase (scrypt-based).
+/// Only tests still write this per-value format.
+#[cfg(test)]
 pub fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
     let encryptor = age::Encryptor::with_user_passphrase(SecretString::from(passphrase.to_owned()));
+    write_encrypted(encryptor, plaintext)
+}
 
+/// Decrypt ciphertext using age with a passphrase (scrypt-based)
+#[cfg(test)]
+pub fn decrypt(ciphertext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let decryptor =
+        age::Decryptor::new(ciphertext).map_err(|e| Error::Decryption(e.to_string()))?;
+    let identity = age::scrypt::Identity::new(SecretString::from(passphrase.to_owned()));
+    read_decrypted(decryptor, &identity)
+}
+
+/// Start a passphrase-encrypted age stream into `output`, for data too large
+/// to hold in memory. Call `finish` on the returned writer to complete it.
+pub fn encrypt_writer<W: Write>(
+    output: W,
+    passphrase: &str,
+) -> Result<age::stream::StreamWriter<W>> {
+    age::Encryptor::with_user_passphrase(SecretString::from(passphrase.to_owned()))
+        .wrap_output(output)
+        .map_err(|e| Error::Encryption(e.to_string()))
+}
+
+/// Read a stream written by `encrypt_writer`, decrypting as it is read.
+/// A wrong passphrase is reported here, before any plaintext is produced.
+pub fn decrypt_reader<R: Read>(input: R, passphrase: &str) -> Result<age::stream::StreamReader<R>> {
+    let decryptor = age::Decryptor::new(input).map_err(|e| Error::Decryption(e.to_string()))?;
+    let identity = age::scrypt::Identity::new(SecretString::from(passphrase.to_owned()));
+    decryptor
+        .decrypt(std::iter::once(&identity as &dyn age::Identity))
+        .map_err(|e| Error::Decryption(e.to_string()))
+}
+
+fn write_encrypted(encryptor: age::Encryptor, plaintext: &[u8]) -> Result<Vec<u8>> {
     let mut encrypted = vec![];
     let mut writer = encryptor
         .wrap_output(&mut encrypted)
@@ -22,17 +178,13 @@ pub fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
     Ok(encrypted)
 }
 
-/// Decrypt ciphertext using age with a passphrase (scrypt-based)
-pub fn decrypt(ciphertext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
-    let decryptor =
-        age::Decryptor::new(ciphertext).map_err(|e| Error::Decryption(e.to_string()))?;
-
+fn read_decrypted(
+    decryptor: age::Decryptor<&[u8]>,
+    identity: &dyn age::Identity,
+) -> Result<Vec<u8>> {
     let mut decrypted = vec![];
     let mut reader = decryptor
-        .decrypt(std::iter::once(
-            &age::scrypt::Identity::new(SecretString::from(passphrase.to_owned()))
-                as &dyn age::Identity,
-        ))
+        .decrypt(std::iter::once(identity))
         .map_err(|e| Error::Decryption(e.to_string()))?;
 
     reader
@@ -46,6 +198,13 @@ pub fn decrypt(ciphertext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
 mod tests {
     use super::*;
 
+    /// Cheap enough to derive in every test
+    const TEST_LOG_N: u8 = 10;
+
+    fn test_key(master_key: &str, salt: &[u8]) -> VaultKey {
+        VaultKey::derive(master_key, salt, TEST_LOG_N, ValueFormat::VaultKey).unwrap()
+    }
+
     #[test]
     fn test_encrypt_decrypt_roundtrip() {
         let plaintext = b"my-secret-value";
@@ -58,6 +217,114 @@ mod tests {
         assert_eq!(decrypted, plaintext);
     }
 
+    #[test]
+    fn test_stream_roundtrip_in_chunks() {
+        // Several age chunks (64 KiB each), written and read piece by piece
+        let chunk: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        let mut encrypted = Vec::new();
+        let mut writer = encrypt_writer(&mut encrypted, "stream-passphrase").unwrap();
+        for _ in 0..50 {
+            writer.write_all(&chunk).unwrap();
+        }
+        writer.finish().unwrap();
+
+        // The whole file at once matches the buffered API
+        assert_eq!(
+            decrypt(&encrypted, "stream-passphrase").unwrap().len(),
+            500_000
+        );
+
+        let mut reader = decrypt_reader(encrypted.as_slice(), "stream-passphrase").unwrap();
+        let mut buf = vec![0u8; chunk.len()];
+        for _ in 0..50 {
+            reader.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, chunk);
+        }
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+        assert!(decrypt_reader(encrypted.as_slice(), "wrong").is_err());
+    }
+
+    #[test]
+    fn test_vault_key_roundtrip() {
+        let key = test_key("master-key", b"salt-salt-salt!!");
+
+        let encrypted = key.encrypt(b"my-secret-value").unwrap();
+        assert_eq!(key.decrypt(&encrypted).unwrap(), b"my-secret-value");
+
+        // Same master key and salt derive the same identity
+        let again = test_key("master-key", b"salt-salt-salt!!");
+        assert_eq!(again.decrypt(&encrypted).unwrap(), b"my-secret-value");
+    }
+
+    #[test]
+    fn test_vault_key_wrong_key_or_salt_fails() {
+        let key = test_key("master-key", b"salt-salt-salt!!");
+        let encrypted = key.encrypt(b"my-secret-value").unwrap();
+
+        let other_key = test_key("other-key", b"salt-salt-salt!!");
+        assert!(other_key.decrypt(&encrypted).is_err());
+
+        let other_salt = test_key("master-key", b"different-salt!!");
+        assert!(other_salt.decrypt(&encrypted).is_err());
+    }
+
+    #[test]
+    fn test_value_hash_is_keyed() {
+        let key = test_key("master-key", b"salt-salt-salt!!");
+        assert_eq!(key.value_hash(b"same"), key.value_hash(b"same"));
+        assert_ne!(key.value_hash(b"same"), key.value_hash(b"other"));
+
+        let other = test_key("other-key", b"salt-salt-salt!!");
+        assert_ne!(key.value_hash(b"same"), other.value_hash(b"same"));
+    }
+
+    #[test]
+    fn test_vault_key_reads_passphrase_values() {
+        // Values written by older versions used the master key as an age passphrase
+        let legacy = encrypt(b"legacy-value", "master-key").unwrap();
+
+        let key = test_key("master-key", b"salt-salt-salt!!");
+        assert_eq!(key.decrypt(&legacy).unwrap(), b"legacy-value");
+    }
+
+    #[test]
+    fn test_vault_key_derives_once() {
+        let before = DERIVATIONS.with(|n| n.get());
+        let key = test_key("master-key", b"salt-salt-salt!!");
+
+        for i in 0..20 {
+            let value = format!("value-{}", i);
+            let encrypted = key.encrypt(value.as_bytes()).unwrap();
+            // No scrypt stanza, so age doesn't run scrypt per value either
+            assert!(!age::Decryptor::new(encrypted.as_slice())
+                .unwrap()
+                .is_scrypt());
+            assert_eq!(key.decrypt(&encrypted).unwrap(), value.as_bytes());
+        }
+
+        assert_eq!(DERIVATIONS.with(|n| n.get()) - before, 1);
+    }
+
+    #[test]
+    fn test_passphrase_format_writes_readable_legacy_values() {
+        let key = VaultKey::derive(
+            "master-key",
+            b"salt-salt-salt!!",
+            TEST_LOG_N,
+            ValueFormat::Passphrase,
+        )
+        .unwrap();
+
+        // What older versions wrote, so they can still read it
+        let encrypted = key.encrypt(b"compatible").unwrap();
+        assert!(age::Decryptor::new(encrypted.as_slice())
+            .unwrap()
+            .is_scrypt());
+        assert_eq!(decrypt(&encrypted, "master-key").unwrap(), b"compatible");
+        assert_eq!(key.decrypt(&encrypted).unwrap(), b"compatible");
+    }
+
     #[test]
     fn test_wrong_passphrase_fails() {
         let plaintext = b"my-secret-value";
@@ -0,0 +1,162 @@
+//! Pass secrets to a child through an inherited pipe instead of its environment.
+//!
+//! Wire format: the child finds the read end's descriptor number in
+//! `SECRET_AGENT_ENV_FD`. The pipe carries `NAME=value` entries, each
+//! terminated by a NUL byte (the layout of `/proc/<pid>/environ`), sorted by
+//! name, followed by EOF. Values may contain newlines and `=`; only NUL is
+//! impossible, since it can't appear in an environment variable either.
+
+use std::collections::HashMap;
+use std::io;
+use std::process::Command;
+
+/// Variable holding the descriptor number the child reads from
+pub const VAR: &str = "SECRET_AGENT_ENV_FD";
+
+/// Serialize `vars` in the wire format
+pub fn encode(vars: &HashMap<String, String>) -> Vec<u8> {
+    let mut names: Vec<&String> = vars.keys().collect();
+    names.sort();
+
+    let mut payload = Vec::new();
+    for name in names {
+        payload.extend_from_slice(name.as_bytes());
+        payload.push(b'=');
+        payload.extend_from_slice(vars[name].as_bytes());
+        payload.push(0);
+    }
+    payload
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::VAR;
+    use secrecy::zeroize::Zeroize;
+    use std::io::{self, PipeWriter, Write};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    /// Lowest descriptor number for the child's end, clear of stdio and of
+    /// low descriptors the command may redirect itself
+    const MIN_FD: RawFd = 100;
+
+    /// The parent's side of the pipe, until the child has been spawned
+    pub struct EnvFd {
+        read: OwnedFd,
+        write: PipeWriter,
+        payload: Vec<u8>,
+    }
+
+    pub fn attach(cmd: &mut Command, payload: Vec<u8>) -> io::Result<EnvFd> {
+        // Both ends are close-on-exec, so other children never inherit them
+        let (read, write) = io::pipe()?;
+
+        // Move the read end up high; the copy stays close-on-exec until
+        // pre_exec clears the flag in the child
+        // SAFETY: F_DUPFD_CLOEXEC on a valid descriptor returns a new one we own
+        let high = unsafe { libc::fcntl(read.as_raw_fd(), libc::F_DUPFD_CLOEXEC, MIN_FD) };
+        if high < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        drop(read);
+        // SAFETY: fcntl() just returned this descriptor
+        let read = unsafe { OwnedFd::from_raw_fd(high) };
+
+        cmd.env(VAR, high.to_string());
+        // SAFETY: fcntl() is async-signal-safe, as pre_exec requires
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::fcntl(high, libc::F_SETFD, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        Ok(EnvFd {
+            read,
+            write,
+            payload,
+        })
+    }
+
+    impl EnvFd {
+        /// Close our copy of the read end and write the payload from a
+        /// thread, so a child that never reads can't block us. Once the
+        /// child exits the write fails with EPIPE and the thread ends.
+        pub fn send(self) {
+            let EnvFd {
+                read,
+                mut write,
+                mut payload,
+            } = self;
+            drop(read);
+            std::thread::spawn(move || {
+                let _ = write.write_all(&payload);
+                payload.zeroize();
+            });
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::io;
+    use std::process::Command;
+
+    pub struct EnvFd;
+
+    pub fn attach(_cmd: &mut Command, _payload: Vec<u8>) -> io::Result<EnvFd> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--env-fd is only supported on Unix",
+        ))
+    }
+
+    impl EnvFd {
+        pub fn send(self) {}
+    }
+}
+
+pub use imp::EnvFd;
+
+/// Arrange for `cmd` to inherit a pipe carrying `vars`. Call `EnvFd::send`
+/// once the child has been spawned.
+pub fn attach(cmd: &mut Command, vars: &HashMap<String, String>) -> io::Result<EnvFd> {
+    imp::attach(cmd, encode(vars))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_is_nul_terminated_and_sorted() {
+        let vars = HashMap::from([
+            ("B".to_string(), "multi\nline=x".to_string()),
+            ("A".to_string(), "one".to_string()),
+        ]);
+        assert_eq!(encode(&vars), b"A=one\0B=multi\nline=x\0");
+        assert!(encode(&HashMap::new()).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_child_reads_vars_from_fd() {
+        let vars = HashMap::from([("TOKEN".to_string(), "from-the-pipe".to_string())]);
+        let mut cmd = Command::new("sh");
+        cmd.args([
+            "-c",
+            "cat /dev/fd/$SECRET_AGENT_ENV_FD; printf '[%s]' \"$TOKEN\"",
+        ])
+        .stdout(std::process::Stdio::piped());
+
+        let env_fd = attach(&mut cmd, &vars).unwrap();
+        let child = cmd.spawn().unwrap();
+        env_fd.send();
+
+        let output = child.wait_with_output().unwrap();
+        assert_eq!(output.stdout, b"TOKEN=from-the-pipe\0[]");
+    }
+}
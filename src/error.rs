@@ -20,11 +20,29 @@ pub enum Error {
     #[error("keychain error: {0}")]
     Keychain(String),
 
+    #[error("keychain access was denied; set SECRET_AGENT_USE_FILE=1 or SECRET_AGENT_PASSPHRASE to use a key without the keychain")]
+    KeychainDenied,
+
+    #[error("master key mismatch: {0}")]
+    KeyMismatch(String),
+
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
 
     #[error("invalid secret name: {0}")]
     InvalidSecretName(String),
+
+    #[error("vault database at {0} is corrupt or unreadable; restore it from a backup (the file was left untouched)")]
+    VaultCorrupt(std::path::PathBuf),
+
+    #[error("invalid config: {0}")]
+    Config(String),
+}
+
+impl Error {
+    /// Exit code for `KeychainDenied`, so scripts can tell a declined
+    /// keychain prompt from other failures
+    pub const KEYCHAIN_DENIED_EXIT_CODE: i32 = 4;
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,56 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Write `content` to `path` unless the file already holds exactly that content.
+/// Skipping identical writes preserves the mtime so file watchers aren't triggered.
+/// Returns true if the file was written.
+pub fn write_if_changed(path: &Path, content: &str, always_write: bool) -> io::Result<bool> {
+    if !always_write {
+        if let Ok(existing) = fs::read(path) {
+            if existing == content.as_bytes() {
+                return Ok(false);
+            }
+        }
+    }
+
+    fs::write(path, content)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_write_if_changed_skips_identical_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        fs::write(&path, "KEY=value\n").unwrap();
+
+        let old_mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(old_mtime)
+            .unwrap();
+
+        assert!(!write_if_changed(&path, "KEY=value\n", false).unwrap());
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), old_mtime);
+
+        assert!(write_if_changed(&path, "KEY=value\n", true).unwrap());
+        assert_ne!(fs::metadata(&path).unwrap().modified().unwrap(), old_mtime);
+    }
+
+    #[test]
+    fn test_write_if_changed_writes_new_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+
+        assert!(write_if_changed(&path, "KEY=value\n", false).unwrap());
+        assert!(write_if_changed(&path, "KEY=other\n", false).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "KEY=other\n");
+    }
+}
@@ -1,8 +1,11 @@
+use crate::agent;
 use crate::error::{Error, Result};
 use crate::secret_gen;
+use secrecy::{ExposeSecret, SecretString};
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 #[cfg(unix)]
 use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
@@ -11,54 +14,203 @@ const SERVICE_NAME: &str = "secret-agent";
 const MASTER_KEY_NAME: &str = "master-key";
 const MASTER_KEY_LENGTH: usize = 32;
 
-/// Get the master key with fallback chain:
+/// Set by `--key-file`
+static KEY_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use the key file at `path` (created if missing) ahead of every other
+/// key source, for this process only
+pub fn set_key_file(path: PathBuf) {
+    let _ = KEY_FILE.set(path);
+}
+
+/// Where `Vault::open_at` gets its master key
+pub enum KeySource {
+    /// The CLI's lookup chain (see `get_or_create_master_key`)
+    Default,
+    /// A passphrase or key supplied by the caller
+    Passphrase(SecretString),
+    /// A key file, created with a random key if it doesn't exist
+    KeyFile(PathBuf),
+}
+
+impl KeySource {
+    /// The master key for a vault; `vault_initialized` says whether the vault
+    /// already holds data, in which case the default chain never makes a new key
+    pub(crate) fn master_key(self, vault_initialized: bool) -> Result<SecretString> {
+        match self {
+            KeySource::Default => {
+                get_or_create_master_key(vault_initialized).map(SecretString::from)
+            }
+            KeySource::Passphrase(key) => {
+                verbose!("key source: supplied by caller");
+                Ok(key)
+            }
+            KeySource::KeyFile(path) => {
+                verbose!("key source: key file {}", path.display());
+                get_or_create_file_key(&path).map(SecretString::from)
+            }
+        }
+    }
+}
+
+/// A master key found in one of the default chain's sources
+pub(crate) struct KeyCandidate {
+    /// Where it was found, e.g. "system keychain"
+    pub(crate) source: String,
+    /// What makes the default chain always pick this source
+    pub(crate) hint: String,
+    pub(crate) key: SecretString,
+}
+
+/// The system keychain's and the key file's master keys, when both exist and
+/// differ, in the order the default chain tries them. Nothing is checked when
+/// an earlier step of the chain (`--key-file`, SECRET_AGENT_PASSPHRASE,
+/// SECRET_AGENT_KEYCHAIN_ACCOUNT, SECRET_AGENT_USE_FILE) picks the key.
+pub(crate) fn divergent_keys() -> Option<Vec<KeyCandidate>> {
+    let explicit = [
+        "SECRET_AGENT_PASSPHRASE",
+        "SECRET_AGENT_KEYCHAIN_ACCOUNT",
+        "SECRET_AGENT_USE_FILE",
+    ];
+    if KEY_FILE.get().is_some() || explicit.iter().any(|var| std::env::var(var).is_ok()) {
+        return None;
+    }
+
+    // Without a key file there's nothing to compare, so skip the keychain read
+    if !get_key_file_path().ok()?.exists() {
+        return None;
+    }
+    let keys = stored_keys();
+    match keys.as_slice() {
+        [first, second] if first.key.expose_secret() != second.key.expose_secret() => Some(keys),
+        _ => None,
+    }
+}
+
+/// The managed master keys that exist: the system keychain's, then the key
+/// file's, whatever the environment selects
+pub(crate) fn stored_keys() -> Vec<KeyCandidate> {
+    let mut keys = Vec::new();
+    let Ok(path) = get_key_file_path() else {
+        return keys;
+    };
+    if let Ok(Some(key)) = get_from_keychain() {
+        keys.push(KeyCandidate {
+            source: "system keychain".to_string(),
+            hint: format!(
+                "unset SECRET_AGENT_PASSPHRASE and SECRET_AGENT_USE_FILE and move {} aside",
+                path.display()
+            ),
+            key: key.into(),
+        });
+    }
+    if let Ok(Some(key)) = get_from_file(&path) {
+        keys.push(KeyCandidate {
+            source: format!("key file {}", path.display()),
+            hint: "set SECRET_AGENT_USE_FILE=1".to_string(),
+            key: key.into(),
+        });
+    }
+    keys
+}
+
+/// Get the master key with fallback chain (after `--key-file`, which wins):
 /// 1. Environment variable SECRET_AGENT_PASSPHRASE (for CI/scripts)
-/// 2. File-based key if SECRET_AGENT_USE_FILE=1 (skip keychain prompts)
-/// 3. System keychain (macOS Keychain, Linux Secret Service)
-/// 4. File at ~/.secret-agent/master.key (headless fallback)
-/// 5. Interactive passphrase prompt (last resort)
-pub fn get_or_create_master_key() -> Result<String> {
+/// 2. Passphrase stored under the keychain account SECRET_AGENT_KEYCHAIN_ACCOUNT
+/// 3. File-based key if SECRET_AGENT_USE_FILE=1 (skip keychain prompts)
+/// 4. System keychain (macOS Keychain, Linux Secret Service)
+/// 5. Passphrase cached by `secret-agent unlock`
+/// 6. File at ~/.secret-agent/master.key (headless fallback)
+/// 7. Interactive passphrase prompt (last resort)
+///
+/// A new key (keychain entry or key file) is only ever made for a vault that
+/// isn't `vault_initialized`; an existing vault needs the key it was made with.
+pub fn get_or_create_master_key(vault_initialized: bool) -> Result<String> {
+    if let Some(path) = KEY_FILE.get() {
+        verbose!("key source: key file {} (--key-file)", path.display());
+        return get_or_create_file_key(path);
+    }
+
     // 1. Check environment variable first (highest priority for CI/automation)
     if let Ok(key) = std::env::var("SECRET_AGENT_PASSPHRASE") {
+        verbose!("key source: SECRET_AGENT_PASSPHRASE");
         return Ok(key);
     }
 
-    // 2. If user prefers file-based storage (avoids keychain prompts)
+    // 2. A user-chosen passphrase kept under their own keychain account
+    if let Ok(account) = std::env::var("SECRET_AGENT_KEYCHAIN_ACCOUNT") {
+        verbose!("key source: keychain account '{}'", account);
+        let entry = keyring::Entry::new(SERVICE_NAME, &account)
+            .map_err(|e| Error::Keychain(e.to_string()))?;
+        return passphrase_from_account(&entry, &account);
+    }
+
+    // 3. If user prefers file-based storage (avoids keychain prompts)
     if std::env::var("SECRET_AGENT_USE_FILE").is_ok() {
-        return get_or_create_file_key();
+        verbose!("key source: key file (SECRET_AGENT_USE_FILE)");
+        let path = get_key_file_path()?;
+        if vault_initialized {
+            return get_from_file(&path)?.ok_or_else(|| missing_key_error(Some(&path)));
+        }
+        return get_or_create_file_key(&path);
     }
 
-    // 3. Try system keychain
+    // 4. Try system keychain
     match get_from_keychain() {
-        Ok(Some(key)) => return Ok(key),
+        Ok(Some(key)) => {
+            verbose!("key source: system keychain");
+            return Ok(key);
+        }
+        Ok(None) if vault_initialized => {
+            verbose!("no master key in the system keychain");
+        }
         Ok(None) => {
             // First run - generate and try to store in keychain
             let key = secret_gen::generate(MASTER_KEY_LENGTH, secret_gen::Charset::Alphanumeric);
             if store_in_keychain(&key).is_ok() {
+                verbose!("key source: system keychain (new key)");
                 return Ok(key);
             }
             // Keychain store failed, try file fallback
-            store_in_file(&key)?;
+            verbose!("key source: key file (keychain store failed)");
+            store_in_file(&get_key_file_path()?, &key)?;
             return Ok(key);
         }
-        Err(_) => {
+        // The user declined the prompt; don't fall back to another key
+        Err(Error::KeychainDenied) => return Err(Error::KeychainDenied),
+        Err(e) => {
             // Keychain unavailable, try file fallback
+            verbose!("system keychain unavailable: {}", e);
         }
     }
 
-    // 3. Try file-based key (for headless Linux)
-    if let Ok(Some(key)) = get_from_file() {
+    // 5. A passphrase cached by `unlock`, ahead of any key file so that a
+    // headless run (no TTY on stdin) still uses it
+    if let Some(key) = agent::cached_key() {
+        verbose!("key source: unlock agent");
+        return Ok(key);
+    }
+
+    // 6. Try file-based key (for headless Linux)
+    if let Ok(Some(key)) = get_key_file_path().and_then(|path| get_from_file(&path)) {
+        verbose!("key source: key file");
         return Ok(key);
     }
 
-    // Check if we should create a new file-based key
     if should_use_file_fallback() {
+        // A new key can't read an existing vault, and anything written with
+        // it would be unreadable with the real one
+        if vault_initialized {
+            return Err(missing_key_error(None));
+        }
+        verbose!("key source: key file (new key, headless)");
         let key = secret_gen::generate(MASTER_KEY_LENGTH, secret_gen::Charset::Alphanumeric);
-        store_in_file(&key)?;
+        store_in_file(&get_key_file_path()?, &key)?;
         return Ok(key);
     }
 
-    // 4. Last resort: prompt for passphrase
+    // 7. Last resort: prompt for passphrase
+    verbose!("key source: passphrase prompt");
     prompt_for_passphrase()
 }
 
@@ -69,7 +221,55 @@ fn get_from_keychain() -> Result<Option<String>> {
     match entry.get_password() {
         Ok(password) => Ok(Some(password)),
         Err(keyring::Error::NoEntry) => Ok(None),
-        Err(e) => Err(Error::Keychain(e.to_string())),
+        Err(e) => Err(keychain_error(e)),
+    }
+}
+
+/// macOS status codes for a cancelled or refused keychain prompt
+/// (errSecUserCanceled, errSecAuthFailed), which keyring reports as a
+/// generic platform failure
+const MACOS_DENIED_CODES: [i32; 2] = [-128, -25293];
+
+/// Map a keyring error, telling a declined prompt apart from other failures.
+/// Secret Service reports a dismissed prompt as `NoStorageAccess`.
+fn keychain_error(e: keyring::Error) -> Error {
+    match &e {
+        keyring::Error::NoStorageAccess(_) => Error::KeychainDenied,
+        keyring::Error::PlatformFailure(inner)
+            if status_code(inner.as_ref())
+                .is_some_and(|code| MACOS_DENIED_CODES.contains(&code)) =>
+        {
+            Error::KeychainDenied
+        }
+        _ => Error::Keychain(e.to_string()),
+    }
+}
+
+/// The `code` field of a platform error's `Debug` output, which is the only
+/// place the macOS status survives keyring's boxing
+fn status_code(error: &(dyn std::error::Error + Send + Sync)) -> Option<i32> {
+    let debug = format!("{:?}", error);
+    let rest = &debug[debug.find("code: ")? + "code: ".len()..];
+    let end = rest
+        .find(|c: char| c != '-' && !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+/// Read a passphrase the user stored themselves. Unlike the managed
+/// `master-key` entry, a missing one is an error rather than created.
+fn passphrase_from_account(entry: &keyring::Entry, account: &str) -> Result<String> {
+    match entry.get_password() {
+        Ok(passphrase) if passphrase.is_empty() => Err(Error::Keychain(format!(
+            "keychain account '{}' holds an empty passphrase",
+            account
+        ))),
+        Ok(passphrase) => Ok(passphrase),
+        Err(keyring::Error::NoEntry) => Err(Error::Keychain(format!(
+            "no keychain entry for service '{}', account '{}'",
+            SERVICE_NAME, account
+        ))),
+        Err(e) => Err(keychain_error(e)),
     }
 }
 
@@ -83,6 +283,10 @@ fn store_in_keychain(key: &str) -> Result<()> {
 }
 
 fn get_key_file_path() -> Result<PathBuf> {
+    if let Some(path) = KEY_FILE.get() {
+        return Ok(path.clone());
+    }
+
     let home = dirs::home_dir().ok_or_else(|| {
         Error::Io(std::io::Error::new(
             std::io::ErrorKind::NotFound,
@@ -92,9 +296,7 @@ fn get_key_file_path() -> Result<PathBuf> {
     Ok(home.join(".secret-agent").join("master.key"))
 }
 
-fn get_from_file() -> Result<Option<String>> {
-    let path = get_key_file_path()?;
-
+fn get_from_file(path: &Path) -> Result<Option<String>> {
     if !path.exists() {
         return Ok(None);
     }
@@ -102,7 +304,7 @@ fn get_from_file() -> Result<Option<String>> {
     // Verify file permissions (should be 600)
     #[cfg(unix)]
     {
-        let metadata = fs::metadata(&path)?;
+        let metadata = fs::metadata(path)?;
         let mode = metadata.permissions().mode();
         if mode & 0o077 != 0 {
             return Err(Error::Keychain(format!(
@@ -112,13 +314,26 @@ fn get_from_file() -> Result<Option<String>> {
         }
     }
 
-    let content = fs::read_to_string(&path)?;
-    Ok(Some(content.trim().to_string()))
+    let content = fs::read_to_string(path)?;
+    let key = content.trim().to_string();
+    verbose!("key file fingerprint: {}", fingerprint(&key));
+    Ok(Some(key))
 }
 
-fn store_in_file(key: &str) -> Result<()> {
-    let path = get_key_file_path()?;
+/// A short, non-secret digest of a master key, in the style of
+/// `ssh-keygen -l`, for checking that a copied key file arrived intact
+pub fn fingerprint(key: &str) -> String {
+    use base64::Engine;
+    use sha2::{Digest, Sha256};
+
+    let digest = Sha256::digest(key.as_bytes());
+    format!(
+        "SHA256:{}",
+        base64::engine::general_purpose::STANDARD_NO_PAD.encode(digest)
+    )
+}
 
+fn store_in_file(path: &Path, key: &str) -> Result<()> {
     // Ensure parent directory exists
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
@@ -132,40 +347,62 @@ fn store_in_file(key: &str) -> Result<()> {
             .create(true)
             .truncate(true)
             .mode(0o600) // Set permissions atomically on creation
-            .open(&path)?;
+            .open(path)?;
         file.write_all(key.as_bytes())?;
     }
 
     #[cfg(not(unix))]
     {
-        fs::write(&path, key)?;
+        fs::write(path, key)?;
     }
 
     eprintln!("Created master key file at {} (chmod 600)", path.display());
+    eprintln!(
+        "Key fingerprint: {} (compare with `secret-agent --verbose list` after copying it)",
+        fingerprint(key)
+    );
 
     Ok(())
 }
 
+/// No key for a vault that already exists, so none may be created
+fn missing_key_error(key_file: Option<&Path>) -> Error {
+    let place = match key_file {
+        Some(path) => format!("the key file {} doesn't exist", path.display()),
+        None => "no master key was found".to_string(),
+    };
+    Error::Keychain(format!(
+        "the vault already exists but {}; run `secret-agent unlock`, set SECRET_AGENT_PASSPHRASE, or restore the original key",
+        place
+    ))
+}
+
 fn should_use_file_fallback() -> bool {
     // Use file fallback on headless systems (no TTY and no keychain)
     !atty::is(atty::Stream::Stdin) || std::env::var("SSH_TTY").is_ok()
 }
 
-fn get_or_create_file_key() -> Result<String> {
-    if let Some(key) = get_from_file()? {
+fn get_or_create_file_key(path: &Path) -> Result<String> {
+    if let Some(key) = get_from_file(path)? {
         return Ok(key);
     }
 
     // Generate and store new key
     let key = secret_gen::generate(MASTER_KEY_LENGTH, secret_gen::Charset::Alphanumeric);
-    store_in_file(&key)?;
+    store_in_file(path, &key)?;
     Ok(key)
 }
 
 fn prompt_for_passphrase() -> Result<String> {
     eprintln!("No keychain available. Please enter a passphrase for the vault:");
-    eprintln!("(Tip: Set SECRET_AGENT_PASSPHRASE env var to skip this prompt)");
+    eprintln!(
+        "(Tip: Set SECRET_AGENT_PASSPHRASE or run `secret-agent unlock` to skip this prompt)"
+    );
+    read_passphrase()
+}
 
+/// Read a non-empty passphrase from the terminal without echoing it
+pub fn read_passphrase() -> Result<String> {
     let passphrase = rpassword::prompt_password("Passphrase: ").map_err(Error::Io)?;
 
     if passphrase.is_empty() {
@@ -176,7 +413,6 @@ fn prompt_for_passphrase() -> Result<String> {
 }
 
 /// Delete the master key from all storage locations
-#[allow(dead_code)]
 pub fn delete_master_key() -> Result<()> {
     // Try keychain
     let _ = keyring::Entry::new(SERVICE_NAME, MASTER_KEY_NAME)
@@ -196,49 +432,59 @@ mod tests {
     use std::io::Write;
     use tempfile::TempDir;
 
-    fn store_in_file_at(path: &std::path::Path, key: &str) -> Result<()> {
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+    #[test]
+    fn test_keychain_error_maps_denial() {
+        #[derive(Debug)]
+        struct MacError {
+            #[allow(dead_code)]
+            code: i32,
         }
-
-        #[cfg(unix)]
-        {
-            let mut file = fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .mode(0o600)
-                .open(path)?;
-            file.write_all(key.as_bytes())?;
+        impl std::fmt::Display for MacError {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "mac error")
+            }
         }
+        impl std::error::Error for MacError {}
 
-        #[cfg(not(unix))]
-        {
-            fs::write(path, key)?;
-        }
+        let dismissed = keyring::Error::NoStorageAccess(Box::new(MacError { code: 0 }));
+        assert!(matches!(keychain_error(dismissed), Error::KeychainDenied));
+        let cancelled = keyring::Error::PlatformFailure(Box::new(MacError { code: -128 }));
+        assert!(matches!(keychain_error(cancelled), Error::KeychainDenied));
 
-        Ok(())
+        let other = keyring::Error::PlatformFailure(Box::new(MacError { code: -25299 }));
+        assert!(matches!(keychain_error(other), Error::Keychain(_)));
+        let bad = keyring::Error::BadEncoding(vec![0xff]);
+        assert!(matches!(keychain_error(bad), Error::Keychain(_)));
     }
 
-    fn get_from_file_at(path: &std::path::Path) -> Result<Option<String>> {
-        if !path.exists() {
-            return Ok(None);
-        }
+    #[test]
+    fn test_fingerprint() {
+        let key = secret_gen::generate(MASTER_KEY_LENGTH, secret_gen::Charset::Alphanumeric);
+        let copy = key.clone();
 
-        #[cfg(unix)]
-        {
-            let metadata = fs::metadata(path)?;
-            let mode = metadata.permissions().mode();
-            if mode & 0o077 != 0 {
-                return Err(Error::Keychain(format!(
-                    "master key file has insecure permissions {:o}, expected 600",
-                    mode & 0o777
-                )));
-            }
-        }
+        assert_eq!(fingerprint(&key), fingerprint(&copy));
+        assert!(fingerprint(&key).starts_with("SHA256:"));
+        assert!(!fingerprint(&key).contains(&key));
+        assert_ne!(fingerprint(&key), fingerprint(&format!("{}x", key)));
+    }
+
+    fn mock_entry() -> keyring::Entry {
+        keyring::Entry::new_with_credential(Box::new(keyring::mock::MockCredential::default()))
+    }
+
+    #[test]
+    fn test_passphrase_from_custom_account() {
+        let entry = mock_entry();
+        entry.set_password("correct horse battery staple").unwrap();
 
-        let content = fs::read_to_string(path)?;
-        Ok(Some(content.trim().to_string()))
+        let passphrase = passphrase_from_account(&entry, "me").unwrap();
+        assert_eq!(passphrase, "correct horse battery staple");
+    }
+
+    #[test]
+    fn test_passphrase_from_missing_account() {
+        let err = passphrase_from_account(&mock_entry(), "me").unwrap_err();
+        assert!(err.to_string().contains("account 'me'"));
     }
 
     #[test]
@@ -247,9 +493,9 @@ mod tests {
         let key_path = temp_dir.path().join("master.key");
 
         let original_key = "test-master-key-12345";
-        store_in_file_at(&key_path, original_key).unwrap();
+        store_in_file(&key_path, original_key).unwrap();
 
-        let retrieved = get_from_file_at(&key_path).unwrap();
+        let retrieved = get_from_file(&key_path).unwrap();
         assert_eq!(retrieved, Some(original_key.to_string()));
     }
 
@@ -258,7 +504,7 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let key_path = temp_dir.path().join("nonexistent.key");
 
-        let result = get_from_file_at(&key_path).unwrap();
+        let result = get_from_file(&key_path).unwrap();
         assert_eq!(result, None);
     }
 
@@ -274,7 +520,7 @@ mod tests {
         perms.set_mode(0o644);
         fs::set_permissions(&key_path, perms).unwrap();
 
-        let result = get_from_file_at(&key_path);
+        let result = get_from_file(&key_path);
         assert!(result.is_err());
         assert!(result
             .unwrap_err()
@@ -288,7 +534,7 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let key_path = temp_dir.path().join("secure.key");
 
-        store_in_file_at(&key_path, "test-key").unwrap();
+        store_in_file(&key_path, "test-key").unwrap();
 
         let metadata = fs::metadata(&key_path).unwrap();
         let mode = metadata.permissions().mode() & 0o777;
@@ -306,6 +552,7 @@ mod tests {
             let mut file = fs::OpenOptions::new()
                 .write(true)
                 .create(true)
+                .truncate(true)
                 .mode(0o600)
                 .open(&key_path)
                 .unwrap();
@@ -317,7 +564,7 @@ mod tests {
             fs::write(&key_path, "my-key-value\n").unwrap();
         }
 
-        let retrieved = get_from_file_at(&key_path).unwrap();
+        let retrieved = get_from_file(&key_path).unwrap();
         assert_eq!(retrieved, Some("my-key-value".to_string()));
     }
 }
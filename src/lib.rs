@@ -0,0 +1,57 @@
+//! Embed the secret-agent vault in another Rust program.
+//!
+//! The vault is the same one the `secret-agent` CLI uses: its location and
+//! master key come from the same environment variables and keychain entries,
+//! so secrets created here show up in `secret-agent list` and vice versa.
+//!
+//! ```no_run
+//! use secret_agent::{secret_gen, Vault};
+//! use secrecy::ExposeSecret;
+//!
+//! # fn main() -> secret_agent::Result<()> {
+//! let vault = Vault::open()?;
+//! let token = secret_gen::generate(32, secret_gen::Charset::Alphanumeric);
+//! vault.create("prod/API_TOKEN", &token)?;
+//! assert_eq!(vault.get("prod/API_TOKEN")?.expose_secret(), token);
+//! # Ok(())
+//! # }
+//! ```
+
+#[macro_use]
+mod log;
+
+mod agent;
+mod app;
+#[cfg(feature = "async")]
+mod async_vault;
+mod cli;
+mod commands;
+mod config;
+mod crypto;
+mod env_fd;
+mod error;
+mod fs_util;
+mod keychain;
+#[cfg(unix)]
+mod peer;
+mod policy;
+mod prompt;
+pub mod sanitize;
+pub mod secret_gen;
+mod signals;
+mod vault;
+
+pub use error::{Error, Result};
+pub use keychain::KeySource;
+pub use sanitize::{sanitize, sanitize_bytes, sanitize_counted};
+pub use secret_gen::{generate, generate_bytes, generate_encoded, Charset, Encoding};
+pub use vault::{Secret, Vault};
+
+#[cfg(feature = "async")]
+pub use async_vault::AsyncVault;
+
+/// Entry point of the `secret-agent` binary; not part of the library API
+#[doc(hidden)]
+pub fn run_cli() {
+    app::run()
+}
@@ -0,0 +1,40 @@
+//! Diagnostics for `--verbose`. Messages go to stderr and must never include
+//! secret values: paths, key sources and counts only.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much to print besides command output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Quiet = 0,
+    Normal = 1,
+    Verbose = 2,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+
+/// Set the level from the global flags; `--quiet` wins over `--verbose`
+pub fn init(quiet: bool, verbose: bool) {
+    let level = if quiet {
+        Level::Quiet
+    } else if verbose {
+        Level::Verbose
+    } else {
+        Level::Normal
+    };
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    LEVEL.load(Ordering::Relaxed) >= level as u8
+}
+
+/// Print a diagnostic to stderr when `--verbose` is on. `mod log` comes
+/// first in lib.rs so every module can use it.
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Verbose) {
+            eprintln!("[verbose] {}", format_args!($($arg)*));
+        }
+    };
+}
@@ -0,0 +1,79 @@
+//! Credentials of the process on the other end of a Unix socket
+
+use anyhow::Result;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+
+/// Only our own user may talk to a socket we serve
+pub fn authorize(peer_uid: u32, own_uid: u32) -> Result<()> {
+    if peer_uid != own_uid {
+        anyhow::bail!(
+            "permission denied: client uid {} does not match daemon uid {}",
+            peer_uid,
+            own_uid
+        );
+    }
+    Ok(())
+}
+
+/// Check the connecting process runs as the same user as we do
+pub fn check(stream: &UnixStream) -> Result<()> {
+    use anyhow::Context;
+    let uid = peer_uid(stream).context("failed to read peer credentials")?;
+    // SAFETY: geteuid(2) has no preconditions and cannot fail
+    authorize(uid, unsafe { libc::geteuid() })
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+    let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: cred and len are valid for writes of the sizes passed
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+    let mut uid = 0;
+    let mut gid = 0;
+    // SAFETY: uid and gid are valid for writes
+    let rc = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(uid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorize_same_uid() {
+        assert!(authorize(1000, 1000).is_ok());
+    }
+
+    #[test]
+    fn test_authorize_rejects_other_uid() {
+        let err = authorize(1001, 1000).unwrap_err();
+        assert!(err.to_string().contains("permission denied"));
+    }
+
+    #[test]
+    fn test_check_accepts_own_process() {
+        let (a, _b) = UnixStream::pair().unwrap();
+        assert!(check(&a).is_ok());
+    }
+}
@@ -0,0 +1,94 @@
+use crate::error::{Error, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+pub const POLICY_FILE: &str = ".secret-agent-policy.toml";
+
+/// Per-repository defaults for `create`, committed as `.secret-agent-policy.toml`
+///
+/// ```toml
+/// charset = "hex"
+/// length = 48
+/// min_entropy_bits = 128
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// Charset used when `--charset` isn't given
+    pub charset: Option<String>,
+    /// Length used when `--length` isn't given
+    pub length: Option<usize>,
+    /// Generated secrets below this many bits of entropy are rejected
+    pub min_entropy_bits: Option<f64>,
+}
+
+impl Policy {
+    /// Find the nearest policy file walking up from `start`
+    pub fn discover(start: &Path) -> Result<Option<(PathBuf, Self)>> {
+        for dir in start.ancestors() {
+            let path = dir.join(POLICY_FILE);
+            if path.is_file() {
+                let content = std::fs::read_to_string(&path)?;
+                let policy = Self::parse(&content)
+                    .map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))?;
+                return Ok(Some((path, policy)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn parse(content: &str) -> std::result::Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_policy() {
+        let policy = Policy::parse(
+            r#"
+            charset = "hex"
+            length = 48
+            min_entropy_bits = 128
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(policy.charset.as_deref(), Some("hex"));
+        assert_eq!(policy.length, Some(48));
+        assert_eq!(policy.min_entropy_bits, Some(128.0));
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_keys() {
+        assert!(Policy::parse("lenght = 48").is_err());
+    }
+
+    #[test]
+    fn test_discover_walks_up() {
+        let root = TempDir::new().unwrap();
+        let nested = root.path().join("services").join("api");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(root.path().join(POLICY_FILE), "length = 40\n").unwrap();
+
+        let (path, policy) = Policy::discover(&nested).unwrap().unwrap();
+        assert_eq!(path, root.path().join(POLICY_FILE));
+        assert_eq!(policy.length, Some(40));
+    }
+
+    #[test]
+    fn test_discover_nearest_wins() {
+        let root = TempDir::new().unwrap();
+        let nested = root.path().join("app");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(root.path().join(POLICY_FILE), "length = 40\n").unwrap();
+        std::fs::write(nested.join(POLICY_FILE), "length = 64\n").unwrap();
+
+        let (_, policy) = Policy::discover(&nested).unwrap().unwrap();
+        assert_eq!(policy.length, Some(64));
+    }
+}
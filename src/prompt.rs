@@ -0,0 +1,21 @@
+use anyhow::{Context, Result};
+use std::io::{self, BufRead, Write};
+
+/// Ask a yes/no question on stderr. Defaults to "no".
+/// Refuses to guess when stdin is not a terminal; callers offer `--yes` for scripts.
+pub fn confirm(question: &str) -> Result<bool> {
+    if !atty::is(atty::Stream::Stdin) {
+        anyhow::bail!("confirmation required; re-run with --yes to proceed non-interactively");
+    }
+
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush().ok();
+
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .context("failed to read confirmation")?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
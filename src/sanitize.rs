@@ -1,53 +1,222 @@
 use base64::Engine;
+use once_cell::sync::Lazy;
 use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Secrets this process has loaded, so error messages can be redacted
+static LOADED: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(Default::default);
 
 /// Sanitize output by replacing secret values with redacted placeholders
 pub fn sanitize(output: &str, secrets: &HashMap<String, String>) -> String {
+    sanitize_counted(output, secrets).0
+}
+
+/// Like `sanitize`, also returning how many occurrences were redacted
+pub fn sanitize_counted(output: &str, secrets: &HashMap<String, String>) -> (String, usize) {
     let mut result = output.to_owned();
+    let mut count = 0;
 
     for (name, value) in secrets {
-        if value.is_empty() {
-            continue;
+        for (needle, placeholder) in needles(name, value) {
+            redact(&mut result, &needle, &placeholder, &mut count);
         }
+    }
+
+    (result, count)
+}
+
+/// The forms of a secret to look for, each with its placeholder
+fn needles(name: &str, value: &str) -> Vec<(String, String)> {
+    let mut needles = Vec::new();
+    if value.is_empty() {
+        return needles;
+    }
 
-        // Direct match
-        result = result.replace(value, &format!("[REDACTED:{}]", name));
+    // Direct match
+    needles.push((value.to_string(), format!("[REDACTED:{}]", name)));
 
-        // Base64 encoded
-        let b64_standard = base64::engine::general_purpose::STANDARD.encode(value);
-        if !b64_standard.is_empty() {
-            result = result.replace(&b64_standard, &format!("[REDACTED:{}:base64]", name));
+    // Base64 encoded
+    let b64_standard = base64::engine::general_purpose::STANDARD.encode(value);
+    needles.push((b64_standard.clone(), format!("[REDACTED:{}:base64]", name)));
+
+    // Base64 URL-safe encoded
+    let b64_url = base64::engine::general_purpose::URL_SAFE.encode(value);
+    if b64_url != b64_standard {
+        needles.push((b64_url, format!("[REDACTED:{}:base64url]", name)));
+    }
+
+    // URL encoded
+    let url_encoded = urlencoding::encode(value);
+    if url_encoded != value {
+        needles.push((
+            url_encoded.to_string(),
+            format!("[REDACTED:{}:urlencoded]", name),
+        ));
+    }
+
+    // URL path-segment encoded, which leaves sub-delims like `+` and `=` alone
+    let path_encoded = encode_path_segment(value);
+    if path_encoded != value && path_encoded != url_encoded {
+        needles.push((path_encoded, format!("[REDACTED:{}:urlpath]", name)));
+    }
+
+    needles
+}
+
+/// Percent-encode `value` as a single URL path segment (RFC 3986 `pchar`):
+/// unreserved characters, sub-delims, `:` and `@` stay as they are, and
+/// everything else, `/`, `?` and `#` included, is encoded
+fn encode_path_segment(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for &byte in value.as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn redact(result: &mut String, needle: &str, placeholder: &str, count: &mut usize) {
+    let found = result.matches(needle).count();
+    if found > 0 {
+        *count += found;
+        *result = result.replace(needle, placeholder);
+    }
+}
+
+/// Redact secrets that only match once ANSI escape sequences are removed, as
+/// when a colorizing tool resets the color in the middle of a token. Matching
+/// runs on a stripped copy; each hit is mapped back to the original text, and
+/// the whole span, escapes included, is replaced.
+fn redact_split_by_ansi(output: &str, secrets: &HashMap<String, String>) -> (String, usize) {
+    if !output.contains('\x1b') {
+        return (output.to_string(), 0);
+    }
+    let (plain, offsets) = strip_ansi_mapped(output);
+
+    // (start, end, placeholder) in `output`
+    let mut spans = Vec::new();
+    for (name, value) in secrets {
+        for (needle, placeholder) in needles(name, value) {
+            for (start, _) in plain.match_indices(&needle) {
+                let end = start + needle.len();
+                spans.push((offsets[start], offsets[end - 1] + 1, placeholder.clone()));
+            }
+        }
+    }
+    spans.sort_by_key(|(start, end, _)| (*start, std::cmp::Reverse(*end)));
+
+    let mut result = String::with_capacity(output.len());
+    let mut pos = 0;
+    let mut count = 0;
+    for (start, end, placeholder) in spans {
+        // Overlaps a span already redacted
+        if start < pos {
+            continue;
         }
+        result.push_str(&output[pos..start]);
+        result.push_str(&placeholder);
+        pos = end;
+        count += 1;
+    }
+    result.push_str(&output[pos..]);
+    (result, count)
+}
+
+/// Remove ANSI escape sequences (colors, cursor movement, OSC titles/links)
+pub fn strip_ansi(text: &str) -> String {
+    strip_ansi_mapped(text).0
+}
 
-        // Base64 URL-safe encoded
-        let b64_url = base64::engine::general_purpose::URL_SAFE.encode(value);
-        if !b64_url.is_empty() && b64_url != b64_standard {
-            result = result.replace(&b64_url, &format!("[REDACTED:{}:base64url]", name));
+/// `strip_ansi`, also returning the offset in `text` of every byte kept
+fn strip_ansi_mapped(text: &str) -> (String, Vec<usize>) {
+    let bytes = text.as_bytes();
+    let mut plain = Vec::with_capacity(bytes.len());
+    let mut offsets = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == 0x1b {
+            i = skip_escape(bytes, i);
+        } else {
+            plain.push(bytes[i]);
+            offsets.push(i);
+            i += 1;
         }
+    }
+    // Escapes are pure ASCII, so removing them leaves valid UTF-8
+    let plain = String::from_utf8(plain).expect("stripping ANSI keeps UTF-8 intact");
+    (plain, offsets)
+}
 
-        // URL encoded
-        let url_encoded = urlencoding::encode(value);
-        if url_encoded != value.as_str() {
-            result = result.replace(
-                url_encoded.as_ref(),
-                &format!("[REDACTED:{}:urlencoded]", name),
-            );
+/// Index just past the escape sequence starting at `bytes[start]` (an ESC)
+fn skip_escape(bytes: &[u8], start: usize) -> usize {
+    let mut i = start + 1;
+    match bytes.get(i) {
+        // CSI: parameters and intermediates, then a final byte in @..~
+        Some(b'[') => {
+            i += 1;
+            while i < bytes.len() && !(0x40..=0x7e).contains(&bytes[i]) {
+                i += 1;
+            }
+            (i + 1).min(bytes.len())
         }
+        // OSC: runs until BEL or ST (ESC \\)
+        Some(b']') => {
+            while i < bytes.len() {
+                match bytes[i] {
+                    0x07 => return i + 1,
+                    0x1b if bytes.get(i + 1) == Some(&b'\\') => return i + 2,
+                    _ => i += 1,
+                }
+            }
+            i
+        }
+        // Two-byte sequences such as ESC ( B
+        Some(b'(' | b')') if bytes.get(i + 1).is_some_and(u8::is_ascii) => i + 2,
+        Some(b) if (0x40..=0x5f).contains(b) => i + 1,
+        _ => i,
     }
+}
+
+/// Record a loaded secret so `redact_loaded` hides it
+pub fn remember(name: &str, value: &str) {
+    LOADED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(name.to_string(), value.to_string());
+}
 
-    result
+/// Redact every secret passed to `remember` from `text`
+pub fn redact_loaded(text: &str) -> String {
+    sanitize(text, &LOADED.lock().unwrap_or_else(|e| e.into_inner()))
 }
 
-/// Sanitize bytes, returning sanitized string
-pub fn sanitize_bytes(output: &[u8], secrets: &HashMap<String, String>) -> String {
+/// Sanitize bytes, returning the sanitized string and the redaction count
+pub fn sanitize_bytes(output: &[u8], secrets: &HashMap<String, String>) -> (String, usize) {
     let output_str = String::from_utf8_lossy(output);
-    sanitize(&output_str, secrets)
+    let (sanitized, count) = sanitize_counted(&output_str, secrets);
+    let (sanitized, split) = redact_split_by_ansi(&sanitized, secrets);
+    (sanitized, count + split)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_redact_loaded() {
+        remember("TEST_LOADED_KEY", "loaded-secret-value");
+
+        let message = redact_loaded("could not start 'loaded-secret-value': not found");
+
+        assert_eq!(
+            message,
+            "could not start '[REDACTED:TEST_LOADED_KEY]': not found"
+        );
+    }
+
     fn secrets() -> HashMap<String, String> {
         let mut s = HashMap::new();
         s.insert("API_KEY".to_string(), "sk-12345".to_string());
@@ -103,6 +272,33 @@ mod tests {
         assert_eq!(result, "param=[REDACTED:SECRET:urlencoded]");
     }
 
+    #[test]
+    fn test_sanitize_url_path_encoded() {
+        let secrets = {
+            let mut s = HashMap::new();
+            s.insert("TOKEN".to_string(), "ab/cd+ef=?#".to_string());
+            s
+        };
+        // In a path segment `+` and `=` stay literal; `/`, `?` and `#` don't
+        let output = "GET /v1/hooks/ab%2Fcd+ef=%3F%23/events";
+        let result = sanitize(output, &secrets);
+        assert_eq!(result, "GET /v1/hooks/[REDACTED:TOKEN:urlpath]/events");
+
+        assert_eq!(encode_path_segment("a b/c"), "a%20b%2Fc");
+        assert_eq!(encode_path_segment("ü@x"), "%C3%BC@x");
+    }
+
+    #[test]
+    fn test_sanitize_counted() {
+        let output = "key=sk-12345, again: sk-12345, pw: hunter2";
+        let (result, count) = sanitize_counted(output, &secrets());
+        assert_eq!(count, 3);
+        assert!(!result.contains("sk-12345"));
+
+        let (_, count) = sanitize_counted("nothing here", &secrets());
+        assert_eq!(count, 0);
+    }
+
     #[test]
     fn test_sanitize_no_match() {
         let output = "nothing secret here";
@@ -110,6 +306,21 @@ mod tests {
         assert_eq!(result, "nothing secret here");
     }
 
+    #[test]
+    fn test_sanitize_bytes_secret_split_by_ansi() {
+        let output = b"token: \x1b[32msk-12\x1b[0m345\x1b[0m done";
+        let (result, count) = sanitize_bytes(output, &secrets());
+        assert_eq!(result, "token: \x1b[32m[REDACTED:API_KEY]\x1b[0m done");
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_strip_ansi() {
+        let colored = "\x1b[1;31merror\x1b[0m: \x1b]8;;http://x\x07link\x1b]8;;\x1b\\ \x1b(Bok";
+        assert_eq!(strip_ansi(colored), "error: link ok");
+        assert_eq!(strip_ansi("plain"), "plain");
+    }
+
     #[test]
     fn test_sanitize_empty_secret() {
         let mut s = HashMap::new();
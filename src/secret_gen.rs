@@ -1,4 +1,7 @@
+use base64::Engine;
+use rand::distributions::Slice;
 use rand::Rng;
+use secrecy::zeroize::Zeroize;
 
 #[derive(Debug, Clone, Copy, Default)]
 pub enum Charset {
@@ -7,49 +10,344 @@ pub enum Charset {
     Ascii,
     Hex,
     Base64,
+    Printable,
+    YamlSafe,
+    Numeric,
+    Uuid,
+    Base58,
 }
 
 impl std::str::FromStr for Charset {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "alphanumeric" => Ok(Charset::Alphanumeric),
-            "ascii" => Ok(Charset::Ascii),
-            "hex" => Ok(Charset::Hex),
-            "base64" => Ok(Charset::Base64),
-            _ => Err(format!("unknown charset: {}", s)),
+        let lower = s.to_lowercase();
+        Charset::ALL
+            .into_iter()
+            .find(|charset| charset.name() == lower || charset.aliases().contains(&lower.as_str()))
+            .ok_or_else(|| format!("unknown charset: {}", s))
+    }
+}
+
+impl Charset {
+    /// Every charset, in the order `secret-agent charsets` lists them
+    pub const ALL: [Charset; 9] = [
+        Charset::Alphanumeric,
+        Charset::Ascii,
+        Charset::Hex,
+        Charset::Base64,
+        Charset::Printable,
+        Charset::YamlSafe,
+        Charset::Numeric,
+        Charset::Base58,
+        Charset::Uuid,
+    ];
+
+    /// The name `--charset` takes
+    pub fn name(self) -> &'static str {
+        match self {
+            Charset::Alphanumeric => "alphanumeric",
+            Charset::Ascii => "ascii",
+            Charset::Hex => "hex",
+            Charset::Base64 => "base64",
+            Charset::Printable => "printable",
+            Charset::YamlSafe => "yaml-safe",
+            Charset::Numeric => "numeric",
+            Charset::Uuid => "uuid",
+            Charset::Base58 => "base58",
+        }
+    }
+
+    /// Other names `--charset` accepts for this charset
+    pub fn aliases(self) -> &'static [&'static str] {
+        match self {
+            Charset::Alphanumeric => &["alnum"],
+            Charset::Hex => &["hexadecimal"],
+            Charset::Base64 => &["b64"],
+            _ => &[],
+        }
+    }
+
+    /// What a generated value is made of
+    pub fn description(self) -> &'static str {
+        match self {
+            Charset::Alphanumeric => "A-Z a-z 0-9",
+            Charset::Ascii => "A-Z a-z 0-9 and !@#$%^&*()-_=+[]{}|;:,.<>?",
+            Charset::Hex => "0-9 a-f",
+            Charset::Base64 => "A-Z a-z 0-9 + /",
+            Charset::Printable => {
+                "letters and digits without look-alikes, -_.,:+=@% and Latin-1 letters"
+            }
+            Charset::YamlSafe => "A-Z a-z 0-9 _, never needing quotes in YAML",
+            Charset::Numeric => "0-9",
+            Charset::Uuid => "a random v4 UUID (--length is ignored)",
+            Charset::Base58 => "A-Z a-z 1-9 without 0 O I l (Bitcoin's alphabet)",
+        }
+    }
+
+    fn pool(self) -> &'static str {
+        match self {
+            Charset::Alphanumeric => ALPHANUMERIC,
+            Charset::Ascii => ASCII_PRINTABLE,
+            Charset::Hex => HEX,
+            Charset::Base64 => BASE64,
+            Charset::Printable => PRINTABLE,
+            Charset::YamlSafe => YAML_SAFE,
+            Charset::Numeric => NUMERIC,
+            Charset::Base58 => BASE58,
+            // UUIDs are built by `generate_uuid`; their digits are hex
+            Charset::Uuid => HEX,
         }
     }
 }
 
-const ALPHANUMERIC: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
-const ASCII_PRINTABLE: &[u8] =
-    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789!@#$%^&*()-_=+[]{}|;:,.<>?";
-const HEX: &[u8] = b"0123456789abcdef";
-const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const ALPHANUMERIC: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+const ASCII_PRINTABLE: &str =
+    "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789!@#$%^&*()-_=+[]{}|;:,.<>?";
+const HEX: &str = "0123456789abcdef";
+const NUMERIC: &str = "0123456789";
+/// Bitcoin's base58 alphabet: alphanumerics without 0, O, I and l
+const BASE58: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BASE64: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+/// ASCII letters and digits without look-alikes (0/O, 1/l/I), shell-inert
+/// punctuation, and Latin-1 letters (U+00C0..=U+00FF minus × and ÷).
+/// No whitespace, quotes, `$`, backslash, or control characters.
+const PRINTABLE: &str = "ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz23456789-_.,:+=@%\
+    ÀÁÂÃÄÅÆÇÈÉÊËÌÍÎÏÐÑÒÓÔÕÖØÙÚÛÜÝÞßàáâãäåæçèéêëìíîïðñòóôõöøùúûüýþÿ";
+
+/// Letters, digits and `_`: none of YAML's indicator characters, so a value
+/// never needs quoting as a plain scalar.
+const YAML_SAFE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789_";
+
+/// Words YAML (1.1 or 1.2) reads as booleans or null rather than strings
+const YAML_KEYWORDS: &[&str] = &["y", "n", "yes", "no", "on", "off", "true", "false", "null"];
+
+/// Longest secret `create` generates, in characters (or bytes for `--bytes`)
+pub const MAX_LENGTH: usize = 4096;
 
+/// Reject lengths that would produce an empty secret
+pub fn validate_length(length: usize) -> Result<(), String> {
+    if length == 0 {
+        return Err("length must be at least 1 (an empty secret is never useful)".to_string());
+    }
+    Ok(())
+}
+
+/// Generate a random secret of `length` characters drawn from `charset`.
+///
+/// Uses `rand::thread_rng` (ChaCha12, seeded from the OS CSPRNG) and samples
+/// with `Slice`, which rejection-samples so every character is equiprobable
+/// regardless of the pool size.
 pub fn generate(length: usize, charset: Charset) -> String {
-    let chars = match charset {
-        Charset::Alphanumeric => ALPHANUMERIC,
-        Charset::Ascii => ASCII_PRINTABLE,
-        Charset::Hex => HEX,
-        Charset::Base64 => BASE64,
-    };
+    if matches!(charset, Charset::Uuid) {
+        return generate_uuid();
+    }
+
+    let chars: Vec<char> = charset.pool().chars().collect();
+    sample(length, charset, &chars)
+}
+
+/// Like `generate`, but never using any character in `exclude` (to avoid
+/// vowels, or characters a provider rejects)
+pub fn generate_excluding(
+    length: usize,
+    charset: Charset,
+    exclude: &str,
+) -> Result<String, String> {
+    let chars = pool_excluding(charset, exclude)?;
+    Ok(sample(length, charset, &chars))
+}
+
+/// Entropy in bits of `generate_excluding(length, charset, exclude)`
+pub fn entropy_bits_excluding(
+    length: usize,
+    charset: Charset,
+    exclude: &str,
+) -> Result<f64, String> {
+    let chars = pool_excluding(charset, exclude)?;
+    Ok(length as f64 * (chars.len() as f64).log2())
+}
+
+/// `charset`'s characters minus those in `exclude`
+fn pool_excluding(charset: Charset, exclude: &str) -> Result<Vec<char>, String> {
+    if matches!(charset, Charset::Uuid) {
+        return Err("characters can't be excluded from a uuid".to_string());
+    }
+    let chars: Vec<char> = charset
+        .pool()
+        .chars()
+        .filter(|c| !exclude.contains(*c))
+        .collect();
+    if chars.is_empty() {
+        return Err(format!(
+            "excluding {:?} leaves no characters to use",
+            exclude
+        ));
+    }
+    // `sample` rejects values starting with a digit or spelling a keyword;
+    // without a character that can start anything else it would never stop
+    if matches!(charset, Charset::YamlSafe) && !chars.iter().any(|c| can_start_yaml_string(*c)) {
+        return Err(format!(
+            "excluding {:?} leaves no character a yaml-safe secret can start with",
+            exclude
+        ));
+    }
+    Ok(chars)
+}
+
+/// Whether a yaml-safe value of every length, one included, can start with `c`
+fn can_start_yaml_string(c: char) -> bool {
+    !c.is_ascii_digit() && !YAML_KEYWORDS.contains(&c.to_lowercase().to_string().as_str())
+}
 
+fn sample(length: usize, charset: Charset, chars: &[char]) -> String {
+    let dist = Slice::new(chars).expect("charset pool is never empty");
     let mut rng = rand::thread_rng();
-    (0..length)
-        .map(|_| {
-            let idx = rng.gen_range(0..chars.len());
-            chars[idx] as char
-        })
-        .collect()
+    loop {
+        let secret: String = (&mut rng).sample_iter(&dist).take(length).collect();
+        // Rejection keeps the accepted values uniformly distributed
+        if !matches!(charset, Charset::YamlSafe) || length == 0 || is_yaml_string(&secret) {
+            return secret;
+        }
+    }
+}
+
+/// How `create --bytes` turns random bytes into a storable string
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Encoding {
+    /// Lowercase hex, two characters per byte
+    Hex,
+    /// RFC 4648 base64 without `=` padding
+    #[default]
+    Base64,
+    /// RFC 4648 URL- and filename-safe base64 (`-`, `_`) without padding
+    Base64Url,
+}
+
+impl std::str::FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hex" => Ok(Encoding::Hex),
+            "base64" => Ok(Encoding::Base64),
+            "base64url" => Ok(Encoding::Base64Url),
+            _ => Err(format!("unknown encoding: {}", s)),
+        }
+    }
+}
+
+impl Encoding {
+    pub fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Hex => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+            Encoding::Base64 => base64::engine::general_purpose::STANDARD_NO_PAD.encode(bytes),
+            Encoding::Base64Url => base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes),
+        }
+    }
+}
+
+/// `n` random bytes from `rand::thread_rng` (seeded from the OS CSPRNG).
+/// Every bit is uniform, so `n` bytes carry exactly `8 * n` bits of entropy.
+pub fn generate_bytes(n: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; n];
+    rand::thread_rng().fill(&mut bytes[..]);
+    bytes
+}
+
+/// Encode `bytes` random bytes. Unlike sampling from `Charset::Base64`, the
+/// result always decodes to exactly `bytes` bytes.
+pub fn generate_encoded(bytes: usize, encoding: Encoding) -> String {
+    let mut raw = generate_bytes(bytes);
+    let encoded = encoding.encode(&raw);
+    raw.zeroize();
+    encoded
+}
+
+/// A random (version 4) UUID in canonical lowercase hyphenated form
+fn generate_uuid() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40; // version 4
+    bytes[8] = (bytes[8] & 0x3f) | 0x80; // RFC 4122 variant
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Whether YAML reads `value` as a string when unquoted: a leading digit
+/// could make it a number (int, float, hex, octal or base 60)
+fn is_yaml_string(value: &str) -> bool {
+    !value.starts_with(|c: char| c.is_ascii_digit())
+        && !YAML_KEYWORDS.contains(&value.to_lowercase().as_str())
+}
+
+/// Entropy in bits of a secret of `length` characters drawn uniformly from `charset`
+pub fn entropy_bits(length: usize, charset: Charset) -> f64 {
+    if matches!(charset, Charset::Uuid) {
+        // 128 bits less the fixed version and variant bits
+        return 122.0;
+    }
+    length as f64 * (charset.pool().chars().count() as f64).log2()
+}
+
+/// A coarse description of a stored value's characters, so its shape can be
+/// checked without revealing it: `numeric`, `hex`, `alnum`, `base64`,
+/// `has-whitespace`, or `mixed` for anything else
+pub fn classify(value: &[u8]) -> &'static str {
+    if value.iter().any(|b| b.is_ascii_whitespace()) {
+        "has-whitespace"
+    } else if value.iter().all(u8::is_ascii_digit) {
+        "numeric"
+    } else if value.iter().all(u8::is_ascii_hexdigit) {
+        "hex"
+    } else if value.iter().all(u8::is_ascii_alphanumeric) {
+        "alnum"
+    } else if is_base64(value) {
+        "base64"
+    } else {
+        "mixed"
+    }
+}
+
+/// Standard or URL-safe base64, with optional `=` padding only at the end
+fn is_base64(value: &[u8]) -> bool {
+    let body = value
+        .strip_suffix(b"==")
+        .or(value.strip_suffix(b"="))
+        .unwrap_or(value);
+    let standard = body
+        .iter()
+        .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/'));
+    let url_safe = body
+        .iter()
+        .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_'));
+    standard || url_safe
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify(b"0123456789abcdefABCDEF"), "hex");
+        assert_eq!(classify(b"sk1234ZZ"), "alnum");
+        assert_eq!(classify(b"2024"), "numeric");
+        assert_eq!(classify(b"3q2+7w=="), "base64");
+        assert_eq!(classify(b"3q2-7w"), "base64");
+        assert_eq!(classify(b"has space"), "has-whitespace");
+        assert_eq!(classify(b"line\n"), "has-whitespace");
+        assert_eq!(classify(b"p@ss=word!"), "mixed");
+        assert_eq!(classify(b"a+b_c"), "mixed");
+    }
+
     #[test]
     fn test_generate_alphanumeric() {
         let secret = generate(32, Charset::Alphanumeric);
@@ -57,6 +355,142 @@ mod tests {
         assert!(secret.chars().all(|c| c.is_alphanumeric()));
     }
 
+    #[test]
+    fn test_generate_yaml_safe() {
+        let charset: Charset = "yaml-safe".parse().unwrap();
+        for _ in 0..200 {
+            let secret = generate(4, charset);
+            assert!(secret
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_'));
+            assert!(!secret.starts_with(|c: char| c.is_ascii_digit()));
+            assert!(is_yaml_string(&secret));
+        }
+        assert!(!is_yaml_string("123"));
+        assert!(!is_yaml_string("0x1F"));
+        assert!(!is_yaml_string("Yes"));
+        assert!(is_yaml_string("yesno"));
+    }
+
+    #[test]
+    fn test_generate_numeric() {
+        let charset: Charset = "numeric".parse().unwrap();
+        for len in [1, 4, 6, 20] {
+            let pin = generate(len, charset);
+            assert_eq!(pin.len(), len);
+            assert!(pin.chars().all(|c| c.is_ascii_digit()));
+        }
+    }
+
+    #[test]
+    fn test_generate_base58() {
+        let charset: Charset = "base58".parse().unwrap();
+        assert_eq!(BASE58.len(), 58);
+
+        let secret = generate(44, charset);
+        assert_eq!(secret.len(), 44);
+        assert!(secret.chars().all(|c| BASE58.contains(c)));
+
+        let long = generate(2000, charset);
+        assert!(!long.contains(['0', 'O', 'I', 'l']));
+    }
+
+    #[test]
+    fn test_generate_uuid() {
+        let charset: Charset = "uuid".parse().unwrap();
+        let uuid = generate(8, charset);
+
+        // Length is ignored: always 8-4-4-4-12 hex digits
+        let groups: Vec<&str> = uuid.split('-').collect();
+        assert_eq!(
+            groups.iter().map(|g| g.len()).collect::<Vec<_>>(),
+            [8, 4, 4, 4, 12]
+        );
+        assert!(groups.iter().all(|g| g.chars().all(|c| HEX.contains(c))));
+        assert!(groups[2].starts_with('4'));
+        assert!(groups[3].starts_with(['8', '9', 'a', 'b']));
+        assert_ne!(uuid, generate(8, charset));
+        assert_eq!(entropy_bits(32, charset), 122.0);
+    }
+
+    #[test]
+    fn test_generate_encoded() {
+        use base64::engine::general_purpose::{STANDARD_NO_PAD, URL_SAFE_NO_PAD};
+
+        for bytes in [1, 16, 32, 33] {
+            let hex = generate_encoded(bytes, "hex".parse().unwrap());
+            assert_eq!(hex.len(), bytes * 2);
+            assert!(hex.chars().all(|c| HEX.contains(c)));
+
+            let b64 = generate_encoded(bytes, "base64".parse().unwrap());
+            assert!(b64.chars().all(|c| BASE64.contains(c)));
+            assert_eq!(STANDARD_NO_PAD.decode(&b64).unwrap().len(), bytes);
+
+            let b64url = generate_encoded(bytes, "base64url".parse().unwrap());
+            assert!(b64url
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+            assert_eq!(URL_SAFE_NO_PAD.decode(&b64url).unwrap().len(), bytes);
+        }
+        assert_eq!(Encoding::Hex.encode(&[0x00, 0xab, 0xff]), "00abff");
+        assert!("base32".parse::<Encoding>().is_err());
+    }
+
+    #[test]
+    fn test_generate_excluding() {
+        let vowels = "aeiouAEIOU";
+        for _ in 0..50 {
+            let secret = generate_excluding(64, Charset::Alphanumeric, vowels).unwrap();
+            assert_eq!(secret.chars().count(), 64);
+            assert!(!secret.contains(|c| vowels.contains(c)), "{}", secret);
+        }
+
+        // Characters outside the charset are simply ignored
+        let secret = generate_excluding(200, Charset::Hex, "abcdef!").unwrap();
+        assert!(secret.chars().all(|c| c.is_ascii_digit()));
+        assert_eq!(
+            entropy_bits_excluding(10, Charset::Hex, "abcdef!").unwrap(),
+            10.0 * 10f64.log2()
+        );
+
+        assert!(generate_excluding(8, Charset::Numeric, "0123456789").is_err());
+        assert!(generate_excluding(8, Charset::Uuid, "a").is_err());
+    }
+
+    #[test]
+    fn test_generate_excluding_yaml_safe_needs_a_leading_char() {
+        let letters: String = ('a'..='z').chain('A'..='Z').collect();
+
+        // Only digits would be left, and no yaml-safe value starts with one
+        let all = format!("{}_", letters);
+        let err = generate_excluding(8, Charset::YamlSafe, &all).unwrap_err();
+        assert!(err.contains("can start with"), "{}", err);
+        assert!(entropy_bits_excluding(8, Charset::YamlSafe, &all).is_err());
+
+        // `y` and `n` alone are keywords, so a one-character secret never fits
+        let keywords_only: String = letters.chars().filter(|c| !"ynYN".contains(*c)).collect();
+        let keywords_only = format!("{}_", keywords_only);
+        assert!(generate_excluding(1, Charset::YamlSafe, &keywords_only).is_err());
+
+        // One usable leading character is enough
+        let secret = generate_excluding(16, Charset::YamlSafe, &letters).unwrap();
+        assert!(secret.starts_with('_'), "{}", secret);
+        assert!(is_yaml_string(&secret));
+    }
+
+    #[test]
+    fn test_entropy_bits() {
+        assert_eq!(entropy_bits(32, Charset::Hex), 128.0);
+        assert_eq!(entropy_bits(10, Charset::Base64), 60.0);
+        assert_eq!(entropy_bits(0, Charset::Alphanumeric), 0.0);
+    }
+
+    #[test]
+    fn test_validate_length() {
+        assert!(validate_length(0).is_err());
+        assert!(validate_length(1).is_ok());
+    }
+
     #[test]
     fn test_generate_hex() {
         let secret = generate(64, Charset::Hex);
@@ -72,12 +506,74 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_generate_uniform_distribution() {
+        // 62 doesn't divide 256, so a sampler taking `byte % 62` would favor
+        // the first 8 characters by ~20%; a power-of-two pool would hide that
+        let samples = 620_000;
+        let secret = generate(samples, Charset::Alphanumeric);
+
+        let mut counts = std::collections::HashMap::new();
+        for c in secret.chars() {
+            *counts.entry(c).or_insert(0usize) += 1;
+        }
+
+        // Each of the 62 characters should appear ~10_000 times; allow 5% drift
+        let expected = samples / ALPHANUMERIC.len();
+        assert_eq!(counts.len(), ALPHANUMERIC.len());
+        for (c, count) in counts {
+            let drift = count.abs_diff(expected);
+            assert!(
+                drift < expected / 20,
+                "character '{}' appeared {} times, expected ~{}",
+                c,
+                count,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_generate_printable() {
+        let secret = generate(500, Charset::Printable);
+
+        // Length is in characters; Latin-1 letters take two bytes in UTF-8
+        assert_eq!(secret.chars().count(), 500);
+        assert!(secret.len() >= 500);
+        assert!(secret
+            .chars()
+            .all(|c| !c.is_control() && !c.is_whitespace() && PRINTABLE.contains(c)));
+        assert!(!secret.contains(['\'', '"', '`', '$', '\\', ';', '&', '|']));
+    }
+
+    #[test]
+    fn test_printable_pool_has_no_ambiguous_chars() {
+        assert!(!PRINTABLE.contains(['0', 'O', '1', 'l', 'I', '×', '÷']));
+    }
+
     #[test]
     fn test_charset_from_str() {
         assert!(matches!("alphanumeric".parse(), Ok(Charset::Alphanumeric)));
         assert!(matches!("hex".parse(), Ok(Charset::Hex)));
         assert!(matches!("base64".parse(), Ok(Charset::Base64)));
         assert!(matches!("ascii".parse(), Ok(Charset::Ascii)));
+        assert!(matches!("printable".parse(), Ok(Charset::Printable)));
         assert!("invalid".parse::<Charset>().is_err());
     }
+
+    #[test]
+    fn test_charset_aliases() {
+        assert!(matches!("alnum".parse(), Ok(Charset::Alphanumeric)));
+        assert!(matches!("hexadecimal".parse(), Ok(Charset::Hex)));
+        assert!(matches!("B64".parse(), Ok(Charset::Base64)));
+        assert!("hexa".parse::<Charset>().is_err());
+        assert!("b-64".parse::<Charset>().is_err());
+
+        // An alias never shadows another charset's name
+        for charset in Charset::ALL {
+            for alias in charset.aliases() {
+                assert!(Charset::ALL.iter().all(|other| other.name() != *alias));
+            }
+        }
+    }
 }
@@ -0,0 +1,191 @@
+//! Forward termination signals from the exec wrapper to its child.
+//!
+//! The child is spawned in its own process group, so a Ctrl-C in the terminal
+//! reaches only secret-agent. We relay SIGINT/SIGTERM/SIGHUP to the child's
+//! group, let it shut down cleanly, and then exit with 128+signal. Signals
+//! are caught before the child is spawned; one arriving before its pid is
+//! known is held and sent as soon as it is.
+
+use std::io;
+
+#[cfg(unix)]
+mod imp {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+    use signal_hook::iterator::{Handle, Signals};
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use std::thread::JoinHandle;
+
+    #[derive(Default)]
+    struct State {
+        /// The child's process group, once it has been spawned
+        pgid: Option<libc::pid_t>,
+        /// Caught before the child existed, still to be sent
+        pending: Option<i32>,
+        /// The last signal caught
+        received: Option<i32>,
+    }
+
+    fn send(pgid: libc::pid_t, signal: i32) {
+        // SAFETY: kill(2) has no memory-safety preconditions
+        unsafe {
+            libc::kill(-pgid, signal);
+        }
+    }
+
+    pub struct Forwarder {
+        handle: Handle,
+        thread: JoinHandle<()>,
+        state: Arc<Mutex<State>>,
+    }
+
+    impl Forwarder {
+        pub fn start() -> io::Result<Self> {
+            let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP])?;
+            let handle = signals.handle();
+            let state = Arc::new(Mutex::new(State::default()));
+
+            let shared = Arc::clone(&state);
+            let thread = std::thread::spawn(move || {
+                for signal in signals.forever() {
+                    let mut state = shared.lock().unwrap_or_else(|e| e.into_inner());
+                    state.received = Some(signal);
+                    match state.pgid {
+                        Some(pgid) => send(pgid, signal),
+                        None => state.pending = Some(signal),
+                    }
+                }
+            });
+
+            Ok(Self {
+                handle,
+                thread,
+                state,
+            })
+        }
+
+        pub fn attach(&self, child_pid: u32) {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let pgid = child_pid as libc::pid_t;
+            state.pgid = Some(pgid);
+            if let Some(signal) = state.pending.take() {
+                send(pgid, signal);
+            }
+        }
+
+        pub fn finish(self) -> Option<i32> {
+            self.handle.close();
+            let _ = self.thread.join();
+            let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            state.received
+        }
+    }
+
+    pub fn isolate(cmd: &mut std::process::Command) {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::io;
+
+    pub struct Forwarder;
+
+    impl Forwarder {
+        pub fn start() -> io::Result<Self> {
+            Ok(Self)
+        }
+
+        pub fn attach(&self, _child_pid: u32) {}
+
+        pub fn finish(self) -> Option<i32> {
+            None
+        }
+    }
+
+    pub fn isolate(_cmd: &mut std::process::Command) {}
+}
+
+/// Put the child in its own process group so signals reach it only via us
+pub fn isolate(cmd: &mut std::process::Command) {
+    imp::isolate(cmd)
+}
+
+/// Start catching termination signals, before spawning the child so none
+/// can kill us in between. `Forwarder::attach` then relays them to the
+/// child's process group.
+pub fn catch() -> io::Result<Forwarder> {
+    imp::Forwarder::start()
+}
+
+pub use imp::Forwarder;
+
+/// Conventional shell exit code for death by signal
+pub fn exit_code_for(signal: i32) -> i32 {
+    128 + signal
+}
+
+/// Conventional name of a signal, e.g. "SIGTERM", or "signal N" if unknown
+#[cfg(unix)]
+pub fn name(signal: i32) -> String {
+    let name = match signal {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        _ => return format!("signal {}", signal),
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_name() {
+        assert_eq!(name(libc::SIGTERM), "SIGTERM");
+        assert_eq!(name(libc::SIGKILL), "SIGKILL");
+        assert_eq!(name(200), "signal 200");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signal_before_spawn_reaches_child() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let forwarder = catch().unwrap();
+        // SAFETY: raise(3) has no memory-safety preconditions; the signal is
+        // caught by the forwarder rather than ending the test process
+        unsafe {
+            libc::raise(libc::SIGINT);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let mut cmd = std::process::Command::new("sleep");
+        cmd.arg("10");
+        isolate(&mut cmd);
+        let mut child = cmd.spawn().unwrap();
+        forwarder.attach(child.id());
+
+        let status = child.wait().unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGINT));
+        assert_eq!(forwarder.finish(), Some(libc::SIGINT));
+    }
+}
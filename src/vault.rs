@@ -1,64 +1,92 @@
-use crate::crypto;
+use crate::crypto::{self, ValueFormat, VaultKey};
 use crate::error::{Error, Result};
-use crate::keychain;
+use crate::keychain::{self, KeyCandidate, KeySource};
+use crate::secret_gen;
+use base64::Engine;
 use chrono::{DateTime, Utc};
-use rusqlite::{params, Connection};
-use secrecy::{ExposeSecret, SecretString};
-use std::path::PathBuf;
-
-const SCHEMA_VERSION: i64 = 1;
+use rayon::iter::Either;
+use rayon::prelude::*;
+use rusqlite::{params, Connection, DatabaseName, OpenFlags, OptionalExtension};
+use secrecy::{ExposeSecret, SecretSlice, SecretString};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const SCHEMA_VERSION: i64 = 4;
+
+/// Set by `--vault-path`
+static VAULT_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Open the database at `path` instead of SECRET_AGENT_VAULT_PATH or the
+/// default location, for this process only
+pub fn set_vault_path(path: PathBuf) {
+    let _ = VAULT_PATH.set(path);
+}
 
 pub struct Secret {
     pub name: String,
     pub created_at: DateTime<Utc>,
-    #[allow(dead_code)]
     pub updated_at: DateTime<Utc>,
+    /// `user@host` (or SECRET_AGENT_IDENTITY) that created it; unknown for
+    /// secrets created before schema v3
+    pub created_by: Option<String>,
+}
+
+/// Which key each secret decrypts with, from `Vault::verify_readable`
+pub struct Readability {
+    /// How many secrets the current key decrypts
+    pub current: usize,
+    /// Secrets only the new key decrypts, i.e. already re-encrypted
+    pub migrated: Vec<String>,
 }
 
 pub struct Vault {
     conn: Connection,
     master_key: SecretString, // Zeroized on drop
+    key: VaultKey,
+    /// Opened with `KeySource::Default`, so a failed decryption is checked
+    /// against the other stored keys
+    default_source: bool,
 }
 
 impl Vault {
-    /// Open the vault, creating it if it doesn't exist
+    /// Open the vault, creating it if it doesn't exist. The path and master
+    /// key are resolved from the environment, as the CLI does.
     pub fn open() -> Result<Self> {
-        let vault_path = get_vault_path()?;
+        Self::open_at(&get_vault_path()?, KeySource::Default)
+    }
+
+    /// Open (or create) the vault database at `path` with the master key
+    /// from `key_source`, without consulting SECRET_AGENT_VAULT_PATH
+    pub fn open_at(path: &Path, key_source: KeySource) -> Result<Self> {
+        verbose!("vault path: {}", path.display());
 
         // Ensure parent directory exists
-        if let Some(parent) = vault_path.parent() {
+        if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?;
         }
 
-        let conn = Connection::open(&vault_path)?;
-
-        // Enable WAL mode for better concurrency
-        conn.execute_batch("PRAGMA journal_mode=WAL;")?;
-
-        // Initialize schema
-        conn.execute_batch(
-            "
-            CREATE TABLE IF NOT EXISTS secrets (
-                name TEXT PRIMARY KEY,
-                encrypted_value BLOB NOT NULL,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
-            );
-
-            CREATE TABLE IF NOT EXISTS metadata (
-                key TEXT PRIMARY KEY,
-                value TEXT NOT NULL
-            );
-            ",
-        )?;
-
-        // Check/set schema version
-        init_schema_version(&conn)?;
-
-        // Get or create master key
-        let master_key = SecretString::from(keychain::get_or_create_master_key()?);
-
-        Ok(Self { conn, master_key })
+        let conn = open_connection(path)?;
+
+        // Get or create master key, unless the keychain and key file disagree
+        let default_source = matches!(key_source, KeySource::Default);
+        let divergent = if default_source {
+            keychain::divergent_keys()
+        } else {
+            None
+        };
+        let master_key = match divergent {
+            Some(candidates) => pick_divergent_key(&conn, candidates)?,
+            None => key_source.master_key(is_initialized(&conn)?)?,
+        };
+        let key = derive_key(&conn, &master_key)?;
+
+        Ok(Self {
+            conn,
+            master_key,
+            key,
+            default_source,
+        })
     }
 
     /// Create a new secret with the given value
@@ -72,7 +100,7 @@ impl Vault {
     }
 
     fn create_internal(&self, name: &str, value: &str, force: bool) -> Result<()> {
-        validate_name(name)?;
+        validate_full_name(name)?;
 
         // Check if secret already exists
         if self.exists(name)? {
@@ -82,19 +110,20 @@ impl Vault {
             return Err(Error::SecretAlreadyExists(name.to_string()));
         }
 
-        let encrypted = crypto::encrypt(value.as_bytes(), self.master_key.expose_secret())?;
+        let encrypted = self.key.encrypt(value.as_bytes())?;
+        let hash = self.key.value_hash(value.as_bytes());
         let now = Utc::now().to_rfc3339();
 
         self.conn.execute(
-            "INSERT INTO secrets (name, encrypted_value, created_at, updated_at) VALUES (?1, ?2, ?3, ?4)",
-            params![name, encrypted, now, now],
+            "INSERT INTO secrets (name, encrypted_value, value_hash, created_at, updated_at, created_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![name, encrypted, hash, now, now, current_identity()],
         )?;
 
         Ok(())
     }
 
-    /// Get the decrypted value of a secret
-    pub fn get(&self, name: &str) -> Result<String> {
+    /// Get the decrypted value of a secret, zeroized when dropped
+    pub fn get(&self, name: &str) -> Result<SecretString> {
         let encrypted: Vec<u8> = self
             .conn
             .query_row(
@@ -107,8 +136,279 @@ impl Vault {
                 _ => Error::Database(e),
             })?;
 
-        let decrypted = crypto::decrypt(&encrypted, self.master_key.expose_secret())?;
-        String::from_utf8(decrypted).map_err(|e| Error::Decryption(e.to_string()))
+        let decrypted = self
+            .key
+            .decrypt(&encrypted)
+            .map_err(|e| self.mismatch_or(e, &encrypted))?;
+        let value = String::from_utf8(decrypted).map_err(|_| {
+            Error::Decryption(format!(
+                "secret '{}' is not UTF-8 text (read binary secrets with get --raw)",
+                name
+            ))
+        })?;
+        Ok(SecretString::from(value))
+    }
+
+    /// Names of the secrets stored as binary, ordered by name
+    pub fn binary_names(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name FROM secrets WHERE binary = 1 ORDER BY name")?;
+        let names = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<String>, _>>()?;
+        Ok(names)
+    }
+
+    /// Store arbitrary bytes, marking the secret as binary. With `replace`,
+    /// an existing secret is overwritten.
+    pub fn store_binary(&self, name: &str, value: &[u8], replace: bool) -> Result<()> {
+        validate_full_name(name)?;
+        let exists = self.exists(name)?;
+        if exists && !replace {
+            return Err(Error::SecretAlreadyExists(name.to_string()));
+        }
+
+        let encrypted = self.key.encrypt(value)?;
+        let hash = self.key.value_hash(value);
+        let now = Utc::now().to_rfc3339();
+
+        if exists {
+            self.conn.execute(
+                "UPDATE secrets SET encrypted_value = ?1, value_hash = ?2, updated_at = ?3, binary = 1 WHERE name = ?4",
+                params![encrypted, hash, now, name],
+            )?;
+        } else {
+            self.conn.execute(
+                "INSERT INTO secrets (name, encrypted_value, value_hash, created_at, updated_at, created_by, binary) VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1)",
+                params![name, encrypted, hash, now, now, current_identity()],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The exact stored bytes of a secret, and whether it was stored as binary
+    pub fn get_bytes(&self, name: &str) -> Result<(SecretSlice<u8>, bool)> {
+        let (encrypted, binary): (Vec<u8>, bool) = self
+            .conn
+            .query_row(
+                "SELECT encrypted_value, binary FROM secrets WHERE name = ?1",
+                params![name],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => Error::SecretNotFound(name.to_string()),
+                _ => Error::Database(e),
+            })?;
+
+        let decrypted = self
+            .key
+            .decrypt(&encrypted)
+            .map_err(|e| self.mismatch_or(e, &encrypted))?;
+        Ok((SecretSlice::from(decrypted), binary))
+    }
+
+    /// Length and class (`secret_gen::classify`, or `binary`) of every
+    /// secret's value, by name, decrypted in parallel like `get_all`. `None`
+    /// marks a secret the current key can't decrypt, such as one moved to a
+    /// new key by `reencrypt`, so it doesn't hide the others.
+    pub fn value_shapes(&self) -> Result<HashMap<String, Option<(usize, &'static str)>>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, encrypted_value, binary FROM secrets")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<std::result::Result<Vec<(String, Vec<u8>, bool)>, _>>()?;
+
+        let key = &self.key;
+        Ok(rows
+            .into_par_iter()
+            .map(|(name, encrypted, binary)| {
+                let shape = key.decrypt(&encrypted).ok().map(|mut value| {
+                    let class = if binary {
+                        "binary"
+                    } else {
+                        secret_gen::classify(&value)
+                    };
+                    let len = value.len();
+                    value.fill(0);
+                    (len, class)
+                });
+                (name, shape)
+            })
+            .collect())
+    }
+
+    /// Get every text secret's decrypted value, ordered by name. Binary
+    /// secrets (see `binary_names`) are left out, as they have no text value.
+    ///
+    /// Values written before the vault key existed each need their own
+    /// scrypt-based age decryption, which is deliberately slow; decrypting
+    /// them serially makes large vaults take many seconds, so the ciphertexts
+    /// are fetched in one query and decrypted across a thread pool.
+    pub fn get_all(&self) -> Result<Vec<(String, SecretString)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, encrypted_value FROM secrets WHERE binary = 0 ORDER BY name")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<Vec<(String, Vec<u8>)>, _>>()?;
+
+        // par_iter + collect preserves the input (name) order
+        let sample = rows.first().map(|(_, encrypted)| encrypted.clone());
+        let key = &self.key;
+        rows.into_par_iter()
+            .map(|(name, encrypted)| {
+                let decrypted = key.decrypt(&encrypted)?;
+                let value =
+                    String::from_utf8(decrypted).map_err(|e| Error::Decryption(e.to_string()))?;
+                Ok((name, SecretString::from(value)))
+            })
+            .collect::<Result<_>>()
+            .map_err(|e| match &sample {
+                Some(sample) => self.mismatch_or(e, sample),
+                None => e,
+            })
+    }
+
+    /// A decryption failure becomes `Error::KeyMismatch` when another stored
+    /// master key (keychain or key file) decrypts `encrypted`, so a user on
+    /// the wrong key is told which one to use. Only checked for vaults opened
+    /// with `KeySource::Default`, and only once decryption has failed.
+    fn mismatch_or(&self, e: Error, encrypted: &[u8]) -> Error {
+        if !self.default_source || !matches!(e, Error::Decryption(_)) {
+            return e;
+        }
+        self.mismatch_with(e, encrypted, keychain::stored_keys())
+    }
+
+    fn mismatch_with(&self, e: Error, encrypted: &[u8], candidates: Vec<KeyCandidate>) -> Error {
+        for candidate in candidates {
+            if candidate.key.expose_secret() == self.master_key.expose_secret() {
+                continue;
+            }
+            let Ok(other) = derive_key(&self.conn, &candidate.key) else {
+                continue;
+            };
+            if other.decrypt(encrypted).is_ok() {
+                return Error::KeyMismatch(format!(
+                    "the vault was encrypted with the master key in the {} ({}), not the one in use ({}); {} to use it",
+                    candidate.source,
+                    key_id(&other),
+                    key_id(&self.key),
+                    candidate.hint
+                ));
+            }
+        }
+        e
+    }
+
+    /// Decrypt every secret, keeping nothing, with the key `reencrypt` would
+    /// find it under: the current key, or `new_key` for rows already moved.
+    /// Fails naming the first secret neither key can read.
+    pub fn verify_readable(&self, new_key: &VaultKey) -> Result<Readability> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, encrypted_value FROM secrets ORDER BY name")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<Vec<(String, Vec<u8>)>, _>>()?;
+
+        let decrypts = |key: &VaultKey, encrypted: &[u8]| {
+            key.decrypt(encrypted)
+                .map(|mut plaintext| plaintext.fill(0))
+                .is_ok()
+        };
+        let current_key = &self.key;
+        // (name, readable by the current key, readable by the new key);
+        // par_iter + collect preserves the name order
+        let checked: Vec<(&str, bool, bool)> = rows
+            .par_iter()
+            .map(|(name, encrypted)| {
+                let current = decrypts(current_key, encrypted);
+                let moved = !current && decrypts(new_key, encrypted);
+                (name.as_str(), current, moved)
+            })
+            .collect();
+
+        let mut readability = Readability {
+            current: 0,
+            migrated: Vec::new(),
+        };
+        for (name, current, moved) in checked {
+            if current {
+                readability.current += 1;
+            } else if moved {
+                readability.migrated.push(name.to_string());
+            } else {
+                return Err(Error::Decryption(format!(
+                    "secret '{}' can't be decrypted with the current or the new key",
+                    name
+                )));
+            }
+        }
+        Ok(readability)
+    }
+
+    /// Keyed hash of `value` as stored in the `value_hash` column
+    pub fn value_hash(&self, value: &str) -> String {
+        self.key.value_hash(value.as_bytes())
+    }
+
+    /// Names of the secrets whose value has the given `value_hash`, ordered by name
+    pub fn find_by_value_hash(&self, hash: &str) -> Result<Vec<String>> {
+        self.backfill_value_hashes()?;
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name FROM secrets WHERE value_hash = ?1 ORDER BY name")?;
+        let names = stmt
+            .query_map(params![hash], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<String>, _>>()?;
+        Ok(names)
+    }
+
+    /// Hash values stored before schema v2, decrypting each once. Done only
+    /// when hashes are looked up, so commands that never read values still
+    /// work with a wrong key; rows that don't decrypt are skipped with a
+    /// warning and tried again next time.
+    fn backfill_value_hashes(&self) -> Result<()> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, encrypted_value FROM secrets WHERE value_hash IS NULL")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<Vec<(String, Vec<u8>)>, _>>()?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let key = &self.key;
+        let (hashes, unreadable): (Vec<_>, Vec<_>) =
+            rows.into_par_iter()
+                .partition_map(|(name, encrypted)| match key.decrypt(&encrypted) {
+                    Ok(mut value) => {
+                        let hash = key.value_hash(&value);
+                        value.fill(0);
+                        Either::Left((name, hash))
+                    }
+                    Err(_) => Either::Right(name),
+                });
+        if !unreadable.is_empty() {
+            eprintln!(
+                "warning: can't decrypt {} to check for duplicate values; skipping",
+                unreadable.join(", ")
+            );
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        for (name, hash) in hashes {
+            tx.execute(
+                "UPDATE secrets SET value_hash = ?1 WHERE name = ?2",
+                params![hash, name],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
     }
 
     /// List all secrets (metadata only, no values)
@@ -116,13 +416,14 @@ impl Vault {
         self.list_by_bucket(None)
     }
 
-    /// List secrets, optionally filtered by bucket
+    /// List secrets, optionally filtered by bucket. A bucket matches every
+    /// nested bucket below it (`company/prod` includes `company/prod/api/KEY`).
     pub fn list_by_bucket(&self, bucket: Option<&str>) -> Result<Vec<Secret>> {
         let all_secrets = self.list_all_internal()?;
 
         match bucket {
             Some(b) => {
-                let prefix = format!("{}/", b);
+                let prefix = format!("{}/", b.trim_end_matches('/'));
                 Ok(all_secrets
                     .into_iter()
                     .filter(|s| s.name.starts_with(&prefix))
@@ -133,31 +434,47 @@ impl Vault {
     }
 
     fn list_all_internal(&self) -> Result<Vec<Secret>> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT name, created_at, updated_at FROM secrets ORDER BY name")?;
+        let mut stmt = self.conn.prepare(
+            "SELECT name, created_at, updated_at, created_by FROM secrets ORDER BY name",
+        )?;
 
         let secrets = stmt
-            .query_map([], |row| {
-                let name: String = row.get(0)?;
-                let created_at: String = row.get(1)?;
-                let updated_at: String = row.get(2)?;
-
-                Ok(Secret {
-                    name,
-                    created_at: DateTime::parse_from_rfc3339(&created_at)
-                        .unwrap_or_default()
-                        .with_timezone(&Utc),
-                    updated_at: DateTime::parse_from_rfc3339(&updated_at)
-                        .unwrap_or_default()
-                        .with_timezone(&Utc),
-                })
-            })?
+            .query_map([], secret_from_row)?
             .collect::<std::result::Result<Vec<_>, _>>()?;
 
         Ok(secrets)
     }
 
+    /// Each bucket with its number of secrets, sorted by bucket, with the
+    /// secrets that have no bucket (`None`) last. Nested buckets are listed
+    /// separately (`company/prod` doesn't count toward `company`).
+    pub fn bucket_summary(&self) -> Result<Vec<(Option<String>, usize)>> {
+        let mut counts: std::collections::BTreeMap<Option<String>, usize> = Default::default();
+        for secret in self.list_all_internal()? {
+            let bucket = parse_bucket_name(&secret.name).0.map(str::to_string);
+            *counts.entry(bucket).or_default() += 1;
+        }
+
+        let unbucketed = counts.remove(&None);
+        let mut summary: Vec<_> = counts.into_iter().collect();
+        summary.extend(unbucketed.map(|count| (None, count)));
+        Ok(summary)
+    }
+
+    /// Get a single secret's metadata (never its value)
+    pub fn get_metadata(&self, name: &str) -> Result<Secret> {
+        self.conn
+            .query_row(
+                "SELECT name, created_at, updated_at, created_by FROM secrets WHERE name = ?1",
+                params![name],
+                secret_from_row,
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => Error::SecretNotFound(name.to_string()),
+                _ => Error::Database(e),
+            })
+    }
+
     /// Delete a secret
     pub fn delete(&self, name: &str) -> Result<()> {
         let rows = self
@@ -171,6 +488,105 @@ impl Vault {
         Ok(())
     }
 
+    /// Delete every secret in `bucket`, nested buckets included, returning
+    /// how many were removed
+    pub fn delete_bucket(&self, bucket: &str) -> Result<usize> {
+        let prefix = format!("{}/", bucket.trim_end_matches('/'));
+        let tx = self.conn.unchecked_transaction()?;
+        let deleted = self.conn.execute(
+            "DELETE FROM secrets WHERE substr(name, 1, length(?1)) = ?1",
+            params![prefix],
+        )?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Delete several secrets in one transaction, returning whether each existed.
+    /// Unless `if_exists` is set, a missing name aborts and nothing is deleted.
+    pub fn delete_many(&self, names: &[String], if_exists: bool) -> Result<Vec<bool>> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        let mut deleted = Vec::with_capacity(names.len());
+        for name in names {
+            match self.delete(name) {
+                Ok(()) => deleted.push(true),
+                Err(Error::SecretNotFound(_)) if if_exists => deleted.push(false),
+                Err(e) => return Err(e),
+            }
+        }
+
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// The key a different master key derives for this vault (same salt)
+    pub fn key_for(&self, master_key: &str) -> Result<VaultKey> {
+        derive_key(&self.conn, &SecretString::from(master_key.to_owned()))
+    }
+
+    /// Re-encrypt one secret under `new_key`, leaving its value, `created_at`
+    /// and every other row alone. Afterwards only `new_key` decrypts it.
+    pub fn reencrypt(&self, name: &str, new_key: &VaultKey) -> Result<()> {
+        let encrypted: Vec<u8> = self
+            .conn
+            .query_row(
+                "SELECT encrypted_value FROM secrets WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => Error::SecretNotFound(name.to_string()),
+                _ => Error::Database(e),
+            })?;
+
+        let mut plaintext = self
+            .key
+            .decrypt(&encrypted)
+            .map_err(|e| self.mismatch_or(e, &encrypted))?;
+        let reencrypted = new_key.encrypt(&plaintext);
+        plaintext.fill(0);
+
+        self.conn.execute(
+            "UPDATE secrets SET encrypted_value = ?1, updated_at = ?2 WHERE name = ?3",
+            params![reencrypted?, Utc::now().to_rfc3339(), name],
+        )?;
+        Ok(())
+    }
+
+    /// Rename a secret, keeping its value and creation metadata
+    pub fn rename(&self, old: &str, new: &str) -> Result<()> {
+        validate_full_name(new)?;
+        if self.exists(new)? {
+            return Err(Error::SecretAlreadyExists(new.to_string()));
+        }
+
+        let rows = self.conn.execute(
+            "UPDATE secrets SET name = ?1, updated_at = ?2 WHERE name = ?3",
+            params![new, Utc::now().to_rfc3339(), old],
+        )?;
+        if rows == 0 {
+            return Err(Error::SecretNotFound(old.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Apply several renames in one transaction; if any fails, none happen
+    pub fn rename_many(&self, renames: &[(String, String)]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for (old, new) in renames {
+            self.rename(old, new)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Start a transaction; writes through this vault are undone unless it
+    /// is committed
+    pub fn transaction(&self) -> Result<rusqlite::Transaction<'_>> {
+        Ok(self.conn.unchecked_transaction()?)
+    }
+
     /// Check if a secret exists
     pub fn exists(&self, name: &str) -> Result<bool> {
         let count: i64 = self.conn.query_row(
@@ -182,24 +598,366 @@ impl Vault {
         Ok(count > 0)
     }
 
+    /// Copy the whole database to `path` using SQLite's online backup API.
+    /// The WAL is checkpointed first so the copy is self-contained.
+    /// Values stay encrypted with the master key.
+    pub fn backup_to(&self, path: &Path) -> Result<()> {
+        self.conn
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        self.conn.backup(DatabaseName::Main, path, None)?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+
+        Ok(())
+    }
+
+    /// Rebuild the database with VACUUM and truncate the WAL so deleted
+    /// secrets stop taking up space. Returns the on-disk size before and after.
+    pub fn compact(&self) -> Result<(u64, u64)> {
+        let before = self.disk_size();
+        self.conn.execute_batch("VACUUM")?;
+        self.conn
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        Ok((before, self.disk_size()))
+    }
+
+    /// Size of the database file plus its write-ahead log
+    fn disk_size(&self) -> u64 {
+        let Some(path) = self.conn.path() else {
+            return 0;
+        };
+        [path.to_string(), format!("{}-wal", path)]
+            .iter()
+            .filter_map(|p| std::fs::metadata(p).ok())
+            .map(|m| m.len())
+            .sum()
+    }
+
+    /// Replace the entire vault contents with a backup made by `backup_to`
+    pub fn restore_from(&mut self, path: &Path) -> Result<()> {
+        // Validate the backup before touching the live vault
+        let source = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|_| Error::VaultCorrupt(path.to_path_buf()))?;
+        let integrity: String = source
+            .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+            .map_err(|_| Error::VaultCorrupt(path.to_path_buf()))?;
+        let has_secrets: bool = source
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'secrets'",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|_| Error::VaultCorrupt(path.to_path_buf()))?;
+        if integrity != "ok" || !has_secrets {
+            return Err(Error::VaultCorrupt(path.to_path_buf()));
+        }
+        drop(source);
+
+        self.conn.restore(
+            DatabaseName::Main,
+            path,
+            None::<fn(rusqlite::backup::Progress)>,
+        )?;
+
+        // The backup carries its own key salt, and may predate the current schema
+        init_schema(&self.conn)?;
+        self.key = derive_key(&self.conn, &self.master_key)?;
+        Ok(())
+    }
+
     /// Update an existing secret's value
     pub fn update(&self, name: &str, value: &str) -> Result<()> {
         if !self.exists(name)? {
             return Err(Error::SecretNotFound(name.to_string()));
         }
 
-        let encrypted = crypto::encrypt(value.as_bytes(), self.master_key.expose_secret())?;
+        let encrypted = self.key.encrypt(value.as_bytes())?;
+        let hash = self.key.value_hash(value.as_bytes());
         let now = Utc::now().to_rfc3339();
 
         self.conn.execute(
-            "UPDATE secrets SET encrypted_value = ?1, updated_at = ?2 WHERE name = ?3",
-            params![encrypted, now, name],
+            "UPDATE secrets SET encrypted_value = ?1, value_hash = ?2, updated_at = ?3, binary = 0 WHERE name = ?4",
+            params![encrypted, hash, now, name],
         )?;
 
         Ok(())
     }
 }
 
+/// Open the SQLite database, verify it is intact, and initialize the schema.
+/// A corrupt or non-SQLite file is reported as `VaultCorrupt` and left untouched.
+fn open_connection(path: &Path) -> Result<Connection> {
+    let corrupt = || Error::VaultCorrupt(path.to_path_buf());
+
+    let conn = Connection::open(path).map_err(|_| corrupt())?;
+
+    let integrity: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|_| corrupt())?;
+    if integrity != "ok" {
+        return Err(corrupt());
+    }
+
+    // Enable WAL mode for better concurrency
+    conn.execute_batch("PRAGMA journal_mode=WAL;")?;
+
+    init_schema(&conn)?;
+
+    Ok(conn)
+}
+
+/// Create the tables if missing and migrate older schemas
+fn init_schema(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS secrets (
+            name TEXT PRIMARY KEY,
+            encrypted_value BLOB NOT NULL,
+            value_hash TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            created_by TEXT,
+            binary INTEGER NOT NULL DEFAULT 0
+        );
+
+        CREATE TABLE IF NOT EXISTS metadata (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+        ",
+    )?;
+
+    // Check/set schema version
+    init_schema_version(conn)?;
+
+    conn.execute_batch("CREATE INDEX IF NOT EXISTS secrets_value_hash ON secrets (value_hash);")?;
+    Ok(())
+}
+
+/// Map a `name, created_at, updated_at, created_by` row to a Secret
+fn secret_from_row(row: &rusqlite::Row) -> rusqlite::Result<Secret> {
+    let name: String = row.get(0)?;
+    let created_at: String = row.get(1)?;
+    let updated_at: String = row.get(2)?;
+    let created_by: Option<String> = row.get(3)?;
+
+    Ok(Secret {
+        name,
+        created_at: DateTime::parse_from_rfc3339(&created_at)
+            .unwrap_or_default()
+            .with_timezone(&Utc),
+        updated_at: DateTime::parse_from_rfc3339(&updated_at)
+            .unwrap_or_default()
+            .with_timezone(&Utc),
+        created_by,
+    })
+}
+
+/// Who is creating secrets: SECRET_AGENT_IDENTITY if set, otherwise
+/// `user@host`. Only the login name and hostname are recorded.
+pub fn current_identity() -> String {
+    if let Ok(identity) = std::env::var("SECRET_AGENT_IDENTITY") {
+        if !identity.is_empty() {
+            return identity;
+        }
+    }
+
+    let user = ["USER", "USERNAME", "LOGNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+        .unwrap_or_else(|| "unknown".to_string());
+    format!(
+        "{}@{}",
+        user,
+        hostname().unwrap_or_else(|| "unknown".to_string())
+    )
+}
+
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer is valid for its full length, which is what we pass
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned()).filter(|h| !h.is_empty())
+}
+
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok().filter(|h| !h.is_empty())
+}
+
+/// Use whichever of the disagreeing `candidates` decrypts the vault, warning
+/// about the mismatch either way. When that can't be told (an empty vault, or
+/// neither key works) the chain's usual pick, the first, is used.
+fn pick_divergent_key(
+    conn: &Connection,
+    mut candidates: Vec<KeyCandidate>,
+) -> Result<SecretString> {
+    let keys = candidates
+        .iter()
+        .map(|candidate| derive_key(conn, &candidate.key))
+        .collect::<Result<Vec<_>>>()?;
+    let matched = matching_candidate(conn, &keys)?;
+    eprintln!("{}", divergence_warning(&candidates, &keys, matched));
+    Ok(candidates.swap_remove(matched.unwrap_or(0)).key)
+}
+
+/// Hashed with a derived vault key to identify it in messages
+const KEY_ID_LABEL: &[u8] = b"secret-agent key id";
+
+/// A short identifier for a derived vault key. It comes from the salted,
+/// scrypt-derived key rather than the master key itself, so printing it tells
+/// an attacker no more about a passphrase than the vault file already does.
+fn key_id(key: &VaultKey) -> String {
+    format!("key id {}", &key.value_hash(KEY_ID_LABEL)[..16])
+}
+
+/// The index of the key that decrypts a stored secret
+fn matching_candidate(conn: &Connection, keys: &[VaultKey]) -> Result<Option<usize>> {
+    let sample: Option<Vec<u8>> = conn
+        .query_row("SELECT encrypted_value FROM secrets LIMIT 1", [], |row| {
+            row.get(0)
+        })
+        .optional()?;
+    let Some(sample) = sample else {
+        return Ok(None);
+    };
+
+    Ok(keys.iter().position(|key| key.decrypt(&sample).is_ok()))
+}
+
+/// `keys` are the candidates' derived vault keys, in the same order
+fn divergence_warning(
+    candidates: &[KeyCandidate],
+    keys: &[VaultKey],
+    matched: Option<usize>,
+) -> String {
+    let sources: Vec<String> = candidates
+        .iter()
+        .zip(keys)
+        .map(|(c, key)| format!("the {} ({})", c.source, key_id(key)))
+        .collect();
+    let mut warning = format!(
+        "warning: {} hold different master keys",
+        sources.join(" and ")
+    );
+    match matched {
+        Some(i) => warning.push_str(&format!(
+            "\nwarning: the vault was encrypted with the {} key, so it is used; {} to always pick it",
+            candidates[i].source, candidates[i].hint
+        )),
+        None => warning.push_str(&format!(
+            "\nwarning: couldn't tell which key the vault uses; using the {} key",
+            candidates[0].source
+        )),
+    }
+    warning
+}
+
+/// Whether the vault already has a key salt or secrets, i.e. a master key
+/// has been used with it
+fn is_initialized(conn: &Connection) -> Result<bool> {
+    Ok(conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM metadata WHERE key = 'key_salt')
+             OR EXISTS(SELECT 1 FROM secrets)",
+        [],
+        |row| row.get(0),
+    )?)
+}
+
+/// `value_format` of a vault whose values are encrypted to the vault key.
+/// Vaults without one use the per-value passphrase format.
+const VAULT_KEY_FORMAT: i64 = 2;
+
+/// scrypt cost of vaults salted before `key_log_n` was recorded
+const UNRECORDED_KEY_LOG_N: u8 = 15;
+
+/// scrypt cost for a new vault's key. SECRET_AGENT_KEY_LOG_N overrides it,
+/// for tests and slow machines; unit tests always use a cheap one.
+fn new_vault_log_n() -> Result<u8> {
+    match std::env::var("SECRET_AGENT_KEY_LOG_N") {
+        Ok(value) => value.parse().map_err(|_| {
+            Error::Config(format!(
+                "SECRET_AGENT_KEY_LOG_N must be a number like {}, got '{}'",
+                crypto::VAULT_KEY_LOG_N,
+                value
+            ))
+        }),
+        Err(_) if cfg!(test) => Ok(10),
+        Err(_) => Ok(crypto::VAULT_KEY_LOG_N),
+    }
+}
+
+fn metadata_value(conn: &Connection, key: &str) -> Result<Option<String>> {
+    Ok(conn
+        .query_row(
+            "SELECT value FROM metadata WHERE key = ?1",
+            params![key],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+/// Derive the vault key, creating the vault's random key salt on first use.
+/// A vault salted while still empty is new, so it gets the vault key format;
+/// one that already holds secrets keeps the passphrase format older versions
+/// read.
+fn derive_key(conn: &Connection, master_key: &SecretString) -> Result<VaultKey> {
+    let salt = match metadata_value(conn, "key_salt")? {
+        Some(encoded) => base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|_| Error::Encryption("invalid key salt in vault metadata".to_string()))?,
+        None => {
+            let salt: [u8; 16] = rand::random();
+            let has_secrets: bool =
+                conn.query_row("SELECT EXISTS(SELECT 1 FROM secrets)", [], |row| row.get(0))?;
+            let tx = conn.unchecked_transaction()?;
+            tx.execute(
+                "INSERT INTO metadata (key, value) VALUES ('key_salt', ?1)",
+                params![base64::engine::general_purpose::STANDARD.encode(salt)],
+            )?;
+            tx.execute(
+                "INSERT INTO metadata (key, value) VALUES ('key_log_n', ?1)",
+                params![new_vault_log_n()?.to_string()],
+            )?;
+            if !has_secrets {
+                tx.execute(
+                    "INSERT INTO metadata (key, value) VALUES ('value_format', ?1)",
+                    params![VAULT_KEY_FORMAT.to_string()],
+                )?;
+            }
+            tx.commit()?;
+            salt.to_vec()
+        }
+    };
+
+    let log_n = match metadata_value(conn, "key_log_n")? {
+        Some(value) => value
+            .parse()
+            .map_err(|_| Error::Encryption("invalid key cost in vault metadata".to_string()))?,
+        None => UNRECORDED_KEY_LOG_N,
+    };
+    let format = match metadata_value(conn, "value_format")? {
+        None => ValueFormat::Passphrase,
+        Some(value) if value == VAULT_KEY_FORMAT.to_string() => ValueFormat::VaultKey,
+        Some(value) => {
+            return Err(Error::Encryption(format!(
+                "the vault uses value format {}, which this version of secret-agent can't write; upgrade it",
+                value
+            )))
+        }
+    };
+
+    VaultKey::derive(master_key.expose_secret(), &salt, log_n, format)
+}
+
 fn init_schema_version(conn: &Connection) -> Result<()> {
     let version: Option<i64> = conn
         .query_row(
@@ -218,7 +976,20 @@ fn init_schema_version(conn: &Connection) -> Result<()> {
             )?;
         }
         Some(v) if v < SCHEMA_VERSION => {
-            // Future: run migrations here
+            // v2: keyed value hashes, filled in by `Vault::backfill_value_hashes`
+            if v < 2 {
+                conn.execute_batch("ALTER TABLE secrets ADD COLUMN value_hash TEXT;")?;
+            }
+            // v3: who created each secret (unknown for older rows)
+            if v < 3 {
+                conn.execute_batch("ALTER TABLE secrets ADD COLUMN created_by TEXT;")?;
+            }
+            // v4: values stored as raw bytes rather than text
+            if v < 4 {
+                conn.execute_batch(
+                    "ALTER TABLE secrets ADD COLUMN binary INTEGER NOT NULL DEFAULT 0;",
+                )?;
+            }
             conn.execute(
                 "UPDATE metadata SET value = ?1 WHERE key = 'schema_version'",
                 params![SCHEMA_VERSION.to_string()],
@@ -230,7 +1001,11 @@ fn init_schema_version(conn: &Connection) -> Result<()> {
     Ok(())
 }
 
-fn get_vault_path() -> Result<PathBuf> {
+pub fn get_vault_path() -> Result<PathBuf> {
+    if let Some(path) = VAULT_PATH.get() {
+        return Ok(path.clone());
+    }
+
     // Allow override via environment variable (useful for testing)
     if let Ok(path) = std::env::var("SECRET_AGENT_VAULT_PATH") {
         return Ok(PathBuf::from(path));
@@ -246,11 +1021,23 @@ fn get_vault_path() -> Result<PathBuf> {
     Ok(home.join(".secret-agent").join("vault.db"))
 }
 
+/// Prefix a bucket-less secret name with a default bucket (`--bucket` or
+/// SECRET_AGENT_DEFAULT_BUCKET); names that already have a bucket are kept
+pub fn with_default_bucket(secret_name: String, bucket: Option<&str>) -> String {
+    match bucket {
+        Some(bucket) if !secret_name.contains('/') => {
+            format!("{}/{}", bucket.trim_end_matches('/'), secret_name)
+        }
+        _ => secret_name,
+    }
+}
+
 /// Parse a secret name into (bucket, name) parts
 /// "prod/API_KEY" -> (Some("prod"), "API_KEY")
 /// "API_KEY" -> (None, "API_KEY")
 pub fn parse_bucket_name(full_name: &str) -> (Option<&str>, &str) {
-    if let Some(pos) = full_name.find('/') {
+    // Buckets may nest (company/prod/KEY); the name is always the last segment
+    if let Some(pos) = full_name.rfind('/') {
         let bucket = &full_name[..pos];
         let name = &full_name[pos + 1..];
         (Some(bucket), name)
@@ -264,7 +1051,9 @@ pub fn secret_name_only(full_name: &str) -> &str {
     parse_bucket_name(full_name).1
 }
 
-fn validate_name(name: &str) -> Result<()> {
+/// Validate a possibly bucketed name (`NAME`, `bucket/NAME`, or nested
+/// `company/prod/NAME`), checking each bucket segment and the name independently
+pub fn validate_full_name(name: &str) -> Result<()> {
     if name.is_empty() {
         return Err(Error::InvalidSecretName("name cannot be empty".to_string()));
     }
@@ -272,9 +1061,11 @@ fn validate_name(name: &str) -> Result<()> {
     // Check for bucket syntax: bucket/name
     let (bucket, secret_name) = parse_bucket_name(name);
 
-    // Validate bucket if present
+    // Validate every bucket segment if present
     if let Some(b) = bucket {
-        validate_name_part(b, "bucket")?;
+        for segment in b.split('/') {
+            validate_name_part(segment, "bucket")?;
+        }
     }
 
     // Validate the secret name part
@@ -322,13 +1113,296 @@ mod tests {
 
     fn setup_test_vault() -> (Vault, TempDir) {
         let temp_dir = TempDir::new().unwrap();
-        let vault_path = temp_dir.path().join("vault.db");
-        std::env::set_var("SECRET_AGENT_VAULT_PATH", vault_path.to_str().unwrap());
-        std::env::set_var("SECRET_AGENT_PASSPHRASE", "test-passphrase");
-        let vault = Vault::open().unwrap();
+        let vault = open_test_vault(&temp_dir.path().join("vault.db"), "test-passphrase");
         (vault, temp_dir)
     }
 
+    fn open_test_vault(path: &Path, passphrase: &str) -> Vault {
+        Vault::open_at(path, KeySource::Passphrase(passphrase.into())).unwrap()
+    }
+
+    fn candidate(source: &str, hint: &str, key: &str) -> KeyCandidate {
+        KeyCandidate {
+            source: source.to_string(),
+            hint: hint.to_string(),
+            key: key.into(),
+        }
+    }
+
+    #[test]
+    fn test_divergent_keys_prefer_the_vaults() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("vault.db");
+        let candidates = vec![
+            candidate("system keychain", "move master.key aside", "keychain-key"),
+            candidate(
+                "key file master.key",
+                "set SECRET_AGENT_USE_FILE=1",
+                "file-key",
+            ),
+        ];
+
+        // An empty vault can't tell them apart, so the keychain wins as usual
+        drop(open_test_vault(&path, "file-key"));
+        let conn = open_connection(&path).unwrap();
+        let keys: Vec<VaultKey> = candidates
+            .iter()
+            .map(|c| derive_key(&conn, &c.key).unwrap())
+            .collect();
+        assert_eq!(matching_candidate(&conn, &keys).unwrap(), None);
+        let warning = divergence_warning(&candidates, &keys, None);
+        assert!(warning.contains("different master keys"));
+        assert!(warning.contains("using the system keychain key"));
+
+        open_test_vault(&path, "file-key")
+            .create("KEY", "value")
+            .unwrap();
+        assert_eq!(matching_candidate(&conn, &keys).unwrap(), Some(1));
+        let warning = divergence_warning(&candidates, &keys, Some(1));
+        assert!(warning.contains(&key_id(&keys[0])));
+        assert!(warning.contains(&key_id(&keys[1])));
+        // Nothing computed from the bare master key is printed
+        assert!(!warning.contains(&keychain::fingerprint("keychain-key")));
+        assert!(!warning.contains(&keychain::fingerprint("file-key")));
+        assert!(warning.contains("encrypted with the key file master.key key"));
+        assert!(warning.contains("set SECRET_AGENT_USE_FILE=1"));
+        assert!(!warning.contains("keychain-key") && !warning.contains("file-key"));
+
+        let key = pick_divergent_key(&conn, candidates).unwrap();
+        assert_eq!(key.expose_secret(), "file-key");
+    }
+
+    #[test]
+    fn test_decryption_failure_reports_key_mismatch() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("vault.db");
+        open_test_vault(&path, "keychain-key")
+            .create("KEY", "value")
+            .unwrap();
+        let encrypted: Vec<u8> = open_connection(&path)
+            .unwrap()
+            .query_row("SELECT encrypted_value FROM secrets", [], |row| row.get(0))
+            .unwrap();
+
+        // Opened with the file key while the keychain holds the vault's key
+        let vault = open_test_vault(&path, "file-key");
+        let e = vault.get("KEY").unwrap_err();
+        assert!(matches!(e, Error::Decryption(_)));
+        let candidates = vec![
+            candidate("system keychain", "move master.key aside", "keychain-key"),
+            candidate(
+                "key file master.key",
+                "set SECRET_AGENT_USE_FILE=1",
+                "file-key",
+            ),
+        ];
+        let e = vault.mismatch_with(e, &encrypted, candidates);
+        let Error::KeyMismatch(message) = e else {
+            panic!("expected a key mismatch, got {:?}", e);
+        };
+        assert!(message.contains("master key in the system keychain"));
+        let conn = open_connection(&path).unwrap();
+        let keychain_key = derive_key(&conn, &SecretString::from("keychain-key")).unwrap();
+        assert!(message.contains(&key_id(&keychain_key)));
+        assert!(message.contains(&key_id(&vault.key)));
+        assert!(!message.contains(&keychain::fingerprint("keychain-key")));
+        assert!(!message.contains(&keychain::fingerprint("file-key")));
+        assert!(message.contains("move master.key aside"));
+
+        // A key that matches nothing leaves the decryption error alone
+        let e = vault.get("KEY").unwrap_err();
+        let candidates = vec![candidate("system keychain", "", "unrelated-key")];
+        assert!(matches!(
+            vault.mismatch_with(e, &encrypted, candidates),
+            Error::Decryption(_)
+        ));
+    }
+
+    #[test]
+    fn test_open_at_keeps_vaults_apart() {
+        let dir = TempDir::new().unwrap();
+        let first = open_test_vault(&dir.path().join("first.db"), "first-passphrase");
+        let second = open_test_vault(&dir.path().join("second.db"), "second-passphrase");
+
+        first.create("SHARED_NAME", "from-first").unwrap();
+        second.create("SHARED_NAME", "from-second").unwrap();
+        first.create("ONLY_FIRST", "value").unwrap();
+
+        assert_eq!(
+            first.get("SHARED_NAME").unwrap().expose_secret(),
+            "from-first"
+        );
+        assert_eq!(
+            second.get("SHARED_NAME").unwrap().expose_secret(),
+            "from-second"
+        );
+        assert!(!second.exists("ONLY_FIRST").unwrap());
+
+        // Each database only opens with its own key
+        drop(second);
+        let reopened = open_test_vault(&dir.path().join("second.db"), "first-passphrase");
+        assert!(reopened.get("SHARED_NAME").is_err());
+    }
+
+    #[test]
+    fn test_created_by_records_identity() {
+        let (vault, _dir) = setup_test_vault();
+        vault.create("PROVENANCE", "value").unwrap();
+
+        let secret = vault.get_metadata("PROVENANCE").unwrap();
+        let created_by = secret.created_by.unwrap();
+        assert!(!created_by.is_empty());
+
+        // Updating keeps the original creator
+        vault.update("PROVENANCE", "changed").unwrap();
+        let again = vault.get_metadata("PROVENANCE").unwrap();
+        assert_eq!(again.created_by.as_deref(), Some(created_by.as_str()));
+    }
+
+    #[test]
+    fn test_find_by_value_hash() {
+        let (vault, _dir) = setup_test_vault();
+        vault.create("FIRST", "shared-value").unwrap();
+        vault.create("SECOND", "shared-value").unwrap();
+        vault.create("THIRD", "other-value").unwrap();
+
+        let hash = vault.value_hash("shared-value");
+        assert_eq!(
+            vault.find_by_value_hash(&hash).unwrap(),
+            ["FIRST", "SECOND"]
+        );
+
+        vault.update("SECOND", "changed").unwrap();
+        assert_eq!(vault.find_by_value_hash(&hash).unwrap(), ["FIRST"]);
+    }
+
+    /// A schema v1 vault (no value hashes) holding `rows` of name and ciphertext
+    fn create_v1_vault(path: &Path, rows: &[(&str, Vec<u8>)]) {
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch(
+            "
+            CREATE TABLE secrets (
+                name TEXT PRIMARY KEY,
+                encrypted_value BLOB NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            );
+            CREATE TABLE metadata (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+            INSERT INTO metadata (key, value) VALUES ('schema_version', '1');
+            ",
+        )
+        .unwrap();
+        let now = Utc::now().to_rfc3339();
+        for (name, encrypted) in rows {
+            conn.execute(
+                "INSERT INTO secrets (name, encrypted_value, created_at, updated_at) VALUES (?1, ?2, ?3, ?3)",
+                params![name, encrypted, now],
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_open_migrates_v1_schema() {
+        let temp_dir = TempDir::new().unwrap();
+        let vault_path = temp_dir.path().join("vault.db");
+        let legacy = crate::crypto::encrypt(b"old-value", "test-passphrase").unwrap();
+        create_v1_vault(&vault_path, &[("OLD", legacy)]);
+
+        let vault = open_test_vault(&vault_path, "test-passphrase");
+
+        let hash = vault.value_hash("old-value");
+        assert_eq!(vault.find_by_value_hash(&hash).unwrap(), ["OLD"]);
+        let version: String = vault
+            .conn
+            .query_row(
+                "SELECT value FROM metadata WHERE key = 'schema_version'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION.to_string());
+
+        // New values stay in the format older versions can read
+        vault.create("NEW", "new-value").unwrap();
+        let encrypted: Vec<u8> = vault
+            .conn
+            .query_row(
+                "SELECT encrypted_value FROM secrets WHERE name = 'NEW'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(
+            crate::crypto::decrypt(&encrypted, "test-passphrase").unwrap(),
+            b"new-value"
+        );
+    }
+
+    #[test]
+    fn test_unreadable_v1_rows_dont_block_open() {
+        let temp_dir = TempDir::new().unwrap();
+        let vault_path = temp_dir.path().join("vault.db");
+        let good = crate::crypto::encrypt(b"good-value", "test-passphrase").unwrap();
+        create_v1_vault(
+            &vault_path,
+            &[("GOOD", good), ("CORRUPT", b"not age at all".to_vec())],
+        );
+
+        // Metadata-only work needs no decryption, even with the wrong key
+        let wrong = open_test_vault(&vault_path, "wrong-passphrase");
+        assert_eq!(wrong.list().unwrap().len(), 2);
+        wrong.rename("CORRUPT", "BROKEN").unwrap();
+        drop(wrong);
+
+        // Hashes are filled in on lookup, skipping what doesn't decrypt
+        let vault = open_test_vault(&vault_path, "test-passphrase");
+        let hash = vault.value_hash("good-value");
+        assert_eq!(vault.find_by_value_hash(&hash).unwrap(), ["GOOD"]);
+    }
+
+    #[test]
+    fn test_new_vault_derives_its_key_once() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("vault.db");
+        let before = crate::crypto::DERIVATIONS.with(|n| n.get());
+
+        let vault = open_test_vault(&path, "test-passphrase");
+        for i in 0..10 {
+            vault.create(&format!("KEY_{}", i), "value").unwrap();
+        }
+        for i in 0..10 {
+            vault.get(&format!("KEY_{}", i)).unwrap();
+        }
+        assert_eq!(vault.get_all().unwrap().len(), 10);
+        assert_eq!(crate::crypto::DERIVATIONS.with(|n| n.get()) - before, 1);
+
+        let format = metadata_value(&vault.conn, "value_format").unwrap();
+        assert_eq!(format, Some(VAULT_KEY_FORMAT.to_string()));
+        assert!(metadata_value(&vault.conn, "key_log_n").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_unknown_value_format_is_refused() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("vault.db");
+        let vault = open_test_vault(&path, "test-passphrase");
+        vault
+            .conn
+            .execute(
+                "UPDATE metadata SET value = '99' WHERE key = 'value_format'",
+                [],
+            )
+            .unwrap();
+        drop(vault);
+
+        let result = Vault::open_at(&path, KeySource::Passphrase("test-passphrase".into()));
+        let Err(e) = result else {
+            panic!("opened a vault in an unknown format");
+        };
+        assert!(e.to_string().contains("value format 99"));
+    }
+
     #[test]
     fn test_create_and_get() {
         let (vault, _temp) = setup_test_vault();
@@ -336,7 +1410,17 @@ mod tests {
         vault.create("TEST_SECRET", "my-value").unwrap();
         let value = vault.get("TEST_SECRET").unwrap();
 
-        assert_eq!(value, "my-value");
+        assert_eq!(value.expose_secret(), "my-value");
+    }
+
+    #[test]
+    fn test_get_returns_redacted_secret() {
+        let (vault, _temp) = setup_test_vault();
+
+        vault.create("TEST_SECRET", "my-value").unwrap();
+        let value: SecretString = vault.get("TEST_SECRET").unwrap();
+
+        assert!(!format!("{:?}", value).contains("my-value"));
     }
 
     #[test]
@@ -384,37 +1468,289 @@ mod tests {
     }
 
     #[test]
-    fn test_validate_name() {
-        assert!(validate_name("VALID_NAME").is_ok());
-        assert!(validate_name("valid-name").is_ok());
-        assert!(validate_name("_private").is_ok());
-        assert!(validate_name("").is_err());
-        assert!(validate_name("123invalid").is_err());
-        assert!(validate_name("has spaces").is_err());
+    fn test_delete_bucket() {
+        let (vault, _temp) = setup_test_vault();
+        for name in [
+            "staging/A",
+            "staging/B",
+            "staging/eu/C",
+            "prod/A",
+            "stagingX/A",
+        ] {
+            vault.create(name, "value").unwrap();
+        }
+
+        assert_eq!(vault.delete_bucket("staging").unwrap(), 3);
+
+        let left: Vec<String> = vault.list().unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(left, ["prod/A", "stagingX/A"]);
+        assert_eq!(vault.delete_bucket("staging/").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_bucket_summary() {
+        let (vault, _temp) = setup_test_vault();
+        for name in ["prod/A", "prod/B", "dev/A", "company/prod/A", "LOOSE"] {
+            vault.create(name, "value").unwrap();
+        }
+
+        assert_eq!(
+            vault.bucket_summary().unwrap(),
+            [
+                (Some("company/prod".to_string()), 1),
+                (Some("dev".to_string()), 1),
+                (Some("prod".to_string()), 2),
+                (None, 1),
+            ]
+        );
     }
 
     #[test]
-    fn test_validate_name_with_bucket() {
-        assert!(validate_name("prod/API_KEY").is_ok());
-        assert!(validate_name("dev/DB_PASS").is_ok());
-        assert!(validate_name("staging/TOKEN").is_ok());
-        assert!(validate_name("/API_KEY").is_err()); // empty bucket
-        assert!(validate_name("prod/").is_err()); // empty name
-        assert!(validate_name("123bucket/KEY").is_err()); // bucket starts with number
-        assert!(validate_name("prod/123KEY").is_err()); // name starts with number
+    fn test_get_all() {
+        let (vault, _temp) = setup_test_vault();
+        vault.create("prod/B", "value-b").unwrap();
+        vault.create("A", "value-a").unwrap();
+        vault.create("C", "value-c").unwrap();
+
+        let all: Vec<(String, String)> = vault
+            .get_all()
+            .unwrap()
+            .into_iter()
+            .map(|(name, value)| (name, value.expose_secret().to_string()))
+            .collect();
+        assert_eq!(
+            all,
+            [
+                ("A".to_string(), "value-a".to_string()),
+                ("C".to_string(), "value-c".to_string()),
+                ("prod/B".to_string(), "value-b".to_string()),
+            ]
+        );
+
+        // Binary secrets have no text value and are left out
+        vault.store_binary("BLOB", &[0xff, 0x00], false).unwrap();
+        assert_eq!(vault.get_all().unwrap().len(), 3);
+        assert_eq!(vault.binary_names().unwrap(), ["BLOB"]);
+    }
+
+    #[test]
+    fn test_delete_many() {
+        let (vault, _temp) = setup_test_vault();
+        vault.create("A", "1").unwrap();
+        vault.create("B", "2").unwrap();
+
+        // A missing name rolls back the whole batch
+        let names = vec!["A".to_string(), "MISSING".to_string()];
+        assert!(matches!(
+            vault.delete_many(&names, false),
+            Err(Error::SecretNotFound(_))
+        ));
+        assert!(vault.exists("A").unwrap());
+
+        let names = vec!["A".to_string(), "MISSING".to_string(), "B".to_string()];
+        assert_eq!(
+            vault.delete_many(&names, true).unwrap(),
+            [true, false, true]
+        );
+        assert!(vault.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rename_many_is_all_or_nothing() {
+        let (vault, _temp) = setup_test_vault();
+        vault.create("OLD_A", "1").unwrap();
+        vault.create("OLD_B", "2").unwrap();
+        vault.create("NEW_B", "taken").unwrap();
+
+        let renames = vec![
+            ("OLD_A".to_string(), "NEW_A".to_string()),
+            ("OLD_B".to_string(), "NEW_B".to_string()),
+        ];
+        assert!(matches!(
+            vault.rename_many(&renames),
+            Err(Error::SecretAlreadyExists(_))
+        ));
+        assert!(vault.exists("OLD_A").unwrap());
+        assert!(!vault.exists("NEW_A").unwrap());
+
+        vault.rename("OLD_A", "NEW_A").unwrap();
+        assert_eq!(vault.get("NEW_A").unwrap().expose_secret(), "1");
+        assert!(!vault.exists("OLD_A").unwrap());
+    }
+
+    #[test]
+    fn test_binary_roundtrip() {
+        let (vault, _temp) = setup_test_vault();
+        let bytes = [0u8, 0xff, 0xfe, b'\n', 0x80];
+        vault.store_binary("BLOB", &bytes, false).unwrap();
+
+        let (stored, binary) = vault.get_bytes("BLOB").unwrap();
+        assert_eq!(stored.expose_secret(), &bytes);
+        assert!(binary);
+        assert!(vault
+            .get("BLOB")
+            .unwrap_err()
+            .to_string()
+            .contains("get --raw"));
+
+        assert!(vault.store_binary("BLOB", b"x", false).is_err());
+        // Replacing with text clears the binary flag
+        vault.update("BLOB", "text").unwrap();
+        let (stored, binary) = vault.get_bytes("BLOB").unwrap();
+        assert_eq!(stored.expose_secret(), b"text");
+        assert!(!binary);
+    }
+
+    #[test]
+    fn test_verify_readable_finds_undecryptable_secret() {
+        let (vault, _temp) = setup_test_vault();
+        let new_key = vault.key_for("a-different-passphrase").unwrap();
+        assert_eq!(vault.verify_readable(&new_key).unwrap().current, 0);
+        vault.create("GOOD", "good-value").unwrap();
+        vault.create("MOVED", "moved-value").unwrap();
+        vault.create("BROKEN", "broken-value").unwrap();
+        assert_eq!(vault.verify_readable(&new_key).unwrap().current, 3);
+
+        // A row already on the new key is reported, not treated as broken
+        vault.reencrypt("MOVED", &new_key).unwrap();
+        let readability = vault.verify_readable(&new_key).unwrap();
+        assert_eq!(readability.current, 2);
+        assert_eq!(readability.migrated, ["MOVED"]);
+
+        vault
+            .conn
+            .execute(
+                "UPDATE secrets SET encrypted_value = x'00ff' WHERE name = 'BROKEN'",
+                [],
+            )
+            .unwrap();
+        let Err(Error::Decryption(message)) = vault.verify_readable(&new_key) else {
+            panic!("expected a decryption error");
+        };
+        assert!(message.contains("'BROKEN'"));
+        assert_eq!(vault.get("GOOD").unwrap().expose_secret(), "good-value");
+    }
+
+    #[test]
+    fn test_reencrypt_only_touches_one_row() {
+        let (vault, _temp) = setup_test_vault();
+        vault.create("MOVED", "moved-value").unwrap();
+        vault.create("KEPT", "kept-value").unwrap();
+        let before = vault.get_metadata("MOVED").unwrap();
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let new_key = vault.key_for("a-different-passphrase").unwrap();
+        vault.reencrypt("MOVED", &new_key).unwrap();
+
+        // The old key can no longer read it; the new one can
+        assert!(vault.get("MOVED").is_err());
+        let encrypted: Vec<u8> = vault
+            .conn
+            .query_row(
+                "SELECT encrypted_value FROM secrets WHERE name = 'MOVED'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(new_key.decrypt(&encrypted).unwrap(), b"moved-value");
+
+        assert_eq!(vault.get("KEPT").unwrap().expose_secret(), "kept-value");
+
+        let after = vault.get_metadata("MOVED").unwrap();
+        assert_eq!(after.created_at, before.created_at);
+        assert!(after.updated_at > before.updated_at);
+
+        assert!(matches!(
+            vault.reencrypt("MISSING", &new_key),
+            Err(Error::SecretNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_get_metadata() {
+        let (vault, _temp) = setup_test_vault();
+
+        vault.create("prod/META", "value").unwrap();
+        let meta = vault.get_metadata("prod/META").unwrap();
+
+        assert_eq!(meta.name, "prod/META");
+        assert_eq!(meta.created_at, meta.updated_at);
+        assert!(matches!(
+            vault.get_metadata("MISSING"),
+            Err(Error::SecretNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_open_corrupt_vault() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("vault.db");
+        std::fs::write(
+            &path,
+            b"this is definitely not a sqlite database, just garbage",
+        )
+        .unwrap();
+
+        let result = open_connection(&path);
+
+        assert!(matches!(result, Err(Error::VaultCorrupt(p)) if p == path));
+        // The damaged file is left in place for recovery
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_validate_full_name() {
+        assert!(validate_full_name("VALID_NAME").is_ok());
+        assert!(validate_full_name("valid-name").is_ok());
+        assert!(validate_full_name("_private").is_ok());
+        assert!(validate_full_name("").is_err());
+        assert!(validate_full_name("123invalid").is_err());
+        assert!(validate_full_name("has spaces").is_err());
+    }
+
+    #[test]
+    fn test_validate_full_name_with_bucket() {
+        assert!(validate_full_name("prod/API_KEY").is_ok());
+        assert!(validate_full_name("dev/DB_PASS").is_ok());
+        assert!(validate_full_name("staging/TOKEN").is_ok());
+        assert!(validate_full_name("/API_KEY").is_err()); // empty bucket
+        assert!(validate_full_name("prod/").is_err()); // empty name
+        assert!(validate_full_name("123bucket/KEY").is_err()); // bucket starts with number
+        assert!(validate_full_name("prod/123KEY").is_err()); // name starts with number
+        assert!(validate_full_name("company/prod/service/KEY").is_ok()); // nested buckets
+        assert!(validate_full_name("a//KEY").is_err()); // empty bucket segment
+        assert!(validate_full_name("a/1b/KEY").is_err()); // segment starts with number
+    }
+
+    #[test]
+    fn test_with_default_bucket() {
+        assert_eq!(
+            with_default_bucket("API_KEY".into(), Some("prod")),
+            "prod/API_KEY"
+        );
+        assert_eq!(
+            with_default_bucket("API_KEY".into(), Some("prod/")),
+            "prod/API_KEY"
+        );
+        assert_eq!(
+            with_default_bucket("dev/API_KEY".into(), Some("prod")),
+            "dev/API_KEY"
+        );
+        assert_eq!(with_default_bucket("API_KEY".into(), None), "API_KEY");
     }
 
     #[test]
     fn test_parse_bucket_name() {
         assert_eq!(parse_bucket_name("prod/API_KEY"), (Some("prod"), "API_KEY"));
         assert_eq!(parse_bucket_name("API_KEY"), (None, "API_KEY"));
-        assert_eq!(parse_bucket_name("a/b/c"), (Some("a"), "b/c")); // only first slash
+        assert_eq!(parse_bucket_name("a/b/c"), (Some("a/b"), "c")); // name is the last segment
     }
 
     #[test]
     fn test_secret_name_only() {
         assert_eq!(secret_name_only("prod/API_KEY"), "API_KEY");
         assert_eq!(secret_name_only("API_KEY"), "API_KEY");
+        assert_eq!(secret_name_only("company/prod/service/KEY"), "KEY");
     }
 
     #[test]
@@ -424,8 +1760,14 @@ mod tests {
         vault.create("prod/API_KEY", "prod-value").unwrap();
         vault.create("dev/API_KEY", "dev-value").unwrap();
 
-        assert_eq!(vault.get("prod/API_KEY").unwrap(), "prod-value");
-        assert_eq!(vault.get("dev/API_KEY").unwrap(), "dev-value");
+        assert_eq!(
+            vault.get("prod/API_KEY").unwrap().expose_secret(),
+            "prod-value"
+        );
+        assert_eq!(
+            vault.get("dev/API_KEY").unwrap().expose_secret(),
+            "dev-value"
+        );
     }
 
     #[test]
@@ -451,4 +1793,31 @@ mod tests {
         assert_eq!(dev.len(), 1);
         assert_eq!(dev[0].name, "dev/KEY1");
     }
+
+    #[test]
+    fn test_list_by_nested_bucket() {
+        let (vault, _temp) = setup_test_vault();
+
+        vault.create("company/prod/api/KEY", "v1").unwrap();
+        vault.create("company/prod/DB_PASS", "v2").unwrap();
+        vault.create("company/dev/KEY", "v3").unwrap();
+        vault.create("company/production/KEY", "v4").unwrap();
+
+        let names = |bucket| -> Vec<String> {
+            vault
+                .list_by_bucket(Some(bucket))
+                .unwrap()
+                .into_iter()
+                .map(|s| s.name)
+                .collect()
+        };
+
+        assert_eq!(
+            names("company/prod"),
+            ["company/prod/DB_PASS", "company/prod/api/KEY"]
+        );
+        assert_eq!(names("company/prod/"), names("company/prod"));
+        assert_eq!(names("company/prod/api"), ["company/prod/api/KEY"]);
+        assert_eq!(names("company").len(), 4);
+    }
 }
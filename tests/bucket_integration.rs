@@ -1,39 +1,58 @@
 use assert_cmd::Command;
 use predicates::prelude::*;
-use serial_test::serial;
 use tempfile::TempDir;
 
-fn secret_agent() -> Command {
-    Command::cargo_bin("secret-agent").unwrap()
+/// Vaults created by the tests use a cheap key derivation
+const TEST_KEY_LOG_N: &str = "10";
+
+/// A temporary home directory and vault, handed to each command through its
+/// own environment so tests can run in parallel
+struct TestEnv {
+    dir: TempDir,
+}
+
+impl TestEnv {
+    fn cmd(&self) -> Command {
+        let mut cmd = Command::cargo_bin("secret-agent").unwrap();
+        // Settings from the shell running the tests must not leak in
+        for (name, _) in std::env::vars_os() {
+            if name.to_string_lossy().starts_with("SECRET_AGENT_") {
+                cmd.env_remove(name);
+            }
+        }
+        cmd.env("HOME", self.dir.path())
+            .env("SECRET_AGENT_VAULT_PATH", self.dir.path().join("vault.db"))
+            .env("SECRET_AGENT_USE_FILE", "1")
+            .env("SECRET_AGENT_KEY_LOG_N", TEST_KEY_LOG_N);
+        cmd
+    }
 }
 
-fn setup_test_env() -> TempDir {
-    let dir = TempDir::new().unwrap();
-    std::env::set_var("SECRET_AGENT_VAULT_PATH", dir.path().join("vault.db"));
-    std::env::set_var("SECRET_AGENT_USE_FILE", "1");
-    dir
+fn setup_test_env() -> TestEnv {
+    TestEnv {
+        dir: TempDir::new().unwrap(),
+    }
 }
 
 #[test]
-#[serial]
 fn test_create_secret_with_bucket() {
-    let _dir = setup_test_env();
+    let env = setup_test_env();
 
     // Create secrets in different buckets
-    secret_agent()
+    env.cmd()
         .args(["create", "prod/API_KEY", "--force"])
         .assert()
         .success()
         .stdout(predicate::str::contains("Created secret: prod/API_KEY"));
 
-    secret_agent()
+    env.cmd()
         .args(["create", "dev/API_KEY", "--force"])
         .assert()
         .success()
         .stdout(predicate::str::contains("Created secret: dev/API_KEY"));
 
     // Both should be listed
-    secret_agent()
+    env.cmd()
         .args(["list"])
         .assert()
         .success()
@@ -41,41 +60,37 @@ fn test_create_secret_with_bucket() {
         .stdout(predicate::str::contains("dev/API_KEY"));
 
     // Cleanup
-    secret_agent()
+    env.cmd()
         .args(["delete", "prod/API_KEY"])
         .assert()
         .success();
-    secret_agent()
-        .args(["delete", "dev/API_KEY"])
-        .assert()
-        .success();
+    env.cmd().args(["delete", "dev/API_KEY"]).assert().success();
 }
 
 #[test]
-#[serial]
 fn test_list_with_bucket_filter() {
-    let _dir = setup_test_env();
+    let env = setup_test_env();
 
     // Create secrets in different buckets
-    secret_agent()
+    env.cmd()
         .args(["create", "prod/KEY1", "--force"])
         .assert()
         .success();
-    secret_agent()
+    env.cmd()
         .args(["create", "prod/KEY2", "--force"])
         .assert()
         .success();
-    secret_agent()
+    env.cmd()
         .args(["create", "dev/KEY1", "--force"])
         .assert()
         .success();
-    secret_agent()
+    env.cmd()
         .args(["create", "GLOBAL_KEY", "--force"])
         .assert()
         .success();
 
     // List only prod bucket
-    secret_agent()
+    env.cmd()
         .args(["list", "--bucket", "prod"])
         .assert()
         .success()
@@ -85,7 +100,7 @@ fn test_list_with_bucket_filter() {
         .stdout(predicate::str::contains("GLOBAL_KEY").not());
 
     // List only dev bucket
-    secret_agent()
+    env.cmd()
         .args(["list", "--bucket", "dev"])
         .assert()
         .success()
@@ -93,81 +108,63 @@ fn test_list_with_bucket_filter() {
         .stdout(predicate::str::contains("prod/KEY1").not());
 
     // Cleanup
-    secret_agent()
-        .args(["delete", "prod/KEY1"])
-        .assert()
-        .success();
-    secret_agent()
-        .args(["delete", "prod/KEY2"])
-        .assert()
-        .success();
-    secret_agent()
-        .args(["delete", "dev/KEY1"])
-        .assert()
-        .success();
-    secret_agent()
-        .args(["delete", "GLOBAL_KEY"])
-        .assert()
-        .success();
+    env.cmd().args(["delete", "prod/KEY1"]).assert().success();
+    env.cmd().args(["delete", "prod/KEY2"]).assert().success();
+    env.cmd().args(["delete", "dev/KEY1"]).assert().success();
+    env.cmd().args(["delete", "GLOBAL_KEY"]).assert().success();
 }
 
 #[test]
-#[serial]
 fn test_exec_with_bucket_secret() {
-    let _dir = setup_test_env();
+    let env = setup_test_env();
 
     // Create secrets in different buckets
-    secret_agent()
+    env.cmd()
         .args(["create", "prod/DB_PASS", "--force"])
         .assert()
         .success();
 
     // exec should work with bucket syntax
-    secret_agent()
+    env.cmd()
         .args(["exec", "--env", "prod/DB_PASS", "printenv", "DB_PASS"])
         .assert()
         .success()
         .stdout(predicate::str::contains("[REDACTED:"));
 
     // Cleanup
-    secret_agent()
+    env.cmd()
         .args(["delete", "prod/DB_PASS"])
         .assert()
         .success();
 }
 
 #[test]
-#[serial]
 fn test_exec_with_bucket_secret_rename() {
-    let _dir = setup_test_env();
+    let env = setup_test_env();
 
     // Create secret in bucket
-    secret_agent()
+    env.cmd()
         .args(["create", "prod/SECRET", "--force"])
         .assert()
         .success();
 
     // exec with rename: bucket/secret:ENV_VAR
-    secret_agent()
+    env.cmd()
         .args(["exec", "--env", "prod/SECRET:MY_VAR", "printenv", "MY_VAR"])
         .assert()
         .success()
         .stdout(predicate::str::contains("[REDACTED:"));
 
     // Cleanup
-    secret_agent()
-        .args(["delete", "prod/SECRET"])
-        .assert()
-        .success();
+    env.cmd().args(["delete", "prod/SECRET"]).assert().success();
 }
 
 #[test]
-#[serial]
 fn test_import_with_bucket() {
-    let _dir = setup_test_env();
+    let env = setup_test_env();
 
     // Import secret into bucket
-    secret_agent()
+    env.cmd()
         .args(["import", "staging/TOKEN"])
         .write_stdin("my_token_value\n")
         .assert()
@@ -175,34 +172,33 @@ fn test_import_with_bucket() {
         .stdout(predicate::str::contains("Imported secret: staging/TOKEN"));
 
     // Should be listed
-    secret_agent()
+    env.cmd()
         .args(["list"])
         .assert()
         .success()
         .stdout(predicate::str::contains("staging/TOKEN"));
 
     // Cleanup
-    secret_agent()
+    env.cmd()
         .args(["delete", "staging/TOKEN"])
         .assert()
         .success();
 }
 
 #[test]
-#[serial]
 fn test_inject_with_bucket() {
-    let _dir = setup_test_env();
+    let env = setup_test_env();
     let temp_dir = TempDir::new().unwrap();
     let env_file = temp_dir.path().join(".env");
 
     // Create secret in bucket
-    secret_agent()
+    env.cmd()
         .args(["create", "prod/API_KEY", "--force"])
         .assert()
         .success();
 
     // Inject should work - uses secret name without bucket for env var name
-    secret_agent()
+    env.cmd()
         .args([
             "inject",
             "prod/API_KEY",
@@ -219,33 +215,32 @@ fn test_inject_with_bucket() {
     assert!(!content.contains("prod/API_KEY="));
 
     // Cleanup
-    secret_agent()
+    env.cmd()
         .args(["delete", "prod/API_KEY"])
         .assert()
         .success();
 }
 
 #[test]
-#[serial]
 fn test_same_name_different_buckets() {
-    let _dir = setup_test_env();
+    let env = setup_test_env();
 
     // Create same-named secrets in different buckets
-    secret_agent()
+    env.cmd()
         .args(["create", "prod/SUPABASE_KEY", "--force"])
         .assert()
         .success();
-    secret_agent()
+    env.cmd()
         .args(["create", "dev/SUPABASE_KEY", "--force"])
         .assert()
         .success();
-    secret_agent()
+    env.cmd()
         .args(["create", "staging/SUPABASE_KEY", "--force"])
         .assert()
         .success();
 
     // All three should exist
-    secret_agent()
+    env.cmd()
         .args(["list"])
         .assert()
         .success()
@@ -254,12 +249,12 @@ fn test_same_name_different_buckets() {
         .stdout(predicate::str::contains("staging/SUPABASE_KEY"));
 
     // Deleting one shouldn't affect others
-    secret_agent()
+    env.cmd()
         .args(["delete", "dev/SUPABASE_KEY"])
         .assert()
         .success();
 
-    secret_agent()
+    env.cmd()
         .args(["list"])
         .assert()
         .success()
@@ -268,45 +263,336 @@ fn test_same_name_different_buckets() {
         .stdout(predicate::str::contains("staging/SUPABASE_KEY"));
 
     // Cleanup
-    secret_agent()
+    env.cmd()
         .args(["delete", "prod/SUPABASE_KEY"])
         .assert()
         .success();
-    secret_agent()
+    env.cmd()
         .args(["delete", "staging/SUPABASE_KEY"])
         .assert()
         .success();
 }
 
 #[test]
-#[serial]
 fn test_backwards_compatibility_no_bucket() {
-    let _dir = setup_test_env();
+    let env = setup_test_env();
 
     // Secrets without bucket should still work
-    secret_agent()
+    env.cmd()
         .args(["create", "SIMPLE_KEY", "--force"])
         .assert()
         .success()
         .stdout(predicate::str::contains("Created secret: SIMPLE_KEY"));
 
     // Should be listed without bucket prefix
-    secret_agent()
+    env.cmd()
         .args(["list"])
         .assert()
         .success()
         .stdout(predicate::str::contains("SIMPLE_KEY"));
 
     // exec should work
-    secret_agent()
+    env.cmd()
         .args(["exec", "--env", "SIMPLE_KEY", "printenv", "SIMPLE_KEY"])
         .assert()
         .success()
         .stdout(predicate::str::contains("[REDACTED:"));
 
     // Cleanup
-    secret_agent()
-        .args(["delete", "SIMPLE_KEY"])
+    env.cmd().args(["delete", "SIMPLE_KEY"]).assert().success();
+}
+
+#[test]
+fn test_list_csv_format() {
+    let env = setup_test_env();
+
+    env.cmd()
+        .args(["create", "prod/CSV_KEY", "--force"])
+        .assert()
+        .success();
+
+    let output = env
+        .cmd()
+        .args(["list", "--format", "csv", "--bucket", "prod"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let mut reader = csv::Reader::from_reader(output.stdout.as_slice());
+    let headers = reader.headers().unwrap().clone();
+    assert_eq!(
+        headers.iter().collect::<Vec<_>>(),
+        ["name", "bucket", "created_at", "updated_at"]
+    );
+
+    let rows: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(&rows[0][0], "prod/CSV_KEY");
+    assert_eq!(&rows[0][1], "prod");
+    assert!(chrono::DateTime::parse_from_rfc3339(&rows[0][2]).is_ok());
+
+    // Cleanup
+    env.cmd()
+        .args(["delete", "prod/CSV_KEY"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_list_json_envelope() {
+    let env = setup_test_env();
+
+    env.cmd()
+        .args(["create", "prod/JSON_KEY", "--force"])
+        .assert()
+        .success();
+
+    let output = env
+        .cmd()
+        .args(["list", "--json-envelope", "--bucket", "prod"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let listing: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(listing["schema"], 1);
+    let secrets = listing["secrets"].as_array().unwrap();
+    assert_eq!(secrets.len(), 1);
+    assert_eq!(secrets[0]["name"], "prod/JSON_KEY");
+    assert_eq!(secrets[0]["bucket"], "prod");
+
+    // Plain --json is the bare array
+    let output = env
+        .cmd()
+        .args(["list", "--json", "--bucket", "prod"])
+        .output()
+        .unwrap();
+    let listing: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(listing.as_array().unwrap().len(), 1);
+
+    // Cleanup
+    env.cmd()
+        .args(["delete", "prod/JSON_KEY"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_exec_env_bucket() {
+    let env = setup_test_env();
+
+    env.cmd()
+        .args(["create", "dev/A", "--force"])
+        .assert()
+        .success();
+    env.cmd()
+        .args(["create", "dev/B", "--force"])
+        .assert()
+        .success();
+    env.cmd()
+        .args(["import", "B", "--replace"])
+        .write_stdin("explicit-b\n")
+        .assert()
+        .success();
+
+    env.cmd()
+        .args(["exec", "--env-bucket", "dev", "--", "env"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("A=[REDACTED:"))
+        .stdout(predicate::str::contains("B=[REDACTED:"));
+
+    // Explicit --env wins over the bucket and the collision is reported
+    env.cmd()
+        .args([
+            "exec",
+            "-e",
+            "B",
+            "--env-bucket",
+            "dev",
+            "--",
+            "printenv",
+            "B",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[REDACTED:B]"))
+        .stderr(predicate::str::contains("conflicts"));
+
+    // Cleanup
+    for name in ["dev/A", "dev/B", "B"] {
+        env.cmd().args(["delete", name]).assert().success();
+    }
+}
+
+#[test]
+fn test_invalid_bucket_names_rejected() {
+    let env = setup_test_env();
+
+    for name in ["a//KEY", "/KEY", "prod/"] {
+        env.cmd()
+            .args(["create", name])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("invalid secret name"));
+
+        env.cmd()
+            .args(["import", name])
+            .write_stdin("value\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("invalid secret name"));
+    }
+}
+
+#[test]
+fn test_nested_buckets() {
+    let env = setup_test_env();
+
+    env.cmd()
+        .args(["create", "company/prod/service/KEY", "--force"])
+        .assert()
+        .success();
+    env.cmd()
+        .args(["create", "company/dev/KEY", "--force"])
+        .assert()
+        .success();
+
+    env.cmd()
+        .args(["list", "--bucket", "company/prod"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("company/prod/service/KEY"))
+        .stdout(predicate::str::contains("company/dev/KEY").not());
+
+    env.cmd()
+        .args([
+            "exec",
+            "--env",
+            "company/prod/service/KEY",
+            "printenv",
+            "KEY",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[REDACTED:"));
+
+    // Cleanup
+    env.cmd()
+        .args(["delete", "company/prod/service/KEY", "company/dev/KEY"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_exec_default_bucket() {
+    let env = setup_test_env();
+
+    env.cmd()
+        .args(["import", "prod/TOKEN", "--replace"])
+        .write_stdin("prod-token\n")
+        .assert()
+        .success();
+    env.cmd()
+        .args(["import", "dev/OTHER", "--replace"])
+        .write_stdin("dev-other\n")
+        .assert()
+        .success();
+
+    // TOKEN resolves to prod/TOKEN; dev/OTHER keeps its explicit bucket
+    env.cmd()
+        .args([
+            "exec",
+            "--bucket",
+            "prod",
+            "-e",
+            "TOKEN",
+            "-e",
+            "dev/OTHER",
+            "--",
+            "sh",
+            "-c",
+            "echo \"$TOKEN $OTHER\"",
+        ])
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::is_match(r"^\[REDACTED:(prod/)?TOKEN\] \[REDACTED:(dev/)?OTHER\]\n$")
+                .unwrap(),
+        );
+
+    // Cleanup
+    env.cmd()
+        .args(["delete", "prod/TOKEN", "dev/OTHER"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_delete_bucket() {
+    let env = setup_test_env();
+
+    for name in ["staging/A", "staging/B", "staging/C", "prod/A"] {
+        env.cmd()
+            .args(["import", name, "--replace"])
+            .write_stdin("value\n")
+            .assert()
+            .success();
+    }
+
+    // Without a terminal to confirm on, --yes is required
+    env.cmd()
+        .args(["delete", "--bucket", "staging"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--yes"));
+
+    env.cmd()
+        .args(["delete", "--bucket", "staging", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Deleted 3 secrets in bucket: staging",
+        ));
+
+    env.cmd()
+        .args(["list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("prod/A"))
+        .stdout(predicate::str::contains("staging/").not());
+
+    // A name and --bucket can't be combined
+    env.cmd()
+        .args(["delete", "prod/A", "--bucket", "prod"])
+        .assert()
+        .failure();
+
+    env.cmd().args(["delete", "prod/A"]).assert().success();
+}
+
+#[test]
+fn test_buckets_json_lists_counts() {
+    let env = setup_test_env();
+
+    for name in ["prod/A", "prod/B", "dev/A", "LOOSE"] {
+        env.cmd()
+            .args(["import", name, "--replace"])
+            .write_stdin("value\n")
+            .assert()
+            .success();
+    }
+
+    env.cmd()
+        .args(["buckets", "--json"])
+        .assert()
+        .success()
+        .stdout(
+            "[{\"bucket\":\"dev\",\"count\":1},{\"bucket\":\"prod\",\"count\":2},{\"bucket\":\"(none)\",\"count\":1}]\n",
+        );
+
+    env.cmd()
+        .args(["delete", "prod/A", "prod/B", "dev/A", "LOOSE"])
         .assert()
         .success();
 }
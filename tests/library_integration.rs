@@ -0,0 +1,64 @@
+use secrecy::ExposeSecret;
+use secret_agent::{secret_gen, Charset, Error, KeySource, Vault};
+use std::collections::HashMap;
+use tempfile::TempDir;
+
+fn open_vault(dir: &TempDir) -> Vault {
+    let key = KeySource::Passphrase("library-passphrase".into());
+    Vault::open_at(&dir.path().join("vault.db"), key).unwrap()
+}
+
+#[test]
+fn test_create_and_get_through_library() {
+    let dir = TempDir::new().unwrap();
+
+    let vault = open_vault(&dir);
+    let value = secret_gen::generate(24, Charset::Hex);
+    vault.create("lib/API_KEY", &value).unwrap();
+
+    assert_eq!(vault.get("lib/API_KEY").unwrap().expose_secret(), value);
+    let names: Vec<String> = vault.list().unwrap().into_iter().map(|s| s.name).collect();
+    assert_eq!(names, ["lib/API_KEY"]);
+    assert!(matches!(
+        vault.create("lib/API_KEY", "again"),
+        Err(Error::SecretAlreadyExists(_))
+    ));
+
+    let secrets = HashMap::from([("API_KEY".to_string(), value.clone())]);
+    assert_eq!(
+        secret_agent::sanitize(&format!("token={}", value), &secrets),
+        "token=[REDACTED:API_KEY]"
+    );
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_async_vault_get_inside_runtime() {
+    use secret_agent::AsyncVault;
+
+    let dir = TempDir::new().unwrap();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        let key = KeySource::Passphrase("library-passphrase".into());
+        let vault = AsyncVault::open_at(dir.path().join("vault.db"), key)
+            .await
+            .unwrap();
+        vault.create("ASYNC_KEY", "async-value").await.unwrap();
+
+        assert_eq!(
+            vault.get("ASYNC_KEY").await.unwrap().expose_secret(),
+            "async-value"
+        );
+        let names: Vec<String> = vault
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(names, ["ASYNC_KEY"]);
+    });
+}